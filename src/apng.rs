@@ -0,0 +1,634 @@
+//! Animated PNG. `acTL` announces the animation and every frame starts with an `fcTL`. The data
+//! of the first frame can be the `IDAT` chunks, in which case the default image is part of the
+//! animation, the data of the others is in `fdAT` chunks. `fcTL` and `fdAT` chunks share one
+//! sequence number, which has to count up from 0 in file order.
+
+use crate::{
+    chunk::{u32_at, Chunk},
+    color::COLOR_CHUNKS,
+    decoder::{self, Image},
+    encoder::{self, Options},
+    ihdr::{ColorType, Ihdr},
+    png::Png,
+};
+use anyhow::{bail, ensure, Context};
+use std::fmt;
+
+/// Largest amount of image data in a single `fdAT` chunk
+const FRAME_DATA_SIZE: usize = 8192;
+
+/// Number of frames and plays in `acTL`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationControl {
+    pub frames: u32,
+    /// 0 plays forever
+    pub plays: u32,
+}
+
+/// What happens to the frame region before the next frame is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dispose {
+    /// left as is
+    None,
+    /// cleared to transparent black
+    Background,
+    /// restored to what it was before the frame
+    Previous,
+}
+
+/// How the frame is drawn over what is already there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blend {
+    /// replaces it, alpha included
+    Source,
+    /// is composited over it
+    Over,
+}
+
+/// Region, delay and operations of a frame in `fcTL`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameControl {
+    pub sequence: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x: u32,
+    pub y: u32,
+    /// numerator and denominator of the delay in seconds, a denominator of 0 means 100
+    pub delay: (u16, u16),
+    pub dispose: Dispose,
+    pub blend: Blend,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub control: FrameControl,
+    /// zlib stream of the frame, without sequence numbers
+    pub data: Vec<u8>,
+    /// whether the data is the `IDAT` chunks
+    pub default_image: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Animation {
+    pub control: AnimationControl,
+    pub frames: Vec<Frame>,
+}
+
+/// Whether `chunk` is an `fcTL` or `fdAT`, the chunks with a sequence number
+pub fn is_animation_chunk(chunk: &Chunk) -> bool {
+    matches!(&chunk.chunk_type().bytes(), b"fcTL" | b"fdAT")
+}
+
+impl TryFrom<&[u8]> for AnimationControl {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        ensure!(data.len() == 8, "acTL holds 8 bytes, not {}", data.len());
+        Ok(AnimationControl {
+            frames: u32_at(data, 0),
+            plays: u32_at(data, 4),
+        })
+    }
+}
+
+impl AnimationControl {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        [self.frames.to_be_bytes(), self.plays.to_be_bytes()].concat()
+    }
+}
+
+impl TryFrom<&[u8]> for FrameControl {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        ensure!(data.len() == 26, "fcTL holds 26 bytes, not {}", data.len());
+        Ok(FrameControl {
+            sequence: u32_at(data, 0),
+            width: u32_at(data, 4),
+            height: u32_at(data, 8),
+            x: u32_at(data, 12),
+            y: u32_at(data, 16),
+            delay: (
+                u16::from_be_bytes([data[20], data[21]]),
+                u16::from_be_bytes([data[22], data[23]]),
+            ),
+            dispose: match data[24] {
+                0 => Dispose::None,
+                1 => Dispose::Background,
+                2 => Dispose::Previous,
+                other => bail!("unknown dispose operation {}", other),
+            },
+            blend: match data[25] {
+                0 => Blend::Source,
+                1 => Blend::Over,
+                other => bail!("unknown blend operation {}", other),
+            },
+        })
+    }
+}
+
+impl FrameControl {
+    /// Frame covering the whole image, shown for `delay_ms` milliseconds
+    pub fn full(ihdr: &Ihdr, delay_ms: u16) -> FrameControl {
+        FrameControl {
+            sequence: 0,
+            width: ihdr.width,
+            height: ihdr.height,
+            x: 0,
+            y: 0,
+            delay: (delay_ms, 1000),
+            dispose: Dispose::None,
+            blend: Blend::Source,
+        }
+    }
+    pub fn delay_seconds(&self) -> f64 {
+        let denominator = match self.delay.1 {
+            0 => 100,
+            x => x,
+        };
+        self.delay.0 as f64 / denominator as f64
+    }
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = [self.sequence, self.width, self.height, self.x, self.y]
+            .iter()
+            .flat_map(|x| x.to_be_bytes())
+            .collect();
+        bytes.extend(self.delay.0.to_be_bytes());
+        bytes.extend(self.delay.1.to_be_bytes());
+        bytes.push(self.dispose as u8);
+        bytes.push(self.blend as u8);
+        bytes
+    }
+}
+
+pub fn is_animated(png: &Png) -> bool {
+    png.chunk_by_type("acTL").is_some()
+}
+
+impl Animation {
+    pub fn from_png(png: &Png) -> anyhow::Result<Animation> {
+        let control = png
+            .chunk_by_type("acTL")
+            .context("this png is not animated")?;
+        let control = AnimationControl::try_from(control.data()).context("invalid acTL chunk")?;
+
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut before_data = true;
+        for chunk in png.chunks() {
+            match &chunk.chunk_type().bytes() {
+                b"fcTL" => frames.push(Frame {
+                    control: FrameControl::try_from(chunk.data()).context("invalid fcTL chunk")?,
+                    data: vec![],
+                    default_image: false,
+                }),
+                b"IDAT" => {
+                    // only an fcTL right before the image data makes it a frame
+                    if before_data && frames.len() == 1 {
+                        frames[0].default_image = true;
+                    }
+                    if let Some(frame) = frames.last_mut().filter(|x| x.default_image) {
+                        frame.data.extend(chunk.data());
+                    }
+                    before_data = false;
+                }
+                b"fdAT" => {
+                    ensure!(
+                        chunk.data().len() >= 4,
+                        "fdAT is missing its sequence number"
+                    );
+                    let frame = frames.last_mut().context("fdAT before any fcTL")?;
+                    frame.data.extend(&chunk.data()[4..]);
+                }
+                _ => {}
+            }
+        }
+        Ok(Animation { control, frames })
+    }
+
+    /// The region of frame `index` as an image of its own, with the palette and color chunks of
+    /// `png`
+    pub fn frame_png(&self, png: &Png, index: usize) -> anyhow::Result<Png> {
+        let frame = self.frames.get(index).with_context(|| {
+            format!(
+                "frame {} does not exist, there are {}",
+                index,
+                self.frames.len()
+            )
+        })?;
+        let ihdr = Ihdr {
+            width: frame.control.width,
+            height: frame.control.height,
+            ..png.ihdr()?
+        };
+
+        let mut chunks = vec![Chunk::new("IHDR".try_into()?, ihdr.as_bytes())];
+        for chunk in png.chunks() {
+            let chunk_type = chunk.chunk_type().bytes();
+            if matches!(&chunk_type, b"PLTE" | b"tRNS") || COLOR_CHUNKS.contains(&&chunk_type) {
+                chunks.push(chunk.clone());
+            }
+        }
+        chunks.push(Chunk::new("IDAT".try_into()?, frame.data.clone()));
+        chunks.push(Chunk::new("IEND".try_into()?, vec![]));
+        Ok(Png::from_chunks(chunks))
+    }
+
+    /// Every frame as it is shown, drawn over the previous ones on an 8-bit RGBA canvas
+    pub fn render(&self, png: &Png) -> anyhow::Result<Vec<Image>> {
+        let ihdr = png.ihdr()?;
+        let (width, height) = (ihdr.width as usize, ihdr.height as usize);
+        let mut canvas = vec![[0u8; 4]; width * height];
+        let mut rendered = Vec::with_capacity(self.frames.len());
+
+        for (i, frame) in self.frames.iter().enumerate() {
+            let control = &frame.control;
+            ensure!(
+                control.x as u64 + control.width as u64 <= width as u64
+                    && control.y as u64 + control.height as u64 <= height as u64,
+                "frame {} does not fit in the image",
+                i
+            );
+            let pixels = decoder::decode(&self.frame_png(png, i)?)?.rgba8()?;
+            let previous = canvas.clone();
+            let region = |row: usize, column: usize| {
+                (control.y as usize + row) * width + control.x as usize + column
+            };
+
+            for row in 0..control.height as usize {
+                for column in 0..control.width as usize {
+                    let source = pixels[row * control.width as usize + column];
+                    let target = &mut canvas[region(row, column)];
+                    *target = match control.blend {
+                        Blend::Source => source,
+                        Blend::Over => over(source, *target),
+                    };
+                }
+            }
+            let samples: Vec<u16> = canvas.iter().flatten().map(|&x| x as u16).collect();
+            let canvas_ihdr = Ihdr::new(ihdr.width, ihdr.height, 8, ColorType::Rgba)?;
+            rendered.push(Image::from_samples(canvas_ihdr, &samples)?);
+
+            for row in 0..control.height as usize {
+                for column in 0..control.width as usize {
+                    let i = region(row, column);
+                    match control.dispose {
+                        Dispose::None => {}
+                        Dispose::Background => canvas[i] = [0; 4],
+                        Dispose::Previous => canvas[i] = previous[i],
+                    }
+                }
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+/// `source` composited over `target`
+fn over(source: [u8; 4], target: [u8; 4]) -> [u8; 4] {
+    let source_alpha = source[3] as u32;
+    let target_alpha = target[3] as u32 * (255 - source_alpha) / 255;
+    let alpha = source_alpha + target_alpha;
+    if alpha == 0 {
+        return [0; 4];
+    }
+    let mut color = [0, 0, 0, alpha as u8];
+    for i in 0..3 {
+        color[i] =
+            ((source[i] as u32 * source_alpha + target[i] as u32 * target_alpha) / alpha) as u8;
+    }
+    color
+}
+
+/// Ways the animation chunks of `png` break the specification
+pub fn problems(png: &Png) -> Vec<String> {
+    let mut problems = vec![];
+    let chunks = png.chunks();
+    let first_data = chunks
+        .iter()
+        .position(|x| &x.chunk_type().bytes() == b"IDAT")
+        .unwrap_or(chunks.len());
+
+    let animation = match Animation::from_png(png) {
+        Ok(animation) => animation,
+        Err(error) => {
+            if chunks.iter().any(is_animation_chunk) {
+                problems.push(format!("fcTL or fdAT chunks without animation: {}", error));
+            }
+            return problems;
+        }
+    };
+    if chunks[first_data..]
+        .iter()
+        .any(|x| &x.chunk_type().bytes() == b"acTL")
+    {
+        problems.push("acTL must come before IDAT".to_string());
+    }
+    if animation.control.frames as usize != animation.frames.len() {
+        problems.push(format!(
+            "acTL announces {} frames, there are {}",
+            animation.control.frames,
+            animation.frames.len()
+        ));
+    }
+    if chunks[..first_data]
+        .iter()
+        .any(|x| &x.chunk_type().bytes() == b"fdAT")
+    {
+        problems.push("fdAT must come after IDAT".to_string());
+    }
+
+    let sequence: Vec<u32> = chunks
+        .iter()
+        .filter(|x| is_animation_chunk(x) && x.data().len() >= 4)
+        .map(|x| u32_at(x.data(), 0))
+        .collect();
+    if let Some((expected, found)) = sequence
+        .iter()
+        .enumerate()
+        .find(|(i, x)| **x as usize != *i)
+    {
+        problems.push(format!(
+            "sequence number {} found where {} was expected",
+            found, expected
+        ));
+    }
+
+    if let Ok(ihdr) = png.ihdr() {
+        for (i, frame) in animation.frames.iter().enumerate() {
+            let control = &frame.control;
+            if control.width == 0 || control.height == 0 {
+                problems.push(format!("frame {} is empty", i));
+            } else if control.x as u64 + control.width as u64 > ihdr.width as u64
+                || control.y as u64 + control.height as u64 > ihdr.height as u64
+            {
+                problems.push(format!("frame {} does not fit in the image", i));
+            }
+            if frame.default_image
+                && (control.width, control.height, control.x, control.y)
+                    != (ihdr.width, ihdr.height, 0, 0)
+            {
+                problems.push("the default image frame must cover the whole image".to_string());
+            }
+            if frame.data.is_empty() {
+                problems.push(format!("frame {} has no data", i));
+            }
+        }
+    }
+    problems
+}
+
+/// Rewrites the sequence numbers of `png` to count up from 0 and the frame count of `acTL`
+pub fn renumber(png: &mut Png) -> anyhow::Result<()> {
+    let mut sequence = 0u32;
+    let mut frames = 0u32;
+    let mut chunks = Vec::with_capacity(png.chunks().len());
+    for chunk in png.chunks() {
+        if is_animation_chunk(chunk) && chunk.data().len() >= 4 {
+            let mut data = chunk.data().to_vec();
+            data[..4].copy_from_slice(&sequence.to_be_bytes());
+            sequence += 1;
+            if &chunk.chunk_type().bytes() == b"fcTL" {
+                frames += 1;
+            }
+            chunks.push(Chunk::new(*chunk.chunk_type(), data));
+        } else {
+            chunks.push(chunk.clone());
+        }
+    }
+    png.set_chunks(chunks);
+
+    if let Some(control) = png.chunk_by_type("acTL") {
+        let control = AnimationControl {
+            frames,
+            ..AnimationControl::try_from(control.data())?
+        };
+        png.replace_chunk(Chunk::new("acTL".try_into()?, control.as_bytes()))?;
+    }
+    Ok(())
+}
+
+/// Removes frame `index` and renumbers the rest. The default image stays in the file when it was
+/// the frame, it is just no longer part of the animation.
+pub fn remove_frame(png: &mut Png, index: usize) -> anyhow::Result<()> {
+    let frames = Animation::from_png(png)?.frames.len();
+    ensure!(
+        index < frames,
+        "frame {} does not exist, there are {}",
+        index,
+        frames
+    );
+    ensure!(frames > 1, "an animation needs at least one frame");
+
+    let mut frame = None;
+    let chunks: Vec<Chunk> = png
+        .chunks()
+        .iter()
+        .filter(|x| {
+            match &x.chunk_type().bytes() {
+                b"fcTL" => frame = Some(frame.map_or(0, |x| x + 1)),
+                b"fdAT" => {}
+                _ => return true,
+            }
+            frame != Some(index)
+        })
+        .cloned()
+        .collect();
+    png.set_chunks(chunks);
+    renumber(png)
+}
+
+/// Builds an animation out of `frames`, which all need the same size. They are converted to
+/// 8-bit RGBA unless they share their color type, bit depth, palette and transparency.
+pub fn assemble(frames: &[Image], delay_ms: u16, plays: u32) -> anyhow::Result<Png> {
+    let first = frames
+        .first()
+        .context("an animation needs at least one frame")?;
+    let size = (first.ihdr().width, first.ihdr().height);
+    for (i, frame) in frames.iter().enumerate() {
+        ensure!(
+            (frame.ihdr().width, frame.ihdr().height) == size,
+            "frame {} is {}x{}, the first one is {}x{}",
+            i,
+            frame.ihdr().width,
+            frame.ihdr().height,
+            size.0,
+            size.1
+        );
+    }
+
+    let format = |x: &Image| {
+        (
+            x.ihdr().color_type,
+            x.ihdr().bit_depth,
+            x.palette().to_vec(),
+            x.transparent().map(<[u16]>::to_vec),
+        )
+    };
+    let mut frames = frames.to_vec();
+    if frames.iter().any(|x| format(x) != format(first)) {
+        let ihdr = Ihdr::new(size.0, size.1, 8, ColorType::Rgba)?;
+        for frame in &mut frames {
+            let samples: Vec<u16> = frame.rgba8()?.iter().flatten().map(|&x| x as u16).collect();
+            *frame = Image::from_samples(ihdr, &samples)?;
+        }
+    }
+
+    let options = Options {
+        level: 9,
+        ..Options::default()
+    };
+    let mut png = encoder::encode(&frames[0], &options)?;
+    let ihdr = png.ihdr()?;
+    let control = AnimationControl {
+        frames: frames.len() as u32,
+        plays,
+    };
+    png.insert_before_data(Chunk::new("acTL".try_into()?, control.as_bytes()));
+    let frame = FrameControl::full(&ihdr, delay_ms);
+    png.insert_before_data(Chunk::new("fcTL".try_into()?, frame.as_bytes()));
+
+    let mut sequence = 1;
+    for image in &frames[1..] {
+        let frame = FrameControl {
+            sequence,
+            ..FrameControl::full(&ihdr, delay_ms)
+        };
+        png.insert_before_end(Chunk::new("fcTL".try_into()?, frame.as_bytes()));
+        sequence += 1;
+        for data in encoder::image_data(image, &options)?.chunks(FRAME_DATA_SIZE) {
+            let data = [&sequence.to_be_bytes()[..], data].concat();
+            png.insert_before_end(Chunk::new("fdAT".try_into()?, data));
+            sequence += 1;
+        }
+    }
+    Ok(png)
+}
+
+impl fmt::Display for Dispose {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Dispose::None => "none",
+            Dispose::Background => "background",
+            Dispose::Previous => "previous",
+        };
+        f.pad(name)
+    }
+}
+
+impl fmt::Display for Blend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Blend::Source => "source",
+            Blend::Over => "over",
+        };
+        f.pad(name)
+    }
+}
+
+impl fmt::Display for Animation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.control.plays {
+            0 => writeln!(f, "{} frames, played forever", self.control.frames)?,
+            plays => writeln!(f, "{} frames, played {} times", self.control.frames, plays)?,
+        }
+        for (i, frame) in self.frames.iter().enumerate() {
+            let x = &frame.control;
+            writeln!(
+                f,
+                "{:>4}  {}x{} at {},{}  {:.3} s  dispose {}, blend {}{}",
+                i,
+                x.width,
+                x.height,
+                x.x,
+                x.y,
+                x.delay_seconds(),
+                x.dispose,
+                x.blend,
+                if frame.default_image {
+                    "  (default image)"
+                } else {
+                    ""
+                }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(value: u16) -> Image {
+        let ihdr = Ihdr::new(4, 3, 8, ColorType::Grayscale).unwrap();
+        Image::from_samples(ihdr, &[value; 12]).unwrap()
+    }
+
+    #[test]
+    fn test_assemble_and_read() {
+        let png = assemble(&[frame(0), frame(100), frame(200)], 50, 2).unwrap();
+        assert!(problems(&png).is_empty(), "{:?}", problems(&png));
+
+        let animation = Animation::from_png(&png).unwrap();
+        assert_eq!(
+            animation.control,
+            AnimationControl {
+                frames: 3,
+                plays: 2
+            }
+        );
+        assert!(animation.frames[0].default_image);
+        assert_eq!(animation.frames[2].control.sequence, 3);
+        assert_eq!(animation.frames[1].control.delay_seconds(), 0.05);
+
+        let rendered = animation.render(&png).unwrap();
+        assert_eq!(rendered[1].rgba8().unwrap()[0], [100, 100, 100, 255]);
+        let single = animation.frame_png(&png, 2).unwrap();
+        assert_eq!(decoder::decode(&single).unwrap().samples(), [200; 12]);
+
+        // frames of different formats end up as RGBA
+        let ihdr = Ihdr::new(4, 3, 8, ColorType::Rgb).unwrap();
+        let color = Image::from_samples(ihdr, &[7; 36]).unwrap();
+        let png = assemble(&[frame(0), color], 50, 0).unwrap();
+        assert_eq!(png.ihdr().unwrap().color_type, ColorType::Rgba);
+    }
+
+    #[test]
+    fn test_remove_and_renumber() {
+        let mut png = assemble(&[frame(0), frame(100), frame(200)], 50, 0).unwrap();
+        remove_frame(&mut png, 1).unwrap();
+        assert!(problems(&png).is_empty(), "{:?}", problems(&png));
+        let animation = Animation::from_png(&png).unwrap();
+        assert_eq!(animation.control.frames, 2);
+        let rendered = animation.render(&png).unwrap();
+        assert_eq!(rendered[1].rgba8().unwrap()[0], [200, 200, 200, 255]);
+
+        // without its fcTL the default image is no longer a frame
+        remove_frame(&mut png, 0).unwrap();
+        let animation = Animation::from_png(&png).unwrap();
+        assert!(!animation.frames[0].default_image);
+        assert!(problems(&png).is_empty(), "{:?}", problems(&png));
+        assert!(remove_frame(&mut png, 0).is_err());
+
+        let mut chunks = png.chunks().to_vec();
+        let fctl = chunks
+            .iter()
+            .position(|x| &x.chunk_type().bytes() == b"fcTL")
+            .unwrap();
+        let mut data = chunks[fctl].data().to_vec();
+        data[3] = 9;
+        chunks[fctl] = Chunk::new("fcTL".try_into().unwrap(), data);
+        let mut png = Png::from_chunks(chunks);
+        assert_eq!(problems(&png).len(), 1);
+        renumber(&mut png).unwrap();
+        assert!(problems(&png).is_empty());
+    }
+
+    #[test]
+    fn test_blend_over() {
+        assert_eq!(over([255, 0, 0, 255], [0, 0, 255, 255]), [255, 0, 0, 255]);
+        assert_eq!(over([255, 0, 0, 0], [0, 0, 255, 255]), [0, 0, 255, 255]);
+        assert_eq!(over([0; 4], [0; 4]), [0; 4]);
+        assert_eq!(over([255, 0, 0, 128], [0, 0, 255, 255]), [128, 0, 127, 255]);
+    }
+}
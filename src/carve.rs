@@ -0,0 +1,118 @@
+//! Recovers pngs buried in arbitrary data, like disk images or memory dumps, by looking for the
+//! png signature and walking the chunks after it until `IEND`.
+
+use crate::{png::Png, scan};
+
+/// A png found in a larger file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Carved {
+    /// where the png signature starts
+    pub offset: usize,
+    pub length: usize,
+    pub chunks: usize,
+    /// chunks whose CRC does not match
+    pub damaged: usize,
+    /// whether the walk reached `IEND`
+    pub complete: bool,
+}
+
+impl Carved {
+    pub fn bytes<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.offset..self.offset + self.length]
+    }
+}
+
+/// Every png in `data`, in order. Only intact ones are kept unless `lenient`, which also keeps
+/// chunks with a wrong CRC and the pngs cut short, up to their last chunk that fits.
+pub fn carve(data: &[u8], lenient: bool) -> Vec<Carved> {
+    let signature = Png::STANDARD_HEADER;
+    let mut found = vec![];
+    let mut start = 0;
+
+    while let Some(offset) = data
+        .get(start..)
+        .and_then(|x| x.windows(signature.len()).position(|x| x == signature))
+        .map(|x| start + x)
+    {
+        // pngs nested in the chunks of another one are carved too
+        start = offset + signature.len();
+        found.extend(walk(data, offset, lenient));
+    }
+    found
+}
+
+fn walk(data: &[u8], offset: usize, lenient: bool) -> Option<Carved> {
+    let mut carved = Carved {
+        offset,
+        length: Png::STANDARD_HEADER.len(),
+        chunks: 0,
+        damaged: 0,
+        complete: false,
+    };
+
+    while let Ok(chunk) = scan::read_chunk(data, offset + carved.length) {
+        if !chunk.crc_ok {
+            if !lenient {
+                break;
+            }
+            carved.damaged += 1;
+        }
+        carved.chunks += 1;
+        carved.length += chunk.length + 12;
+        if &chunk.chunk_type.bytes() == b"IEND" {
+            carved.complete = true;
+            break;
+        }
+    }
+
+    match lenient {
+        true => (carved.chunks > 0).then_some(carved),
+        false => carved.complete.then_some(carved),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    fn testing_png() -> Vec<u8> {
+        Png::from_chunks(vec![
+            Chunk::new("IHDR".try_into().unwrap(), vec![0; 13]),
+            Chunk::new("IDAT".try_into().unwrap(), vec![1, 2, 3]),
+            Chunk::new("IEND".try_into().unwrap(), vec![]),
+        ])
+        .as_bytes()
+    }
+
+    #[test]
+    fn test_carve() {
+        let png = testing_png();
+        let data = [&b"junk"[..], &png, &[0; 9], &png, b"more junk"].concat();
+        let found = carve(&data, false);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].offset, 4);
+        assert_eq!(found[1].offset, 4 + png.len() + 9);
+        assert_eq!(found[1].bytes(&data), png);
+        assert!(found[1].complete);
+        assert_eq!(found[1].chunks, 3);
+    }
+
+    #[test]
+    fn test_damaged() {
+        let png = testing_png();
+        // flip a byte of the IDAT data, then cut the IEND chunk short
+        let mut data = [&png[..], &png[..png.len() - 3]].concat();
+        data[8 + 25 + 8] ^= 1;
+        assert!(carve(&data, false).is_empty());
+
+        let found = carve(&data, true);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].damaged, 1);
+        assert!(found[0].complete);
+        assert_eq!(found[0].length, png.len());
+        assert!(!found[1].complete);
+        assert_eq!(found[1].chunks, 2);
+        assert_eq!(found[1].length, png.len() - 12);
+    }
+}
@@ -1,9 +1,9 @@
 use crate::chunk_type::ChunkType;
-use anyhow::ensure;
+use anyhow::{ensure, Context};
 use crc::{Crc, CRC_32_ISO_HDLC};
 use std::{fmt, str::from_utf8};
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Chunk {
     length: u32,
     chunk_type: ChunkType,
@@ -13,7 +13,39 @@ pub struct Chunk {
 
 pub const HDLC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
 
+/// Parses `chunk` into `slot`, failing when an earlier chunk of the same type already filled it
+pub fn read_once<'a, T: TryFrom<&'a [u8], Error = anyhow::Error>>(
+    slot: &mut Option<T>,
+    chunk: &'a Chunk,
+) -> anyhow::Result<()> {
+    let chunk_type = chunk.chunk_type();
+    ensure!(slot.is_none(), "more than one {} chunk", chunk_type);
+    let value =
+        T::try_from(chunk.data()).with_context(|| format!("invalid {} chunk", chunk_type))?;
+    *slot = Some(value);
+    Ok(())
+}
+
+/// Big-endian `u16` starting at byte `i` of `data`
+pub fn u16_at(data: &[u8], i: usize) -> u16 {
+    u16::from_be_bytes([data[i], data[i + 1]])
+}
+
+/// Big-endian `u32` starting at byte `i` of `data`
+pub fn u32_at(data: &[u8], i: usize) -> u32 {
+    u32::from_be_bytes(data[i..i + 4].try_into().unwrap())
+}
+
+/// Keyword of a chunk like a profile or palette name, in Latin-1, whose code points match the
+/// first 256 of Unicode
+pub fn latin1(data: &[u8]) -> String {
+    data.iter().map(|&x| x as char).collect()
+}
+
 impl Chunk {
+    /// Largest amount of data a chunk holds
+    pub const MAX_DATA: usize = (1 << 31) - 1;
+
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
         let crc = HDLC.checksum(&[&chunk_type.bytes(), &data[..]].concat());
         let length = data.len() as u32;
@@ -30,10 +62,10 @@ impl Chunk {
     pub fn chunk_type(&self) -> &ChunkType {
         &self.chunk_type
     }
-    fn data(&self) -> &[u8] {
+    pub fn data(&self) -> &[u8] {
         &self.data
     }
-    fn crc(&self) -> u32 {
+    pub fn crc(&self) -> u32 {
         self.crc
     }
     pub fn data_as_string(&self) -> Result<String, anyhow::Error> {
@@ -2,7 +2,7 @@ use std::{fmt, str::FromStr};
 
 use anyhow::{ensure, Ok};
 
-#[derive(Debug, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct ChunkType(u32);
 
 impl ChunkType {
@@ -12,24 +12,35 @@ impl ChunkType {
     pub fn bytes(&self) -> [u8; 4] {
         self.0.to_be_bytes()
     }
-    fn is_valid(&self) -> bool {
+    pub fn is_valid(&self) -> bool {
         self.is_reserved_bit_valid()
     }
-    fn is_critical(&self) -> bool {
+    pub fn is_critical(&self) -> bool {
         self.0 & (0x20 << 24) == 0
     }
-    fn is_public(&self) -> bool {
+    pub fn is_public(&self) -> bool {
         self.0 & (0x20 << 16) == 0
     }
-    fn is_reserved_bit_valid(&self) -> bool {
+    pub fn is_reserved_bit_valid(&self) -> bool {
         self.0 & (0x20 << 8) == 0
     }
-    fn is_safe_to_copy(&self) -> bool {
+    pub fn is_safe_to_copy(&self) -> bool {
         self.0 & 0x20 != 0 // or self.0 & 0x20 == 0x20
     }
     pub fn inner(&self) -> u32 {
         self.0
     }
+    /// Maps `seed` to an ancillary, private and safe to copy chunk type, so other tools keep the
+    /// chunk and never try to interpret it
+    pub fn hidden(seed: &[u8; 16]) -> ChunkType {
+        let mut letters = [0; 4];
+        for (letter, bytes) in letters.iter_mut().zip(seed.chunks(4)) {
+            let value = u32::from_be_bytes(bytes.try_into().unwrap());
+            *letter = b'a' + (value % 26) as u8;
+        }
+        letters[2] = letters[2].to_ascii_uppercase();
+        ChunkType(u32::from_be_bytes(letters))
+    }
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
@@ -185,6 +196,17 @@ mod tests {
         assert_eq!(&chunk.to_string(), "RuSt");
     }
 
+    #[test]
+    pub fn test_hidden_chunk_type() {
+        let chunk_type = ChunkType::hidden(&[7; 16]);
+        assert_eq!(chunk_type.to_string(), "hhHh");
+        assert_ne!(chunk_type, ChunkType::hidden(&[8; 16]));
+        assert!(chunk_type.is_valid());
+        assert!(!chunk_type.is_critical());
+        assert!(!chunk_type.is_public());
+        assert!(chunk_type.is_safe_to_copy());
+    }
+
     #[test]
     pub fn test_chunk_type_trait_impls() {
         let chunk_type_1: ChunkType = TryFrom::try_from([82, 117, 83, 116]).unwrap();
@@ -1,4 +1,7 @@
-use crate::commands::{Decode, Encode, Print, Remove};
+use crate::commands::{
+    Analyze, Apng, Capacity, Carve, Chunks, Color, Decode, Encode, Exif, Keygen, Optimize, Palette,
+    Physical, Polyglot, Print, Remove, Scan, Sign, Trailer, Verify,
+};
 use clap::{Parser, Subcommand};
 
 /// hide messages in PNGs
@@ -25,8 +28,24 @@ impl Cli {
 enum Commands {
     Encode(Encode),
     Decode(Decode),
+    Capacity(Capacity),
     Remove(Remove),
     Print(Print),
+    Chunk(Chunks),
+    Exif(Exif),
+    Color(Color),
+    Physical(Physical),
+    Palette(Palette),
+    Apng(Apng),
+    Trailer(Trailer),
+    Polyglot(Polyglot),
+    Carve(Carve),
+    Scan(Scan),
+    Analyze(Analyze),
+    Optimize(Optimize),
+    Keygen(Keygen),
+    Sign(Sign),
+    Verify(Verify),
 }
 
 impl Commands {
@@ -34,8 +53,24 @@ impl Commands {
         match self {
             Commands::Encode(args) => args.exec(),
             Commands::Decode(args) => args.exec(),
+            Commands::Capacity(args) => args.exec(),
             Commands::Remove(args) => args.exec(),
             Commands::Print(args) => args.exec(),
+            Commands::Chunk(args) => args.exec(),
+            Commands::Exif(args) => args.exec(),
+            Commands::Color(args) => args.exec(),
+            Commands::Physical(args) => args.exec(),
+            Commands::Palette(args) => args.exec(),
+            Commands::Apng(args) => args.exec(),
+            Commands::Trailer(args) => args.exec(),
+            Commands::Polyglot(args) => args.exec(),
+            Commands::Carve(args) => args.exec(),
+            Commands::Scan(args) => args.exec(),
+            Commands::Analyze(args) => args.exec(),
+            Commands::Optimize(args) => args.exec(),
+            Commands::Keygen(args) => args.exec(),
+            Commands::Sign(args) => args.exec(),
+            Commands::Verify(args) => args.exec(),
         }
     }
 }
@@ -0,0 +1,618 @@
+use crate::{
+    chunk::{latin1, read_once, u16_at, u32_at, Chunk},
+    ihdr::{ColorType, Ihdr},
+    zlib,
+};
+use anyhow::{bail, ensure, Context};
+use std::fmt;
+
+/// Chunks describing the color space, which all have to come before `PLTE` and `IDAT`
+pub const COLOR_CHUNKS: &[&[u8; 4]] = &[
+    b"gAMA", b"cHRM", b"sRGB", b"iCCP", b"cICP", b"mDCV", b"cLLI",
+];
+
+/// Gamma of `sRGB` images as written in `gAMA`, 1/2.2
+const SRGB_GAMMA: u32 = 45455;
+/// White point and primaries of `sRGB` images as written in `cHRM`
+const SRGB_CHROMATICITIES: [u32; 8] = [31270, 32900, 64000, 33000, 30000, 60000, 15000, 6000];
+/// Size of the header every ICC profile starts with
+const ICC_HEADER_SIZE: usize = 128;
+
+/// Image gamma in `gAMA`, in units of 1/100000
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gamma(pub u32);
+
+/// White point and primaries in `cHRM`, each an (x, y) pair in units of 1/100000
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chromaticities {
+    pub white: (u32, u32),
+    pub red: (u32, u32),
+    pub green: (u32, u32),
+    pub blue: (u32, u32),
+}
+
+/// Rendering intent in `sRGB`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderingIntent {
+    Perceptual,
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric,
+}
+
+/// Embedded ICC profile in `iCCP`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IccProfile {
+    name: String,
+    profile: Vec<u8>,
+}
+
+/// Coding-independent code points in `cICP`, as defined by ITU-T H.273
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cicp {
+    pub primaries: u8,
+    pub transfer: u8,
+    pub matrix: u8,
+    pub full_range: bool,
+}
+
+/// Mastering display color volume in `mDCV`, chromaticities in units of 1/50000 and
+/// luminances in units of 1/10000 cd/m²
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MasteringDisplay {
+    pub primaries: [(u16, u16); 3],
+    pub white: (u16, u16),
+    pub max_luminance: u32,
+    pub min_luminance: u32,
+}
+
+/// Content light level in `cLLI`, in units of 1/10000 cd/m²
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLight {
+    pub max_content: u32,
+    pub max_frame_average: u32,
+}
+
+/// Every color chunk of an image
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColorInfo {
+    pub gamma: Option<Gamma>,
+    pub chromaticities: Option<Chromaticities>,
+    pub srgb: Option<RenderingIntent>,
+    pub icc: Option<IccProfile>,
+    pub cicp: Option<Cicp>,
+    pub mastering: Option<MasteringDisplay>,
+    pub content_light: Option<ContentLight>,
+}
+
+impl TryFrom<&[u8]> for Gamma {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        ensure!(data.len() == 4, "gAMA holds 4 bytes, not {}", data.len());
+        let gamma = u32_at(data, 0);
+        ensure!(gamma > 0, "gamma can't be 0");
+        Ok(Gamma(gamma))
+    }
+}
+
+impl Gamma {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+}
+
+impl TryFrom<&[u8]> for Chromaticities {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        ensure!(data.len() == 32, "cHRM holds 32 bytes, not {}", data.len());
+        let pair = |i: usize| (u32_at(data, 8 * i), u32_at(data, 8 * i + 4));
+        Ok(Chromaticities {
+            white: pair(0),
+            red: pair(1),
+            green: pair(2),
+            blue: pair(3),
+        })
+    }
+}
+
+impl Chromaticities {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        [self.white, self.red, self.green, self.blue]
+            .iter()
+            .flat_map(|(x, y)| [x.to_be_bytes(), y.to_be_bytes()])
+            .flatten()
+            .collect()
+    }
+    fn values(&self) -> [u32; 8] {
+        let [w, r, g, b] = [self.white, self.red, self.green, self.blue];
+        [w.0, w.1, r.0, r.1, g.0, g.1, b.0, b.1]
+    }
+}
+
+impl TryFrom<&[u8]> for RenderingIntent {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        ensure!(data.len() == 1, "sRGB holds 1 byte, not {}", data.len());
+        Ok(match data[0] {
+            0 => RenderingIntent::Perceptual,
+            1 => RenderingIntent::RelativeColorimetric,
+            2 => RenderingIntent::Saturation,
+            3 => RenderingIntent::AbsoluteColorimetric,
+            other => bail!("unknown rendering intent {}", other),
+        })
+    }
+}
+
+impl RenderingIntent {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+}
+
+impl IccProfile {
+    /// Checks that `profile` looks like an ICC profile and that `name` can be stored
+    pub fn new(name: &str, profile: Vec<u8>) -> anyhow::Result<IccProfile> {
+        ensure!(
+            (1..=79).contains(&name.chars().count()),
+            "profile names are 1 to 79 characters long"
+        );
+        ensure!(
+            name.chars()
+                .all(|x| matches!(x as u32, 32..=126 | 161..=255)),
+            "profile names only hold printable Latin-1 characters"
+        );
+        check_icc_header(&profile)?;
+        Ok(IccProfile {
+            name: name.to_string(),
+            profile,
+        })
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn profile(&self) -> &[u8] {
+        &self.profile
+    }
+    /// Color space signature from the profile header, like `RGB` or `GRAY`
+    pub fn color_space(&self) -> Option<&str> {
+        let space = self.profile.get(16..20)?;
+        std::str::from_utf8(space).ok().map(|x| x.trim_end())
+    }
+    /// Fails when the profile can't describe images of `color_type`, gray ones need a `GRAY`
+    /// profile and the others an `RGB` one
+    pub fn check_color_type(&self, color_type: ColorType) -> anyhow::Result<()> {
+        let expected = match color_type {
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => "GRAY",
+            _ => "RGB",
+        };
+        ensure!(
+            self.color_space() == Some(expected),
+            "{} images need an ICC profile in the {} color space, not {}",
+            color_type,
+            expected,
+            self.color_space().unwrap_or("unknown")
+        );
+        Ok(())
+    }
+    /// Profile description from the header's class and version, like `display v4.3`
+    fn kind(&self) -> Option<String> {
+        let class = match self.profile.get(12..16)? {
+            b"scnr" => "input",
+            b"mntr" => "display",
+            b"prtr" => "output",
+            b"link" => "device link",
+            b"spac" => "color space",
+            b"abst" => "abstract",
+            b"nmcl" => "named color",
+            _ => "unknown",
+        };
+        let version = self.profile.get(8..10)?;
+        Some(format!("{} v{}.{}", class, version[0], version[1] >> 4))
+    }
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = self.name.chars().map(|x| x as u8).collect();
+        bytes.extend([0, 0]);
+        bytes.extend(zlib::compress_level(&self.profile, 9));
+        bytes
+    }
+}
+
+fn check_icc_header(profile: &[u8]) -> anyhow::Result<()> {
+    ensure!(
+        profile.len() >= ICC_HEADER_SIZE,
+        "an ICC profile is at least {} bytes, not {}",
+        ICC_HEADER_SIZE,
+        profile.len()
+    );
+    ensure!(&profile[36..40] == b"acsp", "not an ICC profile");
+    let size = u32_at(profile, 0) as usize;
+    ensure!(
+        size == profile.len(),
+        "the ICC profile says it is {} bytes but is {}",
+        size,
+        profile.len()
+    );
+    Ok(())
+}
+
+impl TryFrom<&[u8]> for IccProfile {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let end = data
+            .iter()
+            .position(|&x| x == 0)
+            .context("iCCP is missing the end of the profile name")?;
+        ensure!(
+            (1..=79).contains(&end),
+            "iCCP profile name is {} bytes",
+            end
+        );
+        let method = *data
+            .get(end + 1)
+            .context("iCCP is missing its compression method")?;
+        ensure!(method == 0, "unknown iCCP compression method {}", method);
+        let profile = zlib::decompress(&data[end + 2..]).context("invalid iCCP profile")?;
+        Ok(IccProfile {
+            name: latin1(&data[..end]),
+            profile,
+        })
+    }
+}
+
+impl TryFrom<&[u8]> for Cicp {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        ensure!(data.len() == 4, "cICP holds 4 bytes, not {}", data.len());
+        ensure!(
+            data[2] == 0,
+            "cICP matrix coefficients must be 0 (RGB), not {}",
+            data[2]
+        );
+        ensure!(
+            data[3] <= 1,
+            "cICP full range flag must be 0 or 1, not {}",
+            data[3]
+        );
+        Ok(Cicp {
+            primaries: data[0],
+            transfer: data[1],
+            matrix: data[2],
+            full_range: data[3] == 1,
+        })
+    }
+}
+
+impl Cicp {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        vec![
+            self.primaries,
+            self.transfer,
+            self.matrix,
+            self.full_range as u8,
+        ]
+    }
+}
+
+impl TryFrom<&[u8]> for MasteringDisplay {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        ensure!(data.len() == 24, "mDCV holds 24 bytes, not {}", data.len());
+        let pair = |i: usize| (u16_at(data, 4 * i), u16_at(data, 4 * i + 2));
+        Ok(MasteringDisplay {
+            primaries: [pair(0), pair(1), pair(2)],
+            white: pair(3),
+            max_luminance: u32_at(data, 16),
+            min_luminance: u32_at(data, 20),
+        })
+    }
+}
+
+impl MasteringDisplay {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = self
+            .primaries
+            .iter()
+            .chain([&self.white])
+            .flat_map(|(x, y)| [x.to_be_bytes(), y.to_be_bytes()])
+            .flatten()
+            .collect();
+        bytes.extend(self.max_luminance.to_be_bytes());
+        bytes.extend(self.min_luminance.to_be_bytes());
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for ContentLight {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        ensure!(data.len() == 8, "cLLI holds 8 bytes, not {}", data.len());
+        Ok(ContentLight {
+            max_content: u32_at(data, 0),
+            max_frame_average: u32_at(data, 4),
+        })
+    }
+}
+
+impl ContentLight {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        [self.max_content, self.max_frame_average]
+            .iter()
+            .flat_map(|x| x.to_be_bytes())
+            .collect()
+    }
+}
+
+impl ColorInfo {
+    pub fn from_chunks(chunks: &[Chunk]) -> anyhow::Result<ColorInfo> {
+        let mut info = ColorInfo::default();
+        for chunk in chunks {
+            match &chunk.chunk_type().bytes() {
+                b"gAMA" => read_once(&mut info.gamma, chunk)?,
+                b"cHRM" => read_once(&mut info.chromaticities, chunk)?,
+                b"sRGB" => read_once(&mut info.srgb, chunk)?,
+                b"iCCP" => read_once(&mut info.icc, chunk)?,
+                b"cICP" => read_once(&mut info.cicp, chunk)?,
+                b"mDCV" => read_once(&mut info.mastering, chunk)?,
+                b"cLLI" => read_once(&mut info.content_light, chunk)?,
+                _ => {}
+            }
+        }
+        Ok(info)
+    }
+
+    /// Chunk whose color space decoders use, as the others are ignored when it is present
+    pub fn in_effect(&self) -> Option<&'static str> {
+        if self.cicp.is_some() {
+            Some("cICP")
+        } else if self.icc.is_some() {
+            Some("iCCP")
+        } else if self.srgb.is_some() {
+            Some("sRGB")
+        } else if self.gamma.is_some() || self.chromaticities.is_some() {
+            Some("gAMA/cHRM")
+        } else {
+            None
+        }
+    }
+
+    /// Rules of the specification the color chunks of `chunks` break
+    pub fn problems(&self, chunks: &[Chunk], ihdr: &Ihdr) -> Vec<String> {
+        let mut problems = vec![];
+        if self.icc.is_some() && self.srgb.is_some() {
+            problems.push("iCCP and sRGB must not both be present".to_string());
+        }
+        if self.srgb.is_some() {
+            if self.gamma.is_some_and(|x| x.0 != SRGB_GAMMA) {
+                problems.push(format!("gAMA should be {} along with sRGB", SRGB_GAMMA));
+            }
+            if self
+                .chromaticities
+                .is_some_and(|x| x.values() != SRGB_CHROMATICITIES)
+            {
+                problems.push("cHRM should hold the sRGB primaries along with sRGB".to_string());
+            }
+        }
+        if let Some(icc) = &self.icc {
+            if let Err(error) = check_icc_header(icc.profile()) {
+                problems.push(format!("iCCP: {}", error));
+            }
+            if let Err(error) = icc.check_color_type(ihdr.color_type) {
+                problems.push(error.to_string());
+            }
+        }
+        if self.mastering.is_some() && self.cicp.is_none() {
+            problems.push("mDCV is only meaningful along with cICP".to_string());
+        }
+
+        let first_data = chunks
+            .iter()
+            .position(|x| matches!(&x.chunk_type().bytes(), b"PLTE" | b"IDAT"))
+            .unwrap_or(chunks.len());
+        for chunk in &chunks[first_data..] {
+            let chunk_type = chunk.chunk_type().bytes();
+            if COLOR_CHUNKS.contains(&&chunk_type) {
+                problems.push(format!(
+                    "{} must come before PLTE and IDAT",
+                    chunk.chunk_type()
+                ));
+            }
+        }
+        problems
+    }
+}
+
+/// Name of an ITU-T H.273 color primaries code point
+fn primaries_name(code: u8) -> &'static str {
+    match code {
+        1 => "BT.709",
+        4 => "BT.470M",
+        5 => "BT.601 (625)",
+        6 | 7 => "BT.601 (525)",
+        9 => "BT.2020",
+        10 => "XYZ",
+        11 => "DCI-P3",
+        12 => "Display P3",
+        _ => "unknown",
+    }
+}
+
+/// Name of an ITU-T H.273 transfer characteristics code point
+fn transfer_name(code: u8) -> &'static str {
+    match code {
+        1 | 6 | 14 | 15 => "BT.709",
+        4 => "gamma 2.2",
+        5 => "gamma 2.8",
+        8 => "linear",
+        13 => "sRGB",
+        16 => "PQ",
+        18 => "HLG",
+        _ => "unknown",
+    }
+}
+
+impl fmt::Display for RenderingIntent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RenderingIntent::Perceptual => "perceptual",
+            RenderingIntent::RelativeColorimetric => "relative colorimetric",
+            RenderingIntent::Saturation => "saturation",
+            RenderingIntent::AbsoluteColorimetric => "absolute colorimetric",
+        };
+        f.pad(name)
+    }
+}
+
+impl fmt::Display for ColorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let point = |(x, y): (u32, u32)| format!("({:.5}, {:.5})", x as f64 / 1e5, y as f64 / 1e5);
+        let point16 =
+            |(x, y): (u16, u16)| format!("({:.5}, {:.5})", x as f64 / 5e4, y as f64 / 5e4);
+
+        if let Some(gamma) = self.gamma {
+            writeln!(
+                f,
+                "gAMA  gamma {:.5} (decoding exponent {:.2})",
+                gamma.0 as f64 / 1e5,
+                1e5 / gamma.0 as f64
+            )?;
+        }
+        if let Some(x) = self.chromaticities {
+            writeln!(
+                f,
+                "cHRM  white {} red {} green {} blue {}",
+                point(x.white),
+                point(x.red),
+                point(x.green),
+                point(x.blue)
+            )?;
+        }
+        if let Some(intent) = self.srgb {
+            writeln!(f, "sRGB  {} rendering intent", intent)?;
+        }
+        if let Some(icc) = &self.icc {
+            writeln!(
+                f,
+                "iCCP  \"{}\", {} bytes, {} {}",
+                icc.name,
+                icc.profile.len(),
+                icc.color_space().unwrap_or("unknown"),
+                icc.kind().unwrap_or_default()
+            )?;
+        }
+        if let Some(x) = self.cicp {
+            writeln!(
+                f,
+                "cICP  primaries {} ({}), transfer {} ({}), {} range",
+                x.primaries,
+                primaries_name(x.primaries),
+                x.transfer,
+                transfer_name(x.transfer),
+                if x.full_range { "full" } else { "narrow" }
+            )?;
+        }
+        if let Some(x) = self.mastering {
+            writeln!(
+                f,
+                "mDCV  red {} green {} blue {} white {}, {:.4} to {:.4} cd/m²",
+                point16(x.primaries[0]),
+                point16(x.primaries[1]),
+                point16(x.primaries[2]),
+                point16(x.white),
+                x.min_luminance as f64 / 1e4,
+                x.max_luminance as f64 / 1e4
+            )?;
+        }
+        if let Some(x) = self.content_light {
+            writeln!(
+                f,
+                "cLLI  max content {:.4} cd/m², max frame average {:.4} cd/m²",
+                x.max_content as f64 / 1e4,
+                x.max_frame_average as f64 / 1e4
+            )?;
+        }
+        match self.in_effect() {
+            Some(chunk) => writeln!(f, "colors are interpreted with {}", chunk),
+            None => writeln!(f, "no color information, sRGB is assumed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(chunk_type.try_into().unwrap(), data)
+    }
+
+    fn profile(space: &[u8; 4]) -> Vec<u8> {
+        let mut profile = vec![0; 200];
+        profile[..4].copy_from_slice(&200u32.to_be_bytes());
+        profile[8] = 4;
+        profile[12..16].copy_from_slice(b"mntr");
+        profile[16..20].copy_from_slice(space);
+        profile[36..40].copy_from_slice(b"acsp");
+        profile
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let gamma = Gamma(SRGB_GAMMA);
+        assert_eq!(Gamma::try_from(&gamma.as_bytes()[..]).unwrap(), gamma);
+
+        let values = SRGB_CHROMATICITIES.map(|x| x.to_be_bytes()).concat();
+        let chromaticities = Chromaticities::try_from(&values[..]).unwrap();
+        assert_eq!(chromaticities.white, (31270, 32900));
+        assert_eq!(chromaticities.as_bytes(), values);
+
+        let icc = IccProfile::new("Display P3", profile(b"RGB ")).unwrap();
+        assert_eq!(IccProfile::try_from(&icc.as_bytes()[..]).unwrap(), icc);
+        assert_eq!(icc.color_space(), Some("RGB"));
+
+        let cicp = Cicp::try_from(&[9, 16, 0, 1][..]).unwrap();
+        assert_eq!(cicp.as_bytes(), [9, 16, 0, 1]);
+        assert!(Cicp::try_from(&[9, 16, 1, 1][..]).is_err());
+
+        let data: Vec<u8> = (0..24).collect();
+        assert_eq!(
+            MasteringDisplay::try_from(&data[..]).unwrap().as_bytes(),
+            data
+        );
+        let data: Vec<u8> = (0..8).collect();
+        assert_eq!(ContentLight::try_from(&data[..]).unwrap().as_bytes(), data);
+
+        assert!(RenderingIntent::try_from(&[4][..]).is_err());
+        assert!(IccProfile::new("", profile(b"RGB ")).is_err());
+        assert!(IccProfile::new("name", vec![0; 200]).is_err());
+    }
+
+    #[test]
+    fn test_problems() {
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Grayscale).unwrap();
+        let icc = IccProfile::new("sRGB", profile(b"RGB ")).unwrap();
+        let chunks = vec![
+            chunk("IHDR", ihdr.as_bytes()),
+            chunk("sRGB", vec![0]),
+            chunk("gAMA", Gamma(100000).as_bytes()),
+            chunk("IDAT", vec![]),
+            chunk("iCCP", icc.as_bytes()),
+        ];
+        let info = ColorInfo::from_chunks(&chunks).unwrap();
+        assert_eq!(info.in_effect(), Some("iCCP"));
+        let problems = info.problems(&chunks, &ihdr);
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+
+        let chunks = vec![chunk("sRGB", vec![0]), chunk("sRGB", vec![1])];
+        assert!(ColorInfo::from_chunks(&chunks).is_err());
+
+        let info = ColorInfo::from_chunks(&[chunk("sRGB", vec![1])]).unwrap();
+        assert!(info.problems(&[], &ihdr).is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{
+    decoder,
+    png::Png,
+    steganalysis::{analyze, bit_plane, DETECTION_THRESHOLD},
+};
+use anyhow::Context;
+use clap::Args;
+use std::{fs, path::PathBuf};
+
+/// Estimates how much LSB embedded data each channel of the image at `path` carries
+#[derive(Args, Debug)]
+pub(crate) struct Analyze {
+    /// path to the png file to be analyzed
+    #[clap(value_parser)]
+    path: String,
+
+    /// directory where the bit planes of every channel will be written as png files
+    #[clap(long, value_parser)]
+    bit_planes: Option<PathBuf>,
+
+    /// bits written by `--bit-planes`, 0 being the least significant
+    #[clap(long, value_parser, default_values = &["0"])]
+    planes: Vec<u8>,
+}
+
+impl Analyze {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let file = fs::read(&self.path)?;
+
+        let png: Png = file.as_slice().try_into()?;
+        let image = decoder::decode(&png)?;
+        let reports = analyze(&image)?;
+
+        println!(
+            "{:<8} {:>12} {:>12} {:>10} {:>10}",
+            "channel", "chi-square p", "chi-sq rate", "RS rate", "SPA rate"
+        );
+        for report in &reports {
+            println!(
+                "{:<8} {:>12.4} {:>11.1}% {:>9.1}% {:>9.1}%",
+                report.channel,
+                report.chi_square,
+                report.chi_square_rate * 100.0,
+                report.rs * 100.0,
+                report.sample_pairs * 100.0
+            );
+        }
+
+        let suspicious: Vec<_> = reports
+            .iter()
+            .filter(|report| report.rate() > DETECTION_THRESHOLD)
+            .collect();
+        if suspicious.is_empty() {
+            println!("no sign of LSB embedding");
+        }
+        for report in suspicious {
+            println!(
+                "likely LSB embedding in {} (up to {:.0}% of the samples)",
+                report.channel,
+                report.rate() * 100.0
+            );
+        }
+
+        if let Some(directory) = &self.bit_planes {
+            fs::create_dir_all(directory)?;
+            let stem = PathBuf::from(&self.path)
+                .file_stem()
+                .context("empty file name")?
+                .to_string_lossy()
+                .into_owned();
+
+            for (index, name) in image.ihdr().color_type.channel_names().iter().enumerate() {
+                for &bit in &self.planes {
+                    let plane = bit_plane(&image, index, bit)?;
+                    let filename = directory.join(format!("{}-{}-bit{}.png", stem, name, bit));
+                    fs::write(&filename, plane.as_bytes())?;
+                    println!("written bit plane to file {:?}", filename);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
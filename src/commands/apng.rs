@@ -0,0 +1,152 @@
+use crate::{
+    apng::{self, Animation},
+    decoder,
+    encoder::{self, Options},
+    png::Png,
+};
+use anyhow::ensure;
+use clap::{Args, Subcommand};
+use std::{fs, path::Path, path::PathBuf};
+
+/// Inspects, splits, builds and edits animated PNGs
+#[derive(Args, Debug)]
+pub(crate) struct Apng {
+    #[clap(subcommand)]
+    command: ApngCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum ApngCommand {
+    /// Lists the frames of the animation at file `path` and checks its sequence numbers
+    Info {
+        /// path to the animated png file
+        #[clap(value_parser)]
+        path: String,
+    },
+    /// Writes every frame of the animation at file `path` as a png of its own
+    Extract {
+        /// path to the animated png file
+        #[clap(value_parser)]
+        path: String,
+
+        /// directory the frames are written to, as `<name>-<frame>.png`
+        #[clap(value_parser)]
+        directory: PathBuf,
+
+        /// writes only the region each frame updates, instead of the frame as it is shown
+        #[clap(long, action)]
+        raw: bool,
+    },
+    /// Builds an animation at `output` out of the png files at `frames`, in order
+    Assemble {
+        /// path of the animated png to write
+        #[clap(value_parser)]
+        output: String,
+
+        /// paths to the png files of the frames, all of the same size
+        #[clap(value_parser, required = true)]
+        frames: Vec<String>,
+
+        /// how long every frame is shown, in milliseconds
+        #[clap(long, value_parser, default_value_t = 100)]
+        delay: u16,
+
+        /// how many times the animation plays, 0 plays it forever
+        #[clap(long, value_parser, default_value_t = 0)]
+        plays: u32,
+    },
+    /// Removes frame `index` from the animation at file `path`
+    Drop {
+        /// path to the animated png file
+        #[clap(value_parser)]
+        path: String,
+
+        /// frame to remove, counting from 0
+        #[clap(value_parser)]
+        index: usize,
+    },
+    /// Rewrites the sequence numbers and frame count of the animation at file `path`
+    Renumber {
+        /// path to the animated png file
+        #[clap(value_parser)]
+        path: String,
+    },
+}
+
+fn read(path: &str) -> anyhow::Result<Png> {
+    let file = fs::read(path)?;
+    file.as_slice().try_into()
+}
+
+impl Apng {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        match self.command {
+            ApngCommand::Info { path } => {
+                let png = read(&path)?;
+                print!("{}", Animation::from_png(&png)?);
+                for problem in apng::problems(&png) {
+                    println!("warning: {}", problem);
+                }
+            }
+            ApngCommand::Extract {
+                path,
+                directory,
+                raw,
+            } => {
+                let png = read(&path)?;
+                let animation = Animation::from_png(&png)?;
+                let name = Path::new(&path)
+                    .file_stem()
+                    .map(|x| x.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                fs::create_dir_all(&directory)?;
+
+                let frames = match raw {
+                    true => (0..animation.frames.len())
+                        .map(|i| animation.frame_png(&png, i))
+                        .collect::<anyhow::Result<Vec<_>>>()?,
+                    false => animation
+                        .render(&png)?
+                        .iter()
+                        .map(|x| encoder::encode(x, &Options::default()))
+                        .collect::<anyhow::Result<Vec<_>>>()?,
+                };
+                for (i, frame) in frames.iter().enumerate() {
+                    fs::write(
+                        directory.join(format!("{}-{}.png", name, i)),
+                        frame.as_bytes(),
+                    )?;
+                }
+                println!("written {} frames to {:?}", frames.len(), directory);
+            }
+            ApngCommand::Assemble {
+                output,
+                frames,
+                delay,
+                plays,
+            } => {
+                let images = frames
+                    .iter()
+                    .map(|x| decoder::decode(&read(x)?))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let png = apng::assemble(&images, delay, plays)?;
+                fs::write(&output, png.as_bytes())?;
+                println!("written {} frames to {}", images.len(), output);
+            }
+            ApngCommand::Drop { path, index } => {
+                let mut png = read(&path)?;
+                apng::remove_frame(&mut png, index)?;
+                fs::write(&path, png.as_bytes())?;
+                println!("removed frame {}", index);
+            }
+            ApngCommand::Renumber { path } => {
+                let mut png = read(&path)?;
+                ensure!(apng::is_animated(&png), "this png is not animated");
+                apng::renumber(&mut png)?;
+                fs::write(&path, png.as_bytes())?;
+                println!("sequence numbers rewritten");
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use super::{Carrier, Method};
+use crate::{
+    chunk::Chunk,
+    encryption, envelope, padding,
+    passphrase::SEAL_OVERHEAD,
+    png::Png,
+    shamir::{self, Threshold},
+};
+use clap::Args;
+use std::fs;
+
+/// Reports how long a message fits in the image at `path` once encoded with the given options
+#[derive(Args, Debug)]
+pub(crate) struct Capacity {
+    /// path to the png file the message would be encoded in
+    #[clap(value_parser)]
+    path: String,
+
+    #[clap(flatten)]
+    carrier: Carrier,
+
+    /// account for sealing the message with a passphrase, as `encode --key` does
+    #[clap(long, action)]
+    key: bool,
+
+    /// age public key (`age1...`) or recipients file the message would be encrypted to, can be
+    /// repeated
+    #[clap(long = "recipient", value_parser)]
+    recipients: Vec<String>,
+
+    /// Reed-Solomon parity bytes added to every 255 byte block
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..255))]
+    ecc: Option<u8>,
+
+    /// account for padding the message
+    #[clap(long, action)]
+    pad: bool,
+
+    /// account for splitting the message in secret shares, like `3-of-5`
+    #[clap(long, value_parser)]
+    shares: Option<Threshold>,
+}
+
+impl Capacity {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let file = fs::read(&self.path)?;
+        let png: Png = file.as_slice().try_into()?;
+
+        if self.carrier.method == Method::Chunk {
+            println!(
+                "a chunk holds up to {} bytes, larger messages can be split with `--fragments`",
+                Chunk::MAX_DATA
+            );
+            return Ok(());
+        }
+        if self.carrier.method == Method::Trailer {
+            println!("the trailer holds any message, it is only limited by the file size");
+            return Ok(());
+        }
+
+        let have = self.carrier.capacity(&png)?;
+        println!("the image holds about {} bytes", have);
+
+        let needed = self.payload_size(0)?;
+        if needed >= have {
+            println!("no message fits, the options alone need {} bytes", needed);
+            return Ok(());
+        }
+
+        // payloads only grow with the message, so the longest one that fits is searched for
+        let (mut fits, mut too_long) = (0, have + 1);
+        while too_long - fits > 1 {
+            let length = fits + (too_long - fits) / 2;
+            if self.payload_size(length)? <= have {
+                fits = length;
+            } else {
+                too_long = length;
+            }
+        }
+        println!(
+            "a message of up to {} bytes fits, {} bytes go to the options",
+            fits,
+            self.payload_size(fits)? - fits
+        );
+        Ok(())
+    }
+
+    /// Size of what `encode` would hide for a message of `length` bytes
+    fn payload_size(&self, length: usize) -> Result<usize, anyhow::Error> {
+        let mut data = vec![0; length];
+        if self.pad {
+            data = padding::pad(&data)?;
+        }
+        if !self.recipients.is_empty() {
+            let mut recipients = Vec::new();
+            for recipient in &self.recipients {
+                recipients.extend(encryption::read_recipients(recipient)?);
+            }
+            data = encryption::encrypt(&data, &recipients)?;
+        }
+        if let Some(threshold) = self.shares {
+            data = shamir::split(&data, threshold).remove(0).as_bytes();
+        }
+        if let Some(ecc) = self.ecc {
+            data = envelope::seal(&data, ecc, 1)?.remove(0);
+        }
+        Ok(data.len() + if self.key { SEAL_OVERHEAD } else { 0 })
+    }
+}
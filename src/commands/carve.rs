@@ -0,0 +1,66 @@
+use crate::{carve::carve, png::Png};
+use clap::Args;
+use std::{fs, path::Path, path::PathBuf};
+
+/// Finds the pngs inside any file at `path`, like a disk image or a memory dump, optionally
+/// writing each of them to `directory`
+#[derive(Args, Debug)]
+pub(crate) struct Carve {
+    /// path to the file to search
+    #[clap(value_parser)]
+    path: String,
+
+    /// directory the pngs are written to, as `<name>-<offset>.png`
+    #[clap(value_parser)]
+    directory: Option<PathBuf>,
+
+    /// also keeps pngs with damaged chunks or cut short, up to their last chunk that fits
+    #[clap(long, action)]
+    lenient: bool,
+}
+
+impl Carve {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let data = fs::read(&self.path)?;
+        let found = carve(&data, self.lenient);
+        let name = Path::new(&self.path)
+            .file_stem()
+            .map(|x| x.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if let Some(directory) = &self.directory {
+            fs::create_dir_all(directory)?;
+        }
+
+        for carved in &found {
+            let bytes = carved.bytes(&data);
+            let ihdr = Png::from_damaged(bytes)
+                .ok()
+                .and_then(|(x, _)| x.ihdr().ok());
+            let mut line = format!(
+                "offset {:>10} (0x{:08x}): {} bytes, {} chunks",
+                carved.offset, carved.offset, carved.length, carved.chunks
+            );
+            if let Some(ihdr) = ihdr {
+                line.push_str(&format!(", {}", ihdr));
+            }
+            if carved.damaged > 0 {
+                line.push_str(&format!(", {} damaged", carved.damaged));
+            }
+            if !carved.complete {
+                line.push_str(", cut short before IEND");
+            }
+            println!("{}", line);
+
+            if let Some(directory) = &self.directory {
+                let path = directory.join(format!("{}-{:08x}.png", name, carved.offset));
+                fs::write(path, bytes)?;
+            }
+        }
+
+        match &self.directory {
+            Some(directory) => println!("written {} pngs to {:?}", found.len(), directory),
+            None => println!("{} pngs found", found.len()),
+        }
+        Ok(())
+    }
+}
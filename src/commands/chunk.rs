@@ -0,0 +1,284 @@
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    layout,
+    png::{Png, Position},
+};
+use anyhow::ensure;
+use clap::{Args, Subcommand};
+use std::{
+    fs,
+    io::{self, Write},
+};
+
+/// Copies the raw data of any chunk in and out of png files, or moves, renames, copies and removes
+/// chunks
+#[derive(Args, Debug)]
+pub(crate) struct Chunks {
+    #[clap(subcommand)]
+    command: ChunkCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum ChunkCommand {
+    /// Writes the data of a chunk of type `chunk_type` at file `path`, without its length, type
+    /// or CRC
+    Extract {
+        /// path to the png file (needs to be a png)
+        #[clap(value_parser)]
+        path: String,
+
+        /// type of the chunk
+        #[clap(value_parser)]
+        chunk_type: String,
+
+        /// which of the chunks of that type, counting from 0
+        #[clap(long, value_parser, default_value_t = 0)]
+        index: usize,
+
+        /// file the data is written to, instead of the standard output
+        #[clap(short, long, value_parser)]
+        output: Option<String>,
+    },
+    /// Adds a chunk of type `chunk_type` to the png at file `path`, holding the contents of the
+    /// file at `data`
+    Inject {
+        /// path to the png file (needs to be a png)
+        #[clap(value_parser)]
+        path: String,
+
+        /// type of the new chunk
+        #[clap(value_parser)]
+        chunk_type: String,
+
+        /// path to the file with the data of the chunk
+        #[clap(value_parser)]
+        data: String,
+
+        /// where the chunk goes: start, before-data, after-data, end or the index of a chunk
+        #[clap(long, value_parser, default_value = "before-data")]
+        position: Position,
+
+        /// injects critical chunks too, which decoders can't skip
+        #[clap(long, action)]
+        force: bool,
+    },
+    /// Moves a chunk of type `chunk_type` at file `path` to another position
+    Move {
+        /// path to the png file (needs to be a png)
+        #[clap(value_parser)]
+        path: String,
+
+        /// type of the chunk
+        #[clap(value_parser)]
+        chunk_type: String,
+
+        /// where the chunk goes: start, before-data, after-data, end or the index it ends up at
+        #[clap(long, value_parser)]
+        to: Position,
+
+        /// which of the chunks of that type, counting from 0
+        #[clap(long, value_parser, default_value_t = 0)]
+        index: usize,
+
+        /// moves critical chunks too
+        #[clap(long, action)]
+        force: bool,
+    },
+    /// Changes the type of a chunk of type `chunk_type` at file `path` to `new_type`
+    Rename {
+        /// path to the png file (needs to be a png)
+        #[clap(value_parser)]
+        path: String,
+
+        /// type of the chunk
+        #[clap(value_parser)]
+        chunk_type: String,
+
+        /// new type of the chunk
+        #[clap(value_parser)]
+        new_type: String,
+
+        /// which of the chunks of that type, counting from 0
+        #[clap(long, value_parser, default_value_t = 0)]
+        index: usize,
+
+        /// renames critical chunks too, or to a critical type
+        #[clap(long, action)]
+        force: bool,
+    },
+    /// Duplicates a chunk of type `chunk_type` at file `path`
+    Copy {
+        /// path to the png file (needs to be a png)
+        #[clap(value_parser)]
+        path: String,
+
+        /// type of the chunk
+        #[clap(value_parser)]
+        chunk_type: String,
+
+        /// where the copy goes: start, before-data, after-data, end or the index of a chunk
+        #[clap(long, value_parser)]
+        to: Position,
+
+        /// which of the chunks of that type, counting from 0
+        #[clap(long, value_parser, default_value_t = 0)]
+        index: usize,
+
+        /// copies critical chunks too
+        #[clap(long, action)]
+        force: bool,
+    },
+    /// Removes a chunk of type `chunk_type` at file `path`
+    Rm {
+        /// path to the png file (needs to be a png)
+        #[clap(value_parser)]
+        path: String,
+
+        /// type of the chunk
+        #[clap(value_parser)]
+        chunk_type: String,
+
+        /// which of the chunks of that type, counting from 0
+        #[clap(long, value_parser, default_value_t = 0)]
+        index: usize,
+
+        /// removes every chunk of that type
+        #[clap(long, action, conflicts_with = "index")]
+        all: bool,
+
+        /// removes critical chunks too
+        #[clap(long, action)]
+        force: bool,
+    },
+}
+
+fn read(path: &str) -> anyhow::Result<Png> {
+    let file = fs::read(path)?;
+    file.as_slice().try_into()
+}
+
+/// Refuses to touch a critical chunk without `force`, as the image may no longer decode
+fn check_critical(chunk_type: &ChunkType, force: bool) -> anyhow::Result<()> {
+    ensure!(
+        force || !chunk_type.is_critical(),
+        "{} is a critical chunk and the image may break, use `--force` to edit it anyway",
+        chunk_type
+    );
+    Ok(())
+}
+
+/// Writes `png` to `path` and warns about the rules of the specification it now breaks
+fn write(path: &str, png: &Png) -> anyhow::Result<()> {
+    fs::write(path, png.as_bytes())?;
+    for problem in layout::problems(png) {
+        println!("warning: {}", problem);
+    }
+    Ok(())
+}
+
+impl Chunks {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        match self.command {
+            ChunkCommand::Extract {
+                path,
+                chunk_type,
+                index,
+                output,
+            } => {
+                let png = read(&path)?;
+                let data = png.chunks()[png.find_chunk(&chunk_type, index)?].data();
+                match output {
+                    Some(output) => {
+                        fs::write(&output, data)?;
+                        println!("written {} bytes to {}", data.len(), output);
+                    }
+                    None => io::stdout().write_all(data)?,
+                }
+            }
+            ChunkCommand::Inject {
+                path,
+                chunk_type,
+                data,
+                position,
+                force,
+            } => {
+                let mut png = read(&path)?;
+                let chunk_type: ChunkType = chunk_type.parse()?;
+                check_critical(&chunk_type, force)?;
+                let data = fs::read(&data)?;
+                let length = data.len();
+                let index = png.insert_chunk(Chunk::new(chunk_type, data), position)?;
+                println!(
+                    "injected a {} chunk of {} bytes at index {}",
+                    chunk_type, length, index
+                );
+                write(&path, &png)?;
+            }
+            ChunkCommand::Move {
+                path,
+                chunk_type,
+                to,
+                index,
+                force,
+            } => {
+                let mut png = read(&path)?;
+                let from = png.find_chunk(&chunk_type, index)?;
+                check_critical(png.chunks()[from].chunk_type(), force)?;
+                let to = png.move_chunk(from, to)?;
+                println!("moved {} from index {} to {}", chunk_type, from, to);
+                write(&path, &png)?;
+            }
+            ChunkCommand::Rename {
+                path,
+                chunk_type,
+                new_type,
+                index,
+                force,
+            } => {
+                let mut png = read(&path)?;
+                let at = png.find_chunk(&chunk_type, index)?;
+                let new_type: ChunkType = new_type.parse()?;
+                check_critical(png.chunks()[at].chunk_type(), force)?;
+                check_critical(&new_type, force)?;
+                png.rename_chunk(at, new_type)?;
+                println!("renamed {} at index {} to {}", chunk_type, at, new_type);
+                write(&path, &png)?;
+            }
+            ChunkCommand::Copy {
+                path,
+                chunk_type,
+                to,
+                index,
+                force,
+            } => {
+                let mut png = read(&path)?;
+                let from = png.find_chunk(&chunk_type, index)?;
+                check_critical(png.chunks()[from].chunk_type(), force)?;
+                let to = png.copy_chunk(from, to)?;
+                println!("copied {} at index {} to {}", chunk_type, from, to);
+                write(&path, &png)?;
+            }
+            ChunkCommand::Rm {
+                path,
+                chunk_type,
+                index,
+                all,
+                force,
+            } => {
+                let mut png = read(&path)?;
+                let at = png.find_chunk(&chunk_type, index)?;
+                check_critical(png.chunks()[at].chunk_type(), force)?;
+                let removed = match all {
+                    true => png
+                        .remove_chunks(|x| x.chunk_type().bytes() == chunk_type.as_bytes())?
+                        .len(),
+                    false => png.remove_at(at).map(|_| 1)?,
+                };
+                println!("removed {} {} chunks", removed, chunk_type);
+                write(&path, &png)?;
+            }
+        }
+        Ok(())
+    }
+}
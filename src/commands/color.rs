@@ -0,0 +1,79 @@
+use crate::{chunk::Chunk, color::IccProfile, png::Png};
+use anyhow::Context;
+use clap::Args;
+use std::{fs, path::PathBuf};
+
+/// Shows the color space chunks of the image at file `path`, optionally exporting or replacing
+/// its ICC profile
+#[derive(Args, Debug)]
+pub(crate) struct Color {
+    /// path to the png file (needs to be a png)
+    #[clap(value_parser)]
+    path: String,
+
+    /// writes the embedded ICC profile to this file
+    #[clap(long, value_parser)]
+    export_icc: Option<PathBuf>,
+
+    /// embeds the ICC profile in this file, replacing the current one and any sRGB chunk
+    #[clap(long, value_parser, conflicts_with = "remove-icc")]
+    icc: Option<PathBuf>,
+
+    /// name stored along with the profile, the file name by default
+    #[clap(long, value_parser, requires = "icc")]
+    icc_name: Option<String>,
+
+    /// removes the embedded ICC profile
+    #[clap(long, action)]
+    remove_icc: bool,
+}
+
+impl Color {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let file = fs::read(&self.path)?;
+        let mut png: Png = file.as_slice().try_into()?;
+        let color = png.color()?;
+
+        if let Some(path) = &self.export_icc {
+            let icc = color.icc.as_ref().context("this png has no ICC profile")?;
+            fs::write(path, icc.profile())?;
+            println!("written profile \"{}\" to {:?}", icc.name(), path);
+        }
+
+        if self.icc.is_none() && !self.remove_icc {
+            if self.export_icc.is_none() {
+                print!("{}", color);
+                for problem in color.problems(png.chunks(), &png.ihdr()?) {
+                    println!("warning: {}", problem);
+                }
+            }
+            return Ok(());
+        }
+
+        if self.remove_icc {
+            png.remove_chunk("iCCP")
+                .context("this png has no ICC profile")?;
+            println!("removed the ICC profile");
+        }
+        if let Some(path) = &self.icc {
+            let name = match &self.icc_name {
+                Some(name) => name.clone(),
+                None => path
+                    .file_stem()
+                    .map(|x| x.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            };
+            let icc = IccProfile::new(&name, fs::read(path)?)?;
+            icc.check_color_type(png.ihdr()?.color_type)?;
+
+            png.set_color_chunk(Chunk::new("iCCP".try_into()?, icc.as_bytes()))?;
+            if png.remove_chunk("sRGB").is_ok() {
+                println!("removed the sRGB chunk, it can't be present along with a profile");
+            }
+            println!("embedded profile \"{}\"", icc.name());
+        }
+
+        fs::write(&self.path, png.as_bytes())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use crate::png::Png;
+use anyhow::{ensure, Context};
+use clap::Args;
+use std::fs;
+
+/// Lists the EXIF tags at file `path`, optionally removing some of them
+#[derive(Args, Debug)]
+pub(crate) struct Exif {
+    /// path to png file with EXIF data (needs to be a png)
+    #[clap(value_parser)]
+    path: String,
+
+    /// tag to remove, either by name (`Model`) or by number (`0x0110`), optionally prefixed by its
+    /// directory (`GPS:0x0002`), which GPS and Interop numbers need
+    #[clap(long, value_parser)]
+    remove: Vec<String>,
+
+    /// removes every GPS tag
+    #[clap(long, value_parser)]
+    redact_gps: bool,
+}
+
+impl Exif {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let file = fs::read(&self.path)?;
+
+        let mut png: Png = file.as_slice().try_into()?;
+        let mut exif = png.exif()?.context("this png does not contain EXIF data")?;
+
+        if self.remove.is_empty() && !self.redact_gps {
+            print!("{}", exif);
+            print_summary(&exif);
+            return Ok(());
+        }
+
+        for tag in &self.remove {
+            let removed = exif.remove_tag(tag)?;
+            ensure!(removed > 0, "no EXIF tag {} in this png", tag);
+            println!("removed tag {}", tag);
+        }
+        if self.redact_gps {
+            if exif.redact_gps() {
+                println!("removed GPS information");
+            } else {
+                println!("no GPS information in this png");
+            }
+        }
+
+        png.set_exif(&exif)?;
+        fs::write(&self.path, png.as_bytes())?;
+
+        println!("EXIF data updated successfully");
+
+        Ok(())
+    }
+}
+
+fn print_summary(exif: &crate::exif::Exif) {
+    let fields = [
+        ("make", exif.make()),
+        ("model", exif.model()),
+        ("serial number", exif.serial_number()),
+        ("orientation", exif.orientation().map(|x| x.to_string())),
+        ("modified", exif.date_time()),
+        ("taken", exif.date_time_original()),
+        ("digitized", exif.date_time_digitized()),
+        (
+            "location",
+            exif.gps().map(|gps| match gps.altitude {
+                Some(altitude) => {
+                    format!("{:.6}, {:.6} ({} m)", gps.latitude, gps.longitude, altitude)
+                }
+                None => format!("{:.6}, {:.6}", gps.latitude, gps.longitude),
+            }),
+        ),
+    ];
+
+    println!();
+    for (name, value) in fields {
+        if let Some(value) = value {
+            println!("{:>14}: {}", name, value);
+        }
+    }
+}
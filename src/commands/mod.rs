@@ -0,0 +1,721 @@
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    decoy, encryption, envelope, padding,
+    passphrase::Passphrase,
+    png::Png,
+    shamir::{self, Share, Threshold},
+    stego,
+};
+use anyhow::{bail, ensure, Context};
+use clap::{Args, ValueEnum};
+use std::{
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::PathBuf,
+};
+
+mod analyze;
+mod apng;
+mod capacity;
+mod carve;
+mod chunk;
+mod color;
+mod exif;
+mod optimize;
+mod palette;
+mod physical;
+mod polyglot;
+mod scan;
+mod sign;
+mod trailer;
+
+pub(crate) use self::{
+    analyze::Analyze,
+    apng::Apng,
+    capacity::Capacity,
+    carve::Carve,
+    chunk::Chunks,
+    color::Color,
+    exif::Exif,
+    optimize::Optimize,
+    palette::Palette,
+    physical::Physical,
+    polyglot::Polyglot,
+    scan::Scan,
+    sign::{Keygen, Sign, Verify},
+    trailer::Trailer,
+};
+
+/// Where the message is hidden
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Method {
+    /// a chunk of its own
+    Chunk,
+    /// the lowest bits of every color sample, changing pixels slightly
+    Lsb,
+    /// the lengths of the back references in the compressed image data, leaving pixels intact
+    Idat,
+    /// the filter type of every row of the image, leaving pixels intact
+    Filter,
+    /// twin entries of the palette of an indexed image, leaving colors intact
+    Palette,
+    /// after the end of the image, where decoders stop reading
+    Trailer,
+}
+
+/// Where the message is hidden and how densely
+#[derive(Args, Debug, Clone, Copy)]
+pub(crate) struct Carrier {
+    /// where the message is hidden
+    #[clap(long, value_enum, default_value_t = Method::Chunk)]
+    method: Method,
+
+    /// lowest bits of every sample taken by the lsb method
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..=8), default_value_t = 1)]
+    bits: u8,
+}
+
+impl Carrier {
+    /// Rough number of payload bytes the image itself holds
+    fn capacity(self, png: &Png) -> Result<usize, anyhow::Error> {
+        match self.method {
+            Method::Chunk => bail!("chunks hold any amount of data"),
+            Method::Lsb => stego::lsb::capacity(png, self.bits),
+            Method::Idat => stego::idat::capacity(png),
+            Method::Filter => stego::filter::capacity(png),
+            Method::Palette => stego::palette::capacity(png),
+            Method::Trailer => stego::trailer::capacity(png),
+        }
+    }
+    fn embed(self, png: &mut Png, payload: &[u8]) -> Result<(), anyhow::Error> {
+        match self.method {
+            Method::Chunk => bail!("chunks are written by `encode`"),
+            Method::Lsb => stego::lsb::embed(png, payload, self.bits),
+            Method::Idat => stego::idat::embed(png, payload),
+            Method::Filter => stego::filter::embed(png, payload),
+            Method::Palette => stego::palette::embed(png, payload),
+            Method::Trailer => stego::trailer::embed(png, payload),
+        }
+    }
+    fn extract(self, png: &Png) -> Result<Vec<u8>, anyhow::Error> {
+        match self.method {
+            Method::Chunk => bail!("chunks are read by `decode`"),
+            Method::Lsb => stego::lsb::extract(png, self.bits),
+            Method::Idat => stego::idat::extract(png),
+            Method::Filter => stego::filter::extract(png),
+            Method::Palette => stego::palette::extract(png),
+            Method::Trailer => stego::trailer::extract(png),
+        }
+    }
+}
+
+/// Encode `message` into file in `path`
+#[derive(Args, Debug)]
+pub(crate) struct Encode {
+    /// path to the file that the message will be encoded with (needs to be a png)
+    #[clap(value_parser)]
+    path: String,
+
+    /// key of 4 alphabets where the message will be located
+    #[clap(value_parser)]
+    chunk_type: Option<String>,
+
+    /// message to be encoded
+    #[clap(value_parser)]
+    message: Option<String>,
+
+    /// path to the new png file with the message
+    #[clap(value_parser)]
+    output: Option<String>,
+
+    /// message to be encoded when there is no chunk type to name, as it is derived from `--key`
+    /// or the message goes in the image itself
+    #[clap(long = "message", value_name = "MESSAGE", value_parser, conflicts_with_all = &["chunk-type", "message"])]
+    unnamed_message: Option<String>,
+
+    /// path to the new png file with the message, which works with `--message` too
+    #[clap(
+        short = 'o',
+        long = "output",
+        value_name = "OUTPUT",
+        value_parser,
+        conflicts_with = "output"
+    )]
+    output_path: Option<String>,
+
+    /// age public key (`age1...`) or recipients file to encrypt the message to, can be repeated
+    #[clap(long = "recipient", value_parser)]
+    recipients: Vec<String>,
+
+    /// Reed-Solomon parity bytes added to every 255 byte block, which repair up to half as many
+    /// damaged bytes or as many missing ones
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..255))]
+    ecc: Option<u8>,
+
+    /// number of chunks the message is split across
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..))]
+    fragments: Option<u8>,
+
+    /// split the message in secret shares, like `3-of-5`, each one written to its own copy of the
+    /// image named `<name>-share<n>.png`
+    #[clap(long, value_parser)]
+    shares: Option<Threshold>,
+
+    /// passphrase the chunk type is derived from, instead of naming it, and whose key seals
+    /// every chunk so it looks like random data
+    #[clap(long, value_parser)]
+    key: Option<String>,
+
+    /// pad the message with random bytes, so its size only tells its length roughly
+    #[clap(long, action)]
+    pad: bool,
+
+    /// number of chunks of random data added next to the message, with its chunk type and
+    /// indistinguishable from it, which `remove --key` deletes along with the message
+    #[clap(long, value_parser, default_value_t = 0, requires = "key")]
+    decoys: usize,
+
+    #[clap(flatten)]
+    carrier: Carrier,
+}
+
+/// Reads message at file `path` on the key `chunk_type`
+#[derive(Args, Debug)]
+pub(crate) struct Decode {
+    /// path to png file with message (needs to be a png)
+    #[clap(value_parser)]
+    path: String,
+
+    /// key of 4 alphabets where the message is located
+    #[clap(value_parser, conflicts_with = "key")]
+    chunk_type: Option<String>,
+
+    /// passphrase the chunk type was derived from, instead of naming it
+    #[clap(long, value_parser)]
+    key: Option<String>,
+
+    #[clap(flatten)]
+    carrier: Carrier,
+
+    /// age identity file to decrypt the message with, can be repeated
+    #[clap(long = "identity", value_parser)]
+    identities: Vec<PathBuf>,
+
+    /// another png file holding a share of the same secret, can be repeated
+    #[clap(long = "share", value_parser)]
+    shares: Vec<String>,
+}
+
+/// Removes message at file `path` on the key `chunk_type`
+#[derive(Args, Debug)]
+pub(crate) struct Remove {
+    /// path to png file with message (needs to be a png)
+    #[clap(value_parser)]
+    path: String,
+
+    /// key of 4 alphabets where the message is located
+    #[clap(value_parser, required_unless_present = "key", conflicts_with = "key")]
+    chunk_type: Option<String>,
+
+    /// passphrase the chunk type was derived from, instead of naming it
+    #[clap(long, value_parser)]
+    key: Option<String>,
+}
+
+/// Print all utf-8 encodable chunk types to stdout
+#[derive(Args, Debug)]
+pub(crate) struct Print {
+    #[clap(value_parser)]
+    path: String,
+}
+
+impl Encode {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let file = fs::read(&self.path)?;
+
+        let mut png: Png = file.as_slice().try_into()?;
+        let passphrase = self.key.as_deref().map(Passphrase::derive);
+        let (chunk_type, message) = match passphrase.is_none()
+            && self.carrier.method == Method::Chunk
+        {
+            true => (
+                Some(
+                    self.chunk_type
+                        .as_deref()
+                        .context("missing chunk type, or `--key` to derive one")?
+                        .try_into()?,
+                ),
+                self.message.as_ref().context("missing message")?,
+            ),
+            false => {
+                ensure!(
+                    self.chunk_type.is_none(),
+                    "there is no chunk type to name with `--key` or in the image itself, pass the message with `--message`"
+                );
+                let chunk_type = passphrase
+                    .as_ref()
+                    .filter(|_| self.carrier.method == Method::Chunk)
+                    .map(Passphrase::chunk_type);
+                let message = self
+                    .unnamed_message
+                    .as_ref()
+                    .context("missing message, pass it with `--message`")?;
+                (chunk_type, message)
+            }
+        };
+        if self.carrier.method != Method::Chunk {
+            ensure!(
+                self.fragments.unwrap_or(1) == 1 && self.decoys == 0,
+                "the image itself holds a single payload, without fragments or decoys"
+            );
+        }
+        let mut data: Vec<u8> = message.as_str().into();
+        if self.pad {
+            ensure!(
+                passphrase.is_some() || !self.recipients.is_empty(),
+                "padding only hides the length of encrypted messages, use `--key` or `--recipient`"
+            );
+            data = padding::pad(&data)?;
+        }
+        if !self.recipients.is_empty() {
+            let mut recipients = Vec::new();
+            for recipient in &self.recipients {
+                recipients.extend(encryption::read_recipients(recipient)?);
+            }
+            data = encryption::encrypt(&data, &recipients)?;
+        }
+
+        // every file is ready before any is written, so a message that doesn't fit leaves none
+        let output = self.output.as_ref().or(self.output_path.as_ref());
+        let filename = PathBuf::from(output.unwrap_or(&self.path));
+        let mut outputs = Vec::new();
+        match self.shares {
+            None => {
+                self.embed(&mut png, chunk_type, passphrase.as_ref(), &data)?;
+                outputs.push((filename, png));
+            }
+            Some(threshold) => {
+                let stem = filename
+                    .file_stem()
+                    .context("empty file name")?
+                    .to_string_lossy()
+                    .into_owned();
+                for share in shamir::split(&data, threshold) {
+                    let mut png: Png = file.as_slice().try_into()?;
+                    self.embed(&mut png, chunk_type, passphrase.as_ref(), &share.as_bytes())?;
+                    let name = format!("{}-share{}.png", stem, share.index());
+                    outputs.push((filename.with_file_name(name), png));
+                }
+            }
+        }
+        for (filename, png) in outputs {
+            self.handle_write_file(filename, &png.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds `data` to `png` as one chunk, or as envelope fragments when asked for coding, sealing
+    /// them and mixing them with decoys when there is a passphrase
+    fn embed(
+        &self,
+        png: &mut Png,
+        chunk_type: Option<ChunkType>,
+        passphrase: Option<&Passphrase>,
+        data: &[u8],
+    ) -> Result<(), anyhow::Error> {
+        let mut payloads = vec![data.to_vec()];
+        if self.ecc.is_some() || self.fragments.is_some() {
+            payloads = envelope::seal(data, self.ecc.unwrap_or(0), self.fragments.unwrap_or(1))?;
+        }
+
+        let Some(chunk_type) = chunk_type else {
+            let payload = match passphrase {
+                Some(passphrase) => passphrase.seal(&payloads[0]),
+                None => payloads.swap_remove(0),
+            };
+            let capacity = self.carrier.capacity(png)?;
+            ensure!(
+                payload.len() <= capacity,
+                "needs {} bytes, have {}",
+                payload.len(),
+                capacity
+            );
+            self.carrier.embed(png, &payload)?;
+            match self.carrier.method {
+                Method::Trailer => println!("appended {} bytes after IEND", payload.len()),
+                _ => println!(
+                    "hid {} bytes in the image, which holds about {}",
+                    payload.len(),
+                    capacity
+                ),
+            }
+            return Ok(());
+        };
+
+        let mut chunks: Vec<Chunk> = payloads
+            .into_iter()
+            .map(|payload| match passphrase {
+                Some(passphrase) => Chunk::new(chunk_type, passphrase.seal(&payload)),
+                None => Chunk::new(chunk_type, payload),
+            })
+            .collect();
+        if self.decoys > 0 {
+            chunks = decoy::hide(chunks, self.decoys);
+        }
+        for chunk in chunks {
+            png.insert_before_end(chunk);
+        }
+        Ok(())
+    }
+
+    fn handle_write_file(
+        &self,
+        mut filename: PathBuf,
+        content: &[u8],
+    ) -> Result<(), anyhow::Error> {
+        let mut max_retries = 10;
+        let stem: OsString = filename.file_stem().context("empty file name")?.into();
+        let extension: OsString = filename
+            .extension()
+            .context("invalid file format: file does not contain a extension")?
+            .into();
+
+        ensure!(
+            extension == "png",
+            "invalid file format: file must be a png"
+        );
+
+        let max_size = filename.capacity() + "(10)".len();
+        loop {
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&filename)
+            {
+                Ok(mut file) => {
+                    file.write_all(content)?;
+                    break;
+                }
+                Err(ref err) if err.kind() == ErrorKind::AlreadyExists => {
+                    max_retries -= 1;
+
+                    if max_retries == 0 {
+                        bail!("could not write result to file")
+                    }
+
+                    let mut temp = OsString::with_capacity(max_size);
+                    temp.push(&stem);
+                    temp.push(format!("({})", 10 - max_retries));
+                    temp.push(".");
+                    temp.push(&extension);
+                    filename.set_file_name(temp);
+                }
+                Err(e) => {
+                    return Err(e).context(format!(
+                        "something wrent wrong wile trying to create the file {:?}",
+                        filename.as_os_str()
+                    ))
+                }
+            };
+        }
+        println!("written result to file {:?}", filename);
+        Ok(())
+    }
+}
+impl Decode {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let passphrase = self.key.as_deref().map(Passphrase::derive);
+        let chunk_type = match self.carrier.method {
+            Method::Chunk => Some(resolve_chunk_type(&self.chunk_type, passphrase.as_ref())?),
+            _ => {
+                ensure!(
+                    self.chunk_type.is_none(),
+                    "no chunk type is needed when the message is in the image itself"
+                );
+                None
+            }
+        };
+        let data = if self.shares.is_empty() {
+            self.read_payload(&self.path, chunk_type, passphrase.as_ref())?
+        } else {
+            let mut shares = Vec::new();
+            for path in std::iter::once(&self.path).chain(&self.shares) {
+                let payload = self.read_payload(path, chunk_type, passphrase.as_ref())?;
+                let share = Share::try_from(payload.as_slice())
+                    .with_context(|| format!("{} does not hold a secret share", path))?;
+                println!("found {} in {}", share, path);
+                shares.push(share);
+            }
+            shamir::combine(&shares)?
+        };
+        ensure!(
+            !shamir::is_share(&data),
+            "the message is split in secret shares, pass the files holding the others with `--share`"
+        );
+
+        let mut message = if encryption::is_encrypted(&data) {
+            ensure!(
+                !self.identities.is_empty(),
+                "the message is encrypted, an identity is needed to decode it"
+            );
+            let mut identities = Vec::new();
+            for path in &self.identities {
+                identities.extend(encryption::read_identities(path)?);
+            }
+            encryption::decrypt(&data, &identities)?
+        } else {
+            data
+        };
+        if padding::is_padded(&message) {
+            message = padding::unpad(&message)?;
+        }
+
+        println!(
+            "your secret message is: {}",
+            String::from_utf8(message).context("this type does not contain a valid message")?
+        );
+
+        Ok(())
+    }
+
+    /// Data of the `chunk_type` chunks in the file at `path`, or hidden in the image itself when
+    /// there is no chunk type, opened with the passphrase and put back together when fragmented
+    fn read_payload(
+        &self,
+        path: &str,
+        chunk_type: Option<ChunkType>,
+        passphrase: Option<&Passphrase>,
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        let file = fs::read(path)?;
+
+        let png: Png = match file.as_slice().try_into() {
+            Ok(png) => png,
+            Err(err) => {
+                let (png, damaged) = Png::from_damaged(&file).map_err(|_| err)?;
+                eprintln!(
+                    "warning: {} is damaged, {} chunks could not be read intact",
+                    path, damaged
+                );
+                png
+            }
+        };
+
+        let mut chunks: Vec<Vec<u8>> = match chunk_type {
+            Some(chunk_type) => {
+                let chunks: Vec<Vec<u8>> = png
+                    .chunks()
+                    .iter()
+                    .filter(|x| x.chunk_type() == &chunk_type)
+                    .map(|x| x.data().to_vec())
+                    .collect();
+                ensure!(
+                    !chunks.is_empty(),
+                    "no secret message with type {}",
+                    chunk_type
+                );
+                chunks
+            }
+            None => vec![self.carrier.extract(&png)?],
+        };
+
+        if let Some(passphrase) = passphrase {
+            let total = chunks.len();
+            chunks = chunks
+                .iter()
+                .filter_map(|x| passphrase.open(x).ok())
+                .collect();
+            ensure!(!chunks.is_empty(), "wrong passphrase or damaged message");
+            if chunks.len() < total {
+                eprintln!(
+                    "warning: {} of {} chunks could not be opened, they are decoys or damaged",
+                    total - chunks.len(),
+                    total
+                );
+            }
+        }
+
+        if chunks.iter().any(|x| envelope::is_fragment(x)) {
+            let opened = envelope::open(chunks.iter().map(|x| x.as_slice()))?;
+            if opened.fragments > 1 || opened.corrected > 0 {
+                println!("{}", opened);
+            }
+            return Ok(opened.payload);
+        }
+        Ok(chunks.swap_remove(0))
+    }
+}
+
+impl Remove {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let file = fs::read(&self.path)?;
+
+        let mut png: Png = file.as_slice().try_into()?;
+        let passphrase = self.key.as_deref().map(Passphrase::derive);
+        let chunk_type = resolve_chunk_type(&self.chunk_type, passphrase.as_ref())?.to_string();
+
+        let chunk = png
+            .remove_chunk(&chunk_type)
+            .context(format!("no secret message with type {}", &chunk_type))?;
+
+        // sealed chunks can't be told apart, and the decoys share the derived type with them
+        let fragment = passphrase.is_some() || envelope::is_fragment(chunk.data());
+        let mut fragments = 1;
+        while fragment
+            && png
+                .chunk_by_type(&chunk_type)
+                .is_some_and(|x| passphrase.is_some() || envelope::is_fragment(x.data()))
+        {
+            png.remove_chunk(&chunk_type)?;
+            fragments += 1;
+        }
+
+        if let Ok(mut file) = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&self.path)
+        {
+            file.write_all(&png.as_bytes())?
+        }
+
+        if passphrase.is_some() {
+            println!(
+                "{} sealed chunks deleted successfully, decoys included",
+                fragments
+            );
+            return Ok(());
+        }
+        if fragment {
+            println!("{} message fragments deleted successfully", fragments);
+            return Ok(());
+        }
+        if shamir::is_share(chunk.data()) {
+            println!("secret share deleted successfully");
+            return Ok(());
+        }
+        if encryption::is_encrypted(chunk.data()) {
+            println!("encrypted message deleted successfully");
+            return Ok(());
+        }
+
+        println!(
+            "your secret message is: {}",
+            chunk
+                .data_as_string()
+                .context("this type does not contain a valid message")?
+        );
+
+        println!("message deleted successfully");
+
+        Ok(())
+    }
+}
+
+/// The chunk type given by name, or derived from the passphrase
+fn resolve_chunk_type(
+    chunk_type: &Option<String>,
+    passphrase: Option<&Passphrase>,
+) -> Result<ChunkType, anyhow::Error> {
+    match (chunk_type, passphrase) {
+        (_, Some(passphrase)) => Ok(passphrase.chunk_type()),
+        (Some(chunk_type), None) => chunk_type.as_str().try_into(),
+        (None, None) => bail!("a chunk type or a `--key` is needed"),
+    }
+}
+
+impl Print {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let file = fs::read(&self.path)?;
+
+        let png: Png = file.as_slice().try_into()?;
+
+        let chunks = png.chunks();
+
+        let mut chunk_types: Vec<_> = chunks
+            .iter()
+            .filter_map(|chunk| {
+                if envelope::is_fragment(chunk.data()) {
+                    return Some((chunk.chunk_type(), Some("encoded")));
+                }
+                if shamir::is_share(chunk.data()) {
+                    return Some((chunk.chunk_type(), Some("secret share")));
+                }
+                if encryption::is_encrypted(chunk.data()) {
+                    return Some((chunk.chunk_type(), Some("encrypted")));
+                }
+                chunk
+                    .data_as_string()
+                    .ok()
+                    .and_then(|msg| if !msg.is_empty() { Some(msg) } else { None })
+                    .and(Some((chunk.chunk_type(), None)))
+            })
+            .collect();
+        chunk_types.dedup();
+
+        if !chunk_types.is_empty() {
+            println!("You can try one these chunk types:");
+            for (chunk_type, note) in chunk_types {
+                match note {
+                    Some(note) => println!("  {} ({})", chunk_type, note),
+                    None => println!("  {}", chunk_type),
+                }
+            }
+        } else {
+            println!("no messages in this png")
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        decoder::Image,
+        encoder,
+        ihdr::{ColorType, Ihdr},
+    };
+    use clap::{Command, FromArgMatches};
+
+    fn encode(args: &[&str]) -> Result<(), anyhow::Error> {
+        let matches = Encode::augment_args(Command::new("encode")).try_get_matches_from(args)?;
+        Encode::from_arg_matches(&matches)?.exec()
+    }
+
+    #[test]
+    fn test_encode_to_output() {
+        let dir = std::env::temp_dir().join(format!("just_png-encode-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let ihdr = Ihdr::new(8, 8, 8, ColorType::Rgb).unwrap();
+        let image = Image::from_samples(ihdr, &[7; 192]).unwrap();
+        let png = encoder::encode(&image, &Default::default()).unwrap();
+        let path = dir.join("image.png");
+        fs::write(&path, png.as_bytes()).unwrap();
+        let path = path.to_str().unwrap();
+
+        let keyed = dir.join("keyed.png");
+        let _ = fs::remove_file(&keyed);
+        let keyed = keyed.to_str().unwrap();
+        encode(&["encode", path, "--key", "k", "--message", "hi", "-o", keyed]).unwrap();
+        let png = Png::try_from(fs::read(keyed).unwrap().as_slice()).unwrap();
+        let passphrase = Passphrase::derive("k");
+        let chunk = png
+            .chunk_by_type(&passphrase.chunk_type().to_string())
+            .unwrap();
+        assert_eq!(passphrase.open(chunk.data()).unwrap(), b"hi");
+
+        let appended = dir.join("appended.png");
+        let _ = fs::remove_file(&appended);
+        let appended = appended.to_str().unwrap();
+        let args = ["encode", path, "--method", "trailer", "--message", "yo"];
+        encode(&[&args[..], &["--output", appended]].concat()).unwrap();
+        let png = Png::try_from(fs::read(appended).unwrap().as_slice()).unwrap();
+        assert_eq!(stego::trailer::extract(&png).unwrap(), b"yo");
+
+        assert!(encode(&["encode", path, "ruSt", "hi", keyed, "-o", keyed]).is_err());
+    }
+}
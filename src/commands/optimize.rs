@@ -0,0 +1,61 @@
+use crate::{optimize, png::Png, signature::SIGNATURE_CHUNK};
+use clap::Args;
+use std::fs;
+
+/// Makes the image at file `path` smaller without changing a single pixel, hidden chunks are kept
+#[derive(Args, Debug)]
+pub(crate) struct Optimize {
+    /// path to the png file to be optimized
+    #[clap(value_parser)]
+    path: String,
+
+    /// where to write the optimized image, `path` itself by default
+    #[clap(value_parser)]
+    output: Option<String>,
+
+    /// zlib compression level, from 0 (stored) to 9
+    #[clap(long, value_parser = clap::value_parser!(u8).range(0..=9), default_value_t = 9)]
+    level: u8,
+
+    /// also remove metadata chunks like text, timestamps and EXIF
+    #[clap(long, action)]
+    strip: bool,
+}
+
+impl Optimize {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let file = fs::read(&self.path)?;
+        let png: Png = file.as_slice().try_into()?;
+        let optimized = optimize::optimize(&png, self.level, self.strip)?;
+        let bytes = optimized.png.as_bytes();
+
+        if optimized.before != optimized.after {
+            println!("{} -> {}", optimized.before, optimized.after);
+        }
+        for chunk_type in &optimized.stripped {
+            println!("stripped {} chunk", chunk_type);
+        }
+        for chunk_type in &optimized.dropped {
+            println!(
+                "dropped {} chunk, it no longer matches the color type",
+                chunk_type
+            );
+        }
+        if png
+            .chunks()
+            .iter()
+            .any(|x| &x.chunk_type().bytes() == SIGNATURE_CHUNK)
+        {
+            println!("warning: the image data changed, the signature no longer verifies");
+        }
+
+        fs::write(self.output.as_ref().unwrap_or(&self.path), &bytes)?;
+        println!(
+            "{} -> {} bytes ({:.1}% smaller)",
+            file.len(),
+            bytes.len(),
+            100.0 - bytes.len() as f64 * 100.0 / file.len() as f64
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{
+    decoder::{self, Color},
+    palette::{self, Bkgd, PaletteChunks},
+    png::Png,
+};
+use anyhow::{bail, ensure, Context};
+use clap::Args;
+use std::{fs, path::Path};
+
+/// Shows the palette of the image at file `path` as swatches, optionally exporting, importing or
+/// changing its colors
+#[derive(Args, Debug)]
+pub(crate) struct Palette {
+    /// path to the png file (needs to be a png)
+    #[clap(value_parser)]
+    path: String,
+
+    /// writes the palette to this file, as JSON (`.json`) or a GIMP palette (`.gpl`)
+    #[clap(long, value_parser)]
+    export: Option<String>,
+
+    /// replaces the palette with the one in this JSON (`.json`) or GIMP palette (`.gpl`) file
+    #[clap(long = "import", value_parser)]
+    import_from: Option<String>,
+
+    /// changes the color of a palette entry, like `3=#ff8000` or `3=#ff800080` with alpha
+    #[clap(long, value_parser)]
+    set: Vec<String>,
+
+    /// moves every pixel of a palette index to another one, like `3=5`
+    #[clap(long, value_parser)]
+    remap: Vec<String>,
+}
+
+impl Palette {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let file = fs::read(&self.path)?;
+        let mut png: Png = file.as_slice().try_into()?;
+        let ihdr = png.ihdr()?;
+        let chunks = PaletteChunks::from_chunks(png.chunks(), &ihdr)?;
+        let mut colors = chunks.colors();
+
+        if let Some(path) = &self.export {
+            ensure!(!colors.is_empty(), "this png has no palette");
+            let name = Path::new(&self.path)
+                .file_stem()
+                .map(|x| x.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let contents = match extension(path)? {
+                Format::Json => palette::to_json(&colors),
+                Format::Gpl => palette::to_gpl(&colors, &name),
+            };
+            fs::write(path, contents)?;
+            println!("written {} colors to {}", colors.len(), path);
+        }
+
+        if self.import_from.is_none() && self.set.is_empty() && self.remap.is_empty() {
+            if self.export.is_none() {
+                print_palette(&png, &chunks)?;
+            }
+            return Ok(());
+        }
+
+        if let Some(path) = &self.import_from {
+            let contents = fs::read_to_string(path)?;
+            let imported = match extension(path)? {
+                Format::Json => palette::from_json(&contents)?,
+                // GIMP palettes have no transparency, the current one is kept
+                Format::Gpl => palette::from_gpl(&contents)?
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, x)| [x[0], x[1], x[2], colors.get(i).map_or(255, |x| x[3])])
+                    .collect(),
+            };
+            colors = imported;
+        }
+        for entry in &self.set {
+            let (index, color) = entry
+                .split_once('=')
+                .context("expected an entry like `3=#ff8000`")?;
+            let index: usize = index.trim().parse().context("invalid palette index")?;
+            ensure!(
+                index <= colors.len() && index < 256,
+                "can't set entry {}, the palette only has {} colors",
+                index,
+                colors.len()
+            );
+            let color = palette::parse_color(color)?;
+            match colors.get_mut(index) {
+                Some(entry) => *entry = color,
+                None => colors.push(color),
+            }
+        }
+        if self.import_from.is_some() || !self.set.is_empty() {
+            palette::set_colors(&mut png, &colors)?;
+            println!("palette updated to {} colors", colors.len());
+        }
+
+        if !self.remap.is_empty() {
+            let mut pairs = Vec::new();
+            for pair in &self.remap {
+                let (from, to) = pair
+                    .split_once('=')
+                    .context("expected indices like `3=5`")?;
+                let index = |x: &str| x.trim().parse::<u8>().context("invalid palette index");
+                pairs.push((index(from)?, index(to)?));
+            }
+            palette::remap(&mut png, &pairs)?;
+            println!("remapped {} palette indices", pairs.len());
+        }
+
+        fs::write(&self.path, png.as_bytes())?;
+        Ok(())
+    }
+}
+
+enum Format {
+    Json,
+    Gpl,
+}
+
+fn extension(path: &str) -> anyhow::Result<Format> {
+    match Path::new(path).extension().and_then(|x| x.to_str()) {
+        Some("json") => Ok(Format::Json),
+        Some("gpl") => Ok(Format::Gpl),
+        _ => bail!("palette files need a .json or .gpl extension"),
+    }
+}
+
+/// A few blanks painted with `color` on terminals that support 24-bit colors
+fn swatch(color: &Color) -> String {
+    format!(
+        "\x1b[48;2;{};{};{}m    \x1b[0m",
+        color[0], color[1], color[2]
+    )
+}
+
+fn print_palette(png: &Png, chunks: &PaletteChunks) -> anyhow::Result<()> {
+    let ihdr = png.ihdr()?;
+    let colors = chunks.colors();
+    if colors.is_empty() {
+        println!("no palette");
+    } else {
+        // pixels are only counted when they are indices into the palette
+        let counts = match decoder::decode(png) {
+            Ok(image) if !image.palette().is_empty() => palette::counts(&image),
+            _ => vec![],
+        };
+        for (i, color) in colors.iter().enumerate() {
+            let mut line = format!(
+                "{:>3} {} {:<9}",
+                i,
+                swatch(color),
+                palette::format_color(color)
+            );
+            if let Some(count) = counts.get(i) {
+                line.push_str(&format!(" {:>8} pixels", count));
+            }
+            if let Some(frequency) = chunks.hist.as_ref().and_then(|x| x.0.get(i)) {
+                line.push_str(&format!(", hIST {}", frequency));
+            }
+            println!("{}", line.trim_end());
+        }
+    }
+
+    match chunks.bkgd {
+        Some(Bkgd::Index(index)) => match colors.get(index as usize) {
+            Some(color) => println!("background: index {} {}", index, swatch(color)),
+            None => println!("background: index {}", index),
+        },
+        Some(Bkgd::Gray(gray)) => println!("background: gray {}", gray),
+        Some(Bkgd::Rgb(rgb)) => println!("background: {:?}", rgb),
+        None => {}
+    }
+    for splt in &chunks.suggested {
+        println!(
+            "suggested palette {:?}: {} colors at {} bits",
+            splt.name,
+            splt.entries.len(),
+            splt.depth
+        );
+    }
+    for problem in chunks.problems(png.chunks(), &ihdr) {
+        println!("warning: {}", problem);
+    }
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    physical::{Offset, Scale, Time, PHYSICAL_CHUNKS},
+    png::Png,
+};
+use anyhow::ensure;
+use clap::Args;
+use std::fs;
+
+/// Shows or edits the resolution, page offset, pixel scale and modification time of every image
+/// at `paths`
+#[derive(Args, Debug)]
+pub(crate) struct Physical {
+    /// paths to the png files (need to be pngs)
+    #[clap(value_parser, required = true)]
+    paths: Vec<String>,
+
+    /// sets the resolution in dots per inch (pHYs)
+    #[clap(long, value_parser)]
+    dpi: Option<u32>,
+
+    /// sets the modification time to now (tIME)
+    #[clap(long, action, conflicts_with = "time")]
+    touch: bool,
+
+    /// sets the modification time, like `2024-05-17 13:45:00` in UTC (tIME)
+    #[clap(long, value_parser)]
+    time: Option<Time>,
+
+    /// sets the position on a page, like `10,20` in pixels or `10,20um` in micrometers (oFFs)
+    #[clap(long, value_parser, allow_hyphen_values = true)]
+    offset: Option<Offset>,
+
+    /// sets the physical size of a pixel, like `0.001,0.001m` in meters or `1e-5,1e-5rad` in
+    /// radians (sCAL)
+    #[clap(long, value_parser)]
+    scale: Option<Scale>,
+
+    /// removes the chunk of this type, one of pHYs, tIME, oFFs or sCAL
+    #[clap(long, value_parser)]
+    remove: Vec<String>,
+}
+
+impl Physical {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let mut remove = Vec::new();
+        for chunk_type in &self.remove {
+            let chunk_type = ChunkType::try_from(chunk_type.as_str())?;
+            ensure!(
+                PHYSICAL_CHUNKS.contains(&&chunk_type.bytes()),
+                "{} is not a physical or timing chunk",
+                chunk_type
+            );
+            remove.push(chunk_type);
+        }
+        let chunks = self.chunks()?;
+
+        for path in &self.paths {
+            if self.paths.len() > 1 {
+                println!("{}:", path);
+            }
+            let file = fs::read(path)?;
+            let mut png: Png = file.as_slice().try_into()?;
+
+            if !self.edits() && remove.is_empty() {
+                print!("{}", png.physical()?);
+                continue;
+            }
+
+            for chunk_type in &remove {
+                if png.remove_chunk(&chunk_type.to_string()).is_ok() {
+                    println!("removed {} chunk", chunk_type);
+                }
+            }
+            if let Some(dpi) = self.dpi {
+                png.set_dpi(dpi)?;
+            }
+            match (self.touch, self.time) {
+                (true, _) => png.touch()?,
+                (false, Some(time)) => png.set_time(time)?,
+                (false, None) => {}
+            }
+            for chunk in &chunks {
+                png.set_chunk(chunk.clone())?;
+            }
+            // checks what was written can be read back
+            print!("{}", png.physical()?);
+            fs::write(path, png.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Whether any chunk is set
+    fn edits(&self) -> bool {
+        self.dpi.is_some()
+            || self.touch
+            || self.time.is_some()
+            || self.offset.is_some()
+            || self.scale.is_some()
+    }
+
+    /// `oFFs` and `sCAL` chunks to be written to every image
+    fn chunks(&self) -> anyhow::Result<Vec<Chunk>> {
+        let mut chunks = Vec::new();
+        if let Some(offset) = self.offset {
+            chunks.push(Chunk::new("oFFs".try_into()?, offset.as_bytes()));
+        }
+        if let Some(scale) = &self.scale {
+            chunks.push(Chunk::new("sCAL".try_into()?, scale.as_bytes()));
+        }
+        Ok(chunks)
+    }
+}
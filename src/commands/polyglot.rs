@@ -0,0 +1,78 @@
+use crate::{png::Png, polyglot};
+use anyhow::{ensure, Context};
+use clap::Args;
+use std::{fs, path::Path};
+
+/// Writes a copy of the png at file `path` to `output` that also opens as a ZIP archive of
+/// `files`, or lists the archive inside `path` when there is no `output`
+#[derive(Args, Debug)]
+pub(crate) struct Polyglot {
+    /// path to the png file (needs to be a png)
+    #[clap(value_parser)]
+    path: String,
+
+    /// path of the polyglot file to write
+    #[clap(value_parser)]
+    output: Option<String>,
+
+    /// files to put in the archive, under their file names
+    #[clap(value_parser, conflicts_with = "archive")]
+    files: Vec<String>,
+
+    /// appends this existing ZIP archive instead of making one out of `files`
+    #[clap(long, value_parser, requires = "output")]
+    archive: Option<String>,
+}
+
+impl Polyglot {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let file = fs::read(&self.path)?;
+        let mut png: Png = file.as_slice().try_into()?;
+
+        let Some(output) = &self.output else {
+            let entries = polyglot::list(&file)?;
+            for entry in &entries {
+                println!(
+                    "{:>10} bytes ({:>10} stored) at offset {:>8}: {}",
+                    entry.size, entry.compressed_size, entry.offset, entry.name
+                );
+            }
+            println!("{} files in the archive", entries.len());
+            return Ok(());
+        };
+
+        let archive = match &self.archive {
+            Some(path) => fs::read(path)?,
+            None => {
+                ensure!(
+                    !self.files.is_empty(),
+                    "missing files to put in the archive"
+                );
+                let files = self
+                    .files
+                    .iter()
+                    .map(|path| {
+                        let name = Path::new(path)
+                            .file_name()
+                            .context("invalid file path")?
+                            .to_string_lossy()
+                            .into_owned();
+                        Ok((name, fs::read(path)?))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                polyglot::zip(&files)?
+            }
+        };
+        polyglot::append(&mut png, &archive)?;
+
+        let bytes = png.as_bytes();
+        let entries = polyglot::list(&bytes)?;
+        fs::write(output, bytes)?;
+        println!(
+            "written {} with {} files in the archive",
+            output,
+            entries.len()
+        );
+        Ok(())
+    }
+}
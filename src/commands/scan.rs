@@ -0,0 +1,54 @@
+use crate::scan::{scan, Risk};
+use clap::Args;
+use std::fs;
+
+/// Looks for hidden payloads in the files at `paths` and prints a risk report for each
+#[derive(Args, Debug)]
+pub(crate) struct Scan {
+    /// paths to the png files to be scanned
+    #[clap(value_parser, required = true)]
+    paths: Vec<String>,
+
+    /// also list every chunk of each file
+    #[clap(long, value_parser)]
+    verbose: bool,
+}
+
+impl Scan {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let mut flagged = 0;
+
+        for path in &self.paths {
+            let report = match fs::read(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|file| scan(&file))
+            {
+                Ok(report) => report,
+                Err(e) => {
+                    println!("{}: could not be scanned: {}", path, e);
+                    continue;
+                }
+            };
+
+            print!("{}: {}", path, report);
+            if self.verbose {
+                for chunk in &report.chunks {
+                    println!(
+                        "    offset {:>8}: {} ({} bytes){}",
+                        chunk.offset,
+                        chunk.chunk_type,
+                        chunk.length,
+                        if chunk.crc_ok { "" } else { ", bad CRC" }
+                    );
+                }
+            }
+            flagged += (report.risk() > Risk::Low) as usize;
+        }
+
+        if self.paths.len() > 1 {
+            println!("{} of {} files flagged", flagged, self.paths.len());
+        }
+
+        Ok(())
+    }
+}
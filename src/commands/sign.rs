@@ -0,0 +1,129 @@
+use crate::{
+    chunk_type::ChunkType,
+    encryption,
+    png::Png,
+    signature::{self, hex, key_id},
+};
+use anyhow::bail;
+use clap::Args;
+use std::{fs, path::PathBuf};
+
+/// Generates a signing key pair at `<name>.key` (secret) and `<name>.pub` (public)
+#[derive(Args, Debug)]
+pub(crate) struct Keygen {
+    /// path of the key files, without extension
+    #[clap(value_parser)]
+    name: PathBuf,
+
+    /// generate an age encryption identity at `<name>.txt` and its recipient at `<name>.recipient` instead
+    #[clap(long, action)]
+    age: bool,
+}
+
+/// Signs the image at file `path`, along with the chosen ancillary chunks
+#[derive(Args, Debug)]
+pub(crate) struct Sign {
+    /// path to the png file to be signed (needs to be a png)
+    #[clap(value_parser)]
+    path: String,
+
+    /// secret key file created by `keygen`
+    #[clap(long, value_parser)]
+    key: PathBuf,
+
+    /// extra chunk type to be covered by the signature, IHDR, PLTE and IDAT always are
+    #[clap(long = "chunk", value_parser)]
+    chunks: Vec<String>,
+}
+
+/// Checks the signature of the image at file `path`
+#[derive(Args, Debug)]
+pub(crate) struct Verify {
+    /// path to the signed png file
+    #[clap(value_parser)]
+    path: String,
+
+    /// trusted public key file, the one matching the signature is used
+    #[clap(long = "key", value_parser, required = true)]
+    keys: Vec<PathBuf>,
+}
+
+impl Keygen {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        if self.age {
+            return self.exec_age();
+        }
+
+        let key = signature::generate_key();
+        let secret = self.name.with_extension("key");
+        let public = self.name.with_extension("pub");
+
+        signature::write_signing_key(&secret, &key)?;
+        signature::write_verifying_key(&public, &key.verifying_key())?;
+
+        println!(
+            "written key {} to files {:?} and {:?}",
+            hex(&key_id(&key.verifying_key())),
+            secret,
+            public
+        );
+        Ok(())
+    }
+
+    fn exec_age(&self) -> Result<(), anyhow::Error> {
+        let identity = encryption::generate_identity();
+        let secret = self.name.with_extension("txt");
+        let public = self.name.with_extension("recipient");
+
+        encryption::write_identity(&secret, &identity)?;
+        encryption::write_recipient(&public, &identity.to_public())?;
+
+        println!(
+            "written recipient {} to files {:?} and {:?}",
+            identity.to_public(),
+            secret,
+            public
+        );
+        Ok(())
+    }
+}
+
+impl Sign {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let file = fs::read(&self.path)?;
+        let key = signature::read_signing_key(&self.key)?;
+        let chunks = self
+            .chunks
+            .iter()
+            .map(|x| ChunkType::try_from(x.as_str()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut png: Png = file.as_slice().try_into()?;
+        png.sign(&key, &chunks)?;
+        fs::write(&self.path, png.as_bytes())?;
+
+        println!("signed with key {}", hex(&key_id(&key.verifying_key())));
+        Ok(())
+    }
+}
+
+impl Verify {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let file = fs::read(&self.path)?;
+        let keys = self
+            .keys
+            .iter()
+            .map(|path| signature::read_verifying_key(path))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let png: Png = file.as_slice().try_into()?;
+        let verification = png.verify(&keys)?;
+        print!("{}", verification);
+
+        if !verification.is_intact() {
+            bail!("verification failed");
+        }
+        println!("image verified successfully");
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use crate::png::Png;
+use anyhow::ensure;
+use clap::Args;
+use std::fs;
+
+/// Shows the data appended after the end of the image at file `path`, optionally extracting or
+/// removing it
+#[derive(Args, Debug)]
+pub(crate) struct Trailer {
+    /// path to the png file (needs to be a png)
+    #[clap(value_parser)]
+    path: String,
+
+    /// writes the appended data to this file
+    #[clap(long, value_parser)]
+    extract: Option<String>,
+
+    /// removes the appended data from the png
+    #[clap(long, action)]
+    strip: bool,
+}
+
+impl Trailer {
+    pub(crate) fn exec(self) -> Result<(), anyhow::Error> {
+        let file = fs::read(&self.path)?;
+        let mut png: Png = file.as_slice().try_into()?;
+        let trailer = png.trailer();
+        ensure!(!trailer.is_empty(), "no data after IEND");
+
+        match sniff(trailer) {
+            Some(kind) => println!("{} bytes after IEND, looks like {}", trailer.len(), kind),
+            None => println!("{} bytes after IEND", trailer.len()),
+        }
+
+        if let Some(path) = &self.extract {
+            fs::write(path, trailer)?;
+            println!("written to {}", path);
+        }
+        if self.strip {
+            png.set_trailer(vec![]);
+            fs::write(&self.path, png.as_bytes())?;
+            println!("removed");
+        }
+        Ok(())
+    }
+}
+
+/// Names the kind of file `data` starts with, for the common ones
+fn sniff(data: &[u8]) -> Option<&'static str> {
+    const KINDS: &[(&[u8], &str)] = &[
+        (b"PK\x03\x04", "a ZIP archive"),
+        (b"Rar!\x1a\x07", "a RAR archive"),
+        (b"7z\xbc\xaf\x27\x1c", "a 7z archive"),
+        (b"\x1f\x8b", "gzip data"),
+        (b"%PDF", "a PDF document"),
+        (b"\x89PNG\r\n\x1a\n", "another png"),
+        (b"\xff\xd8\xff", "a JPEG image"),
+        (b"GIF8", "a GIF image"),
+    ];
+    KINDS
+        .iter()
+        .find(|(magic, _)| data.starts_with(magic))
+        .map(|(_, kind)| *kind)
+}
@@ -0,0 +1,440 @@
+use crate::{
+    filter::unfilter,
+    ihdr::{ColorType, Ihdr},
+    png::Png,
+    zlib,
+};
+use anyhow::{ensure, Context};
+
+/// Red, green, blue and alpha of a palette entry
+pub type Color = [u8; 4];
+
+/// First column, first row, column step and row step of each Adam7 pass
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// Unfiltered image data, each row packed the same way it is stored in the file. Interlaced
+/// images are put back in plain row order.
+#[derive(Debug, Clone)]
+pub struct Image {
+    ihdr: Ihdr,
+    data: Vec<u8>,
+    palette: Vec<Color>,
+    transparent: Option<Vec<u16>>,
+}
+
+impl Image {
+    pub fn new(ihdr: Ihdr, data: Vec<u8>) -> anyhow::Result<Image> {
+        ensure!(
+            ihdr.row_bytes(ihdr.width)
+                .checked_mul(ihdr.height as usize)
+                .is_some_and(|x| x == data.len()),
+            "image data does not match its dimensions"
+        );
+        Ok(Image {
+            ihdr,
+            data,
+            palette: vec![],
+            transparent: None,
+        })
+    }
+    /// Packs `samples`, one per channel of every pixel, the way `samples` returns them
+    pub fn from_samples(ihdr: Ihdr, samples: &[u16]) -> anyhow::Result<Image> {
+        let width = ihdr.width as usize * ihdr.color_type.channels();
+        let count = width
+            .checked_mul(ihdr.height as usize)
+            .context("the image is too large")?;
+        ensure!(
+            samples.len() == count,
+            "there must be {} samples for the image dimensions, not {}",
+            count,
+            samples.len()
+        );
+        let depth = ihdr.bit_depth as usize;
+        ensure!(
+            samples.iter().all(|&x| (x as u32) < 1 << depth),
+            "samples must fit in {} bits",
+            depth
+        );
+
+        let data = match depth {
+            16 => samples.iter().flat_map(|x| x.to_be_bytes()).collect(),
+            8 => samples.iter().map(|&x| x as u8).collect(),
+            _ => {
+                let row_bytes = ihdr.row_bytes(ihdr.width);
+                let mut data = vec![0u8; row_bytes * ihdr.height as usize];
+                for (row, samples) in data.chunks_exact_mut(row_bytes).zip(samples.chunks(width)) {
+                    for (i, &x) in samples.iter().enumerate() {
+                        let bit = i * depth;
+                        row[bit / 8] |= (x as u8) << (8 - depth - bit % 8);
+                    }
+                }
+                data
+            }
+        };
+        Image::new(ihdr, data)
+    }
+    pub fn with_palette(mut self, palette: Vec<Color>) -> Image {
+        self.palette = palette;
+        self
+    }
+    /// Sets the color drawn transparent in grayscale and RGB images, one sample per channel
+    pub fn with_transparent(mut self, transparent: Vec<u16>) -> Image {
+        self.transparent = Some(transparent);
+        self
+    }
+    pub fn ihdr(&self) -> &Ihdr {
+        &self.ihdr
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+    /// Entries of `PLTE` with their alpha from `tRNS`, empty for images without a palette
+    pub fn palette(&self) -> &[Color] {
+        &self.palette
+    }
+    /// Color from `tRNS` drawn transparent in grayscale and RGB images
+    pub fn transparent(&self) -> Option<&[u16]> {
+        self.transparent.as_deref()
+    }
+    pub fn row_bytes(&self) -> usize {
+        self.ihdr.row_bytes(self.ihdr.width)
+    }
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        self.data.chunks_exact(self.row_bytes())
+    }
+    /// Every sample of every pixel at its own bit depth, palette indexes for indexed images
+    pub fn samples(&self) -> Vec<u16> {
+        let depth = self.ihdr.bit_depth as usize;
+        let per_row = self.ihdr.width as usize * self.ihdr.color_type.channels();
+        match depth {
+            16 => self
+                .data
+                .chunks_exact(2)
+                .map(|x| u16::from_be_bytes([x[0], x[1]]))
+                .collect(),
+            8 => self.data.iter().map(|&x| x as u16).collect(),
+            _ => self
+                .rows()
+                .flat_map(|row| (0..per_row).map(move |i| read_bits(row, i, depth) as u16))
+                .collect(),
+        }
+    }
+    /// Every pixel as 8-bit RGBA, with the palette looked up and the `tRNS` color made
+    /// transparent
+    pub fn rgba8(&self) -> anyhow::Result<Vec<Color>> {
+        Ok(self
+            .rgba16()?
+            .into_iter()
+            .map(|x| x.map(|x| (x >> 8) as u8))
+            .collect())
+    }
+    /// Every pixel as 16-bit RGBA, like `rgba8` but keeping every bit of 16-bit samples
+    pub fn rgba16(&self) -> anyhow::Result<Vec<[u16; 4]>> {
+        let color_type = self.ihdr.color_type;
+        let max = (1u32 << self.ihdr.bit_depth) - 1;
+        let scale = |x: u16| (x as u32 * 65535 / max) as u16;
+
+        let samples = self.samples();
+        let pixels = samples.chunks_exact(color_type.channels());
+        if color_type == ColorType::Indexed {
+            return pixels
+                .map(|x| {
+                    self.palette
+                        .get(x[0] as usize)
+                        .map(|color| color.map(|x| x as u16 * 257))
+                        .with_context(|| format!("pixel index `{}` is outside the palette", x[0]))
+                })
+                .collect();
+        }
+
+        Ok(pixels
+            .map(|x| {
+                let alpha = match (&self.transparent, color_type.has_alpha()) {
+                    (_, true) => scale(x[x.len() - 1]),
+                    (Some(key), false) if key.as_slice() == x => 0,
+                    _ => 65535,
+                };
+                match color_type {
+                    ColorType::Grayscale | ColorType::GrayscaleAlpha => {
+                        [scale(x[0]), scale(x[0]), scale(x[0]), alpha]
+                    }
+                    _ => [scale(x[0]), scale(x[1]), scale(x[2]), alpha],
+                }
+            })
+            .collect())
+    }
+}
+
+/// Bytes of filtered rows the image data of `ihdr` holds, counting every Adam7 pass, or `None`
+/// when that overflows
+fn filtered_size(ihdr: &Ihdr) -> Option<usize> {
+    let (width, height) = (ihdr.width as usize, ihdr.height as usize);
+    let passes: &[(usize, usize, usize, usize)] = match ihdr.interlaced {
+        true => &ADAM7,
+        false => &[(0, 0, 1, 1)],
+    };
+    passes.iter().try_fold(0usize, |size, &(x0, y0, dx, dy)| {
+        let pass_width = width.saturating_sub(x0).div_ceil(dx);
+        let pass_height = height.saturating_sub(y0).div_ceil(dy);
+        match pass_width {
+            0 => Some(size),
+            _ => (ihdr.row_bytes(pass_width as u32) + 1)
+                .checked_mul(pass_height)?
+                .checked_add(size),
+        }
+    })
+}
+
+/// Inflates, unfilters and deinterlaces the image data of `png`
+pub fn decode(png: &Png) -> anyhow::Result<Image> {
+    let ihdr = png.ihdr()?;
+    let raw = zlib::decompress(&png.image_data())?;
+    let width = ihdr.width as usize;
+    let height = ihdr.height as usize;
+
+    // the dimensions come from the file, so they are checked against the data before allocating
+    let size = filtered_size(&ihdr).context("the image is too large")?;
+    ensure!(raw.len() >= size, "image data is shorter than the image");
+
+    let mut offset = 0;
+    let data = if ihdr.interlaced {
+        let row_bytes = ihdr.row_bytes(ihdr.width);
+        let bits_per_pixel = ihdr.bits_per_pixel();
+        let size = row_bytes
+            .checked_mul(height)
+            .context("the image is too large")?;
+        let mut data = vec![0u8; size];
+        for (x0, y0, dx, dy) in ADAM7 {
+            let pass_width = width.saturating_sub(x0).div_ceil(dx);
+            let pass_height = height.saturating_sub(y0).div_ceil(dy);
+            if pass_width == 0 || pass_height == 0 {
+                continue;
+            }
+
+            let pass = unfilter_pass(&ihdr, &raw, pass_width, pass_height, &mut offset)?;
+            let pass_row_bytes = ihdr.row_bytes(pass_width as u32);
+            for (py, from) in pass.chunks_exact(pass_row_bytes).enumerate() {
+                let y = y0 + py * dy;
+                let to = &mut data[y * row_bytes..(y + 1) * row_bytes];
+                for px in 0..pass_width {
+                    copy_pixel(from, px, to, x0 + px * dx, bits_per_pixel);
+                }
+            }
+        }
+        data
+    } else {
+        unfilter_pass(&ihdr, &raw, width, height, &mut offset)?
+    };
+
+    let mut palette: Vec<Color> = vec![];
+    let mut transparent = None;
+    let trns = png.chunk_by_type("tRNS").map(|x| x.data());
+    if let Some(plte) = png.chunk_by_type("PLTE") {
+        let alphas = trns.unwrap_or_default();
+        palette = plte
+            .data()
+            .chunks_exact(3)
+            .enumerate()
+            .map(|(i, x)| [x[0], x[1], x[2], alphas.get(i).copied().unwrap_or(255)])
+            .collect();
+    }
+    match ihdr.color_type {
+        ColorType::Indexed => ensure!(!palette.is_empty(), "missing PLTE chunk"),
+        ColorType::Grayscale | ColorType::Rgb => {
+            transparent = trns.map(|x| {
+                x.chunks_exact(2)
+                    .map(|x| u16::from_be_bytes([x[0], x[1]]))
+                    .collect()
+            })
+        }
+        _ => {}
+    }
+
+    Ok(Image {
+        ihdr,
+        data,
+        palette,
+        transparent,
+    })
+}
+
+/// Unfilters the `height` rows `width` pixels wide starting at `offset` of `raw`, moving
+/// `offset` past them
+fn unfilter_pass(
+    ihdr: &Ihdr,
+    raw: &[u8],
+    width: usize,
+    height: usize,
+    offset: &mut usize,
+) -> anyhow::Result<Vec<u8>> {
+    let row_bytes = ihdr.row_bytes(width as u32);
+    let size = (row_bytes + 1)
+        .checked_mul(height)
+        .context("the image is too large")?;
+    let lines = raw
+        .get(*offset..)
+        .and_then(|x| x.get(..size))
+        .context("image data is shorter than the image")?;
+    *offset += size;
+
+    let mut data = vec![0u8; row_bytes * height];
+    let mut previous = vec![0u8; row_bytes];
+    for (y, line) in lines.chunks_exact(row_bytes + 1).enumerate() {
+        let row = &mut data[y * row_bytes..(y + 1) * row_bytes];
+        row.copy_from_slice(&line[1..]);
+        unfilter(line[0], row, &previous, ihdr.filter_distance())?;
+        previous.copy_from_slice(row);
+    }
+    Ok(data)
+}
+
+/// Value of the `index`th group of `bits` bits in `row`, most significant first
+fn read_bits(row: &[u8], index: usize, bits: usize) -> u8 {
+    let bit = index * bits;
+    row[bit / 8] >> (8 - bits - bit % 8) & ((1u16 << bits) - 1) as u8
+}
+
+/// Copies pixel `from_x` of the row `from` over pixel `to_x` of the row `to`, which must still be
+/// zero when pixels are smaller than a byte
+fn copy_pixel(from: &[u8], from_x: usize, to: &mut [u8], to_x: usize, bits_per_pixel: usize) {
+    if bits_per_pixel >= 8 {
+        let bytes = bits_per_pixel / 8;
+        to[to_x * bytes..(to_x + 1) * bytes]
+            .copy_from_slice(&from[from_x * bytes..(from_x + 1) * bytes]);
+    } else {
+        let bit = to_x * bits_per_pixel;
+        to[bit / 8] |= read_bits(from, from_x, bits_per_pixel) << (8 - bits_per_pixel - bit % 8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    fn png(ihdr: Ihdr, raw: &[u8], extra: Vec<Chunk>) -> Png {
+        let mut chunks = vec![Chunk::new("IHDR".try_into().unwrap(), ihdr.as_bytes())];
+        chunks.extend(extra);
+        chunks.push(Chunk::new("IDAT".try_into().unwrap(), zlib::compress(raw)));
+        chunks.push(Chunk::new("IEND".try_into().unwrap(), vec![]));
+        Png::from_chunks(chunks)
+    }
+
+    /// Image data of `image` split in Adam7 passes, every row with filter type 0
+    fn interlace(image: &Image) -> Vec<u8> {
+        let ihdr = image.ihdr();
+        let (width, height) = (ihdr.width as usize, ihdr.height as usize);
+        let mut raw = vec![];
+        for (x0, y0, dx, dy) in ADAM7 {
+            let pass_width = width.saturating_sub(x0).div_ceil(dx);
+            if pass_width == 0 {
+                continue;
+            }
+            for y in (y0..height).step_by(dy) {
+                let mut row = vec![0; ihdr.row_bytes(pass_width as u32)];
+                for px in 0..pass_width {
+                    let from = &image.data()[y * image.row_bytes()..];
+                    copy_pixel(from, x0 + px * dx, &mut row, px, ihdr.bits_per_pixel());
+                }
+                raw.push(0);
+                raw.extend(row);
+            }
+        }
+        raw
+    }
+
+    #[test]
+    fn test_deinterlace() {
+        for (bit_depth, color_type) in [
+            (1, ColorType::Grayscale),
+            (2, ColorType::Grayscale),
+            (8, ColorType::Rgb),
+            (16, ColorType::GrayscaleAlpha),
+        ] {
+            for (width, height) in [(1, 1), (5, 3), (13, 9)] {
+                let ihdr = Ihdr::new(width, height, bit_depth, color_type).unwrap();
+                let data: Vec<u8> = (0..ihdr.row_bytes(width) * height as usize)
+                    .map(|i| (i * 37 % 256) as u8)
+                    .collect();
+                let plain = Image::new(ihdr, data).unwrap();
+
+                let interlaced = Ihdr {
+                    interlaced: true,
+                    ..ihdr
+                };
+                let decoded = decode(&png(interlaced, &interlace(&plain), vec![])).unwrap();
+                assert_eq!(decoded.samples(), plain.samples());
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_samples() {
+        let ihdr = Ihdr::new(3, 2, 2, ColorType::Grayscale).unwrap();
+        let image = Image::from_samples(ihdr, &[3, 2, 1, 0, 1, 2]).unwrap();
+        assert_eq!(image.data(), [0b1110_0100, 0b0001_1000]);
+        assert_eq!(image.samples(), [3, 2, 1, 0, 1, 2]);
+        assert!(Image::from_samples(ihdr, &[4, 2, 1, 0, 1, 2]).is_err());
+        assert!(Image::from_samples(ihdr, &[1]).is_err());
+    }
+
+    #[test]
+    fn test_samples() {
+        let ihdr = Ihdr::new(3, 1, 2, ColorType::Grayscale).unwrap();
+        let image = Image::new(ihdr, vec![0b1110_0100]).unwrap();
+        assert_eq!(image.samples(), [3, 2, 1]);
+        assert_eq!(image.rgba8().unwrap()[0], [255, 255, 255, 255]);
+        assert_eq!(image.rgba8().unwrap()[1], [170, 170, 170, 255]);
+
+        let ihdr = Ihdr::new(1, 1, 16, ColorType::Rgba).unwrap();
+        let image = Image::new(ihdr, vec![1, 2, 3, 4, 5, 6, 0xff, 0xff]).unwrap();
+        assert_eq!(image.samples(), [0x0102, 0x0304, 0x0506, 0xffff]);
+        assert_eq!(image.rgba8().unwrap(), [[1, 3, 5, 255]]);
+    }
+
+    #[test]
+    fn test_palette_and_transparency() {
+        let ihdr = Ihdr::new(4, 1, 4, ColorType::Indexed).unwrap();
+        let extra = vec![
+            Chunk::new("PLTE".try_into().unwrap(), vec![1, 2, 3, 4, 5, 6]),
+            Chunk::new("tRNS".try_into().unwrap(), vec![0]),
+        ];
+        let image = decode(&png(ihdr, &[0, 0x01, 0x10], extra)).unwrap();
+        assert_eq!(
+            image.rgba8().unwrap(),
+            [[1, 2, 3, 0], [4, 5, 6, 255], [4, 5, 6, 255], [1, 2, 3, 0]]
+        );
+        assert!(decode(&png(ihdr, &[0, 0x01, 0x10], vec![])).is_err());
+
+        let ihdr = Ihdr::new(2, 1, 8, ColorType::Rgb).unwrap();
+        let extra = vec![Chunk::new(
+            "tRNS".try_into().unwrap(),
+            vec![0, 1, 0, 2, 0, 3],
+        )];
+        let image = decode(&png(ihdr, &[0, 1, 2, 3, 4, 5, 6], extra)).unwrap();
+        assert_eq!(image.rgba8().unwrap(), [[1, 2, 3, 0], [4, 5, 6, 255]]);
+    }
+
+    #[test]
+    fn test_dimensions_beyond_the_data() {
+        let ihdr = Ihdr::new(u32::MAX >> 1, u32::MAX >> 1, 16, ColorType::Rgba).unwrap();
+        assert!(decode(&png(ihdr, &[0, 1, 2, 3], vec![])).is_err());
+        let interlaced = Ihdr {
+            interlaced: true,
+            ..ihdr
+        };
+        assert!(decode(&png(interlaced, &[0, 1, 2, 3], vec![])).is_err());
+
+        let ihdr = Ihdr::new(4, 4, 8, ColorType::Grayscale).unwrap();
+        assert!(decode(&png(ihdr, &[0; 19], vec![])).is_err());
+        assert!(decode(&png(ihdr, &[0; 20], vec![])).is_ok());
+    }
+}
@@ -0,0 +1,41 @@
+use crate::chunk::Chunk;
+use rand::{rngs::OsRng, seq::SliceRandom, RngCore};
+
+/// Makes `count` chunks of random content, each with the chunk type and the size of one of the
+/// `real` chunks, so that sealed messages can't be told apart from them
+pub fn decoys(real: &[Chunk], count: usize) -> Vec<Chunk> {
+    (0..count)
+        .filter_map(|_| real.choose(&mut OsRng))
+        .map(|x| {
+            let mut data = vec![0; x.data().len()];
+            OsRng.fill_bytes(&mut data);
+            Chunk::new(*x.chunk_type(), data)
+        })
+        .collect()
+}
+
+/// Mixes the `real` chunks with `count` decoys in a random order
+pub fn hide(real: Vec<Chunk>, count: usize) -> Vec<Chunk> {
+    let mut chunks = decoys(&real, count);
+    chunks.extend(real);
+    chunks.shuffle(&mut OsRng);
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+
+    #[test]
+    fn test_hide_among_decoys() {
+        let real = Chunk::new(ChunkType::hidden(&[1; 16]), vec![42; 300]);
+        let chunks = hide(vec![real], 5);
+        assert_eq!(chunks.len(), 6);
+        assert!(chunks.iter().all(|x| x.data().len() == 300));
+        assert!(chunks
+            .iter()
+            .all(|x| x.chunk_type() == &ChunkType::hidden(&[1; 16])));
+        assert_eq!(chunks.iter().filter(|x| x.data() == [42; 300]).count(), 1);
+    }
+}
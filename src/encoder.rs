@@ -0,0 +1,214 @@
+use crate::{
+    chunk::Chunk,
+    decoder::Image,
+    filter::{filter, filter_best},
+    ihdr::{ColorType, Ihdr},
+    png::Png,
+    zlib,
+};
+use anyhow::ensure;
+
+/// How the filter type of every row is picked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterStrategy {
+    /// the same filter type for every row
+    Fixed(u8),
+    /// the filter type with the smallest sum of the filtered bytes taken as signed
+    Heuristic,
+    /// the filter type whose row compresses smallest, trying every one of them
+    BruteForce,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    pub filter: FilterStrategy,
+    /// zlib compression level, from 0 (stored) to 9
+    pub level: u8,
+    /// largest amount of data in a single `IDAT` chunk
+    pub idat_size: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            filter: FilterStrategy::Heuristic,
+            level: zlib::DEFAULT_LEVEL,
+            idat_size: 8192,
+        }
+    }
+}
+
+/// Builds a PNG file out of `image`, with `PLTE` and `tRNS` when it has a palette or a
+/// transparent color. Rows are always written in plain order, never interlaced.
+pub fn encode(image: &Image, options: &Options) -> anyhow::Result<Png> {
+    ensure!(options.idat_size > 0, "IDAT chunks must hold some data");
+    let ihdr = Ihdr {
+        interlaced: false,
+        ..*image.ihdr()
+    };
+
+    let mut chunks = vec![Chunk::new("IHDR".try_into()?, ihdr.as_bytes())];
+    let palette = image.palette();
+    if !palette.is_empty() {
+        ensure!(
+            matches!(
+                ihdr.color_type,
+                ColorType::Indexed | ColorType::Rgb | ColorType::Rgba
+            ),
+            "{} images can't have a palette",
+            ihdr.color_type
+        );
+        ensure!(
+            palette.len() <= 256,
+            "a palette holds up to 256 colors, not {}",
+            palette.len()
+        );
+        let plte = palette.iter().flat_map(|x| [x[0], x[1], x[2]]).collect();
+        chunks.push(Chunk::new("PLTE".try_into()?, plte));
+    }
+
+    let mut trns: Vec<u8> = match ihdr.color_type {
+        ColorType::Indexed => palette.iter().map(|x| x[3]).collect(),
+        _ => image
+            .transparent()
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|x| x.to_be_bytes())
+            .collect(),
+    };
+    if ihdr.color_type == ColorType::Indexed {
+        while trns.last() == Some(&255) {
+            trns.pop();
+        }
+    }
+    if !trns.is_empty() {
+        chunks.push(Chunk::new("tRNS".try_into()?, trns));
+    }
+
+    for data in image_data(image, options)?.chunks(options.idat_size) {
+        chunks.push(Chunk::new("IDAT".try_into()?, data.to_vec()));
+    }
+    chunks.push(Chunk::new("IEND".try_into()?, vec![]));
+    Ok(Png::from_chunks(chunks))
+}
+
+/// Filtered and compressed rows of `image`, the contents of its `IDAT` chunks
+pub fn image_data(image: &Image, options: &Options) -> anyhow::Result<Vec<u8>> {
+    Ok(zlib::compress_level(
+        &filter_rows(image, options.filter)?,
+        options.level,
+    ))
+}
+
+/// Every row of `image` behind the filter type picked by `strategy`
+pub fn filter_rows(image: &Image, strategy: FilterStrategy) -> anyhow::Result<Vec<u8>> {
+    let row_bytes = image.row_bytes();
+    let distance = image.ihdr().filter_distance();
+    let mut raw = Vec::with_capacity((row_bytes + 1) * image.ihdr().height as usize);
+    let mut previous = vec![0u8; row_bytes];
+    for row in image.rows() {
+        let (filter_type, filtered) = match strategy {
+            FilterStrategy::Fixed(filter_type) => {
+                (filter_type, filter(filter_type, row, &previous, distance)?)
+            }
+            FilterStrategy::Heuristic => filter_best(0..5, row, &previous, distance)?,
+            FilterStrategy::BruteForce => {
+                let mut best: Option<(usize, u8, Vec<u8>)> = None;
+                for filter_type in 0..5 {
+                    let filtered = filter(filter_type, row, &previous, distance)?;
+                    let size = zlib::compress_level(&filtered, 1).len();
+                    if best.as_ref().is_none_or(|x| size < x.0) {
+                        best = Some((size, filter_type, filtered));
+                    }
+                }
+                best.map(|(_, filter_type, filtered)| (filter_type, filtered))
+                    .expect("five filter types were tried")
+            }
+        };
+        raw.push(filter_type);
+        raw.extend(filtered);
+        previous.copy_from_slice(row);
+    }
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder;
+
+    fn gradient(ihdr: Ihdr) -> Vec<u16> {
+        let max = (1u32 << ihdr.bit_depth) - 1;
+        let channels = ihdr.color_type.channels() as u32;
+        (0..ihdr.width * ihdr.height * channels)
+            .map(|i| (i * 7 % (max + 1)) as u16)
+            .collect()
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        for (bit_depth, color_type) in [
+            (1, ColorType::Grayscale),
+            (4, ColorType::Grayscale),
+            (16, ColorType::Grayscale),
+            (8, ColorType::Rgb),
+            (16, ColorType::Rgba),
+            (8, ColorType::GrayscaleAlpha),
+        ] {
+            let ihdr = Ihdr::new(19, 7, bit_depth, color_type).unwrap();
+            let samples = gradient(ihdr);
+            let image = Image::from_samples(ihdr, &samples).unwrap();
+            for filter in [
+                FilterStrategy::Fixed(0),
+                FilterStrategy::Fixed(4),
+                FilterStrategy::Heuristic,
+                FilterStrategy::BruteForce,
+            ] {
+                for level in [0, 1, 9] {
+                    let options = Options {
+                        filter,
+                        level,
+                        idat_size: 64,
+                    };
+                    let png = encode(&image, &options).unwrap();
+                    assert!(png
+                        .chunks()
+                        .iter()
+                        .all(|x| x.data().len() <= 64 || &x.chunk_type().bytes() != b"IDAT"));
+                    assert_eq!(decoder::decode(&png).unwrap().samples(), samples);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_palette_and_transparency() {
+        let ihdr = Ihdr::new(4, 2, 2, ColorType::Indexed).unwrap();
+        let palette = vec![[0, 0, 0, 0], [255, 0, 0, 255], [0, 0, 255, 255]];
+        let image = Image::from_samples(ihdr, &[0, 1, 2, 1, 2, 2, 0, 0])
+            .unwrap()
+            .with_palette(palette);
+        let png = encode(&image, &Options::default()).unwrap();
+        assert_eq!(png.chunk_by_type("tRNS").unwrap().data(), [0]);
+        let decoded = decoder::decode(&png).unwrap();
+        assert_eq!(decoded.rgba8().unwrap(), image.rgba8().unwrap());
+
+        let ihdr = Ihdr::new(2, 1, 8, ColorType::Rgb).unwrap();
+        let image = Image::from_samples(ihdr, &[1, 2, 3, 4, 5, 6])
+            .unwrap()
+            .with_transparent(vec![4, 5, 6]);
+        let png = encode(&image, &Options::default()).unwrap();
+        assert_eq!(
+            png.chunk_by_type("tRNS").unwrap().data(),
+            [0, 4, 0, 5, 0, 6]
+        );
+        let rgba = decoder::decode(&png).unwrap().rgba8().unwrap();
+        assert_eq!(rgba, [[1, 2, 3, 255], [4, 5, 6, 0]]);
+
+        let ihdr = Ihdr::new(2, 1, 8, ColorType::Grayscale).unwrap();
+        let image = Image::from_samples(ihdr, &[1, 2])
+            .unwrap()
+            .with_palette(vec![[0; 4]]);
+        assert!(encode(&image, &Options::default()).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use age::{
+    secrecy::ExposeSecret,
+    x25519::{Identity, Recipient},
+};
+use anyhow::{ensure, Context};
+use std::{
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+/// First line of every binary age file
+const AGE_HEADER: &[u8] = b"age-encryption.org/v1\n";
+
+/// Whether `data` looks like an age file, as written by `encrypt`
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(AGE_HEADER)
+}
+
+/// Seals `message` to all `recipients` in the age format, one X25519 stanza each
+pub fn encrypt(message: &[u8], recipients: &[Recipient]) -> anyhow::Result<Vec<u8>> {
+    ensure!(!recipients.is_empty(), "no recipients to encrypt to");
+
+    let encryptor =
+        age::Encryptor::with_recipients(recipients.iter().map(|x| x as &dyn age::Recipient))?;
+    let mut data = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut data)?;
+    writer.write_all(message)?;
+    writer.finish()?;
+    Ok(data)
+}
+
+/// Opens an age file with the first of `identities` matching one of its stanzas
+pub fn decrypt(data: &[u8], identities: &[Box<dyn age::Identity>]) -> anyhow::Result<Vec<u8>> {
+    let decryptor = age::Decryptor::new_buffered(data)?;
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|x| x.as_ref()))
+        .context("could not decrypt message")?;
+    let mut message = Vec::new();
+    reader.read_to_end(&mut message)?;
+    Ok(message)
+}
+
+pub fn generate_identity() -> Identity {
+    Identity::generate()
+}
+
+/// Parses `value` as an `age1...` recipient, or else reads every recipient in the file at that path
+pub fn read_recipients(value: &str) -> anyhow::Result<Vec<Recipient>> {
+    if value.starts_with("age1") {
+        return Ok(vec![parse_recipient(value)?]);
+    }
+
+    let content = fs::read_to_string(value)
+        .with_context(|| format!("`{}` is neither a recipient nor a recipients file", value))?;
+    let recipients = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(parse_recipient)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    ensure!(!recipients.is_empty(), "no recipients in file `{}`", value);
+    Ok(recipients)
+}
+
+fn parse_recipient(value: &str) -> anyhow::Result<Recipient> {
+    value
+        .parse()
+        .map_err(|err| anyhow::anyhow!("invalid recipient `{}`: {}", value, err))
+}
+
+/// Reads an identity file in the format written by `age-keygen`
+pub fn read_identities(path: &Path) -> anyhow::Result<Vec<Box<dyn age::Identity>>> {
+    let file = age::IdentityFile::from_file(path.to_string_lossy().into_owned())
+        .with_context(|| format!("could not read identity file {:?}", path))?;
+    Ok(file.into_identities()?)
+}
+
+pub fn write_identity(path: &Path, identity: &Identity) -> anyhow::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("could not create key file {:?}", path))?;
+    writeln!(file, "# public key: {}", identity.to_public())?;
+    writeln!(file, "{}", identity.to_string().expose_secret())?;
+    Ok(())
+}
+
+pub fn write_recipient(path: &Path, recipient: &Recipient) -> anyhow::Result<()> {
+    fs::write(path, format!("{}\n", recipient))
+        .with_context(|| format!("could not create key file {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boxed(identity: Identity) -> Vec<Box<dyn age::Identity>> {
+        vec![Box::new(identity)]
+    }
+
+    #[test]
+    fn test_encrypt_to_many_recipients() {
+        let alice = generate_identity();
+        let bob = generate_identity();
+        let recipients = [alice.to_public(), bob.to_public()];
+
+        let data = encrypt(b"This is where your secret message will be!", &recipients).unwrap();
+        assert!(is_encrypted(&data));
+        assert_eq!(
+            data.windows(b"-> X25519 ".len())
+                .filter(|x| x == b"-> X25519 ")
+                .count(),
+            2
+        );
+
+        for identity in [alice, bob] {
+            assert_eq!(
+                decrypt(&data, &boxed(identity)).unwrap(),
+                b"This is where your secret message will be!"
+            );
+        }
+    }
+
+    #[test]
+    fn test_decrypt_with_wrong_identity() {
+        let data = encrypt(b"secret", &[generate_identity().to_public()]).unwrap();
+        assert!(decrypt(&data, &boxed(generate_identity())).is_err());
+        assert!(!is_encrypted(b"secret"));
+    }
+
+    #[test]
+    fn test_key_files() {
+        let dir = std::env::temp_dir().join(format!("just_png-age-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let identity_path = dir.join("key.txt");
+        let recipient_path = dir.join("key.recipient");
+        let _ = fs::remove_file(&identity_path);
+
+        let identity = generate_identity();
+        write_identity(&identity_path, &identity).unwrap();
+        write_recipient(&recipient_path, &identity.to_public()).unwrap();
+
+        let recipients = read_recipients(recipient_path.to_str().unwrap()).unwrap();
+        assert_eq!(recipients, [identity.to_public()]);
+        assert_eq!(
+            read_recipients(&identity.to_public().to_string()).unwrap(),
+            recipients
+        );
+        assert!(read_recipients("age1nope").is_err());
+
+        let data = encrypt(b"secret", &recipients).unwrap();
+        let identities = read_identities(&identity_path).unwrap();
+        assert_eq!(decrypt(&data, &identities).unwrap(), b"secret");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,369 @@
+//! Container for payloads that are coded with Reed-Solomon and/or split across several chunks.
+//!
+//! Every fragment starts with a small header:
+//!
+//! | bytes | field                                              |
+//! |-------|----------------------------------------------------|
+//! | 3     | magic `jpE`                                        |
+//! | 1     | version                                            |
+//! | 1     | fragment index                                     |
+//! | 1     | fragment count                                     |
+//! | 1     | parity symbols per codeword, 0 when uncoded        |
+//! | 4     | payload length, big endian                         |
+//!
+//! The payload is cut into equally sized codewords whose symbols are interleaved before being
+//! split into fragments, so a lost fragment or a damaged run of bytes only costs a few symbols of
+//! each codeword.
+
+use crate::reed_solomon::ReedSolomon;
+use anyhow::{ensure, Context};
+use std::{collections::HashMap, fmt};
+
+const MAGIC: &[u8; 3] = b"jpE";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 11;
+
+/// Whether `data` is an envelope fragment, as written by `seal`
+pub fn is_fragment(data: &[u8]) -> bool {
+    data.len() >= HEADER_SIZE && data.starts_with(MAGIC) && data[3] == VERSION
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Header {
+    index: u8,
+    count: u8,
+    parity: u8,
+    length: u32,
+}
+
+impl Header {
+    fn as_bytes(&self) -> Vec<u8> {
+        [
+            &MAGIC[..],
+            &[VERSION, self.index, self.count, self.parity],
+            &self.length.to_be_bytes(),
+        ]
+        .concat()
+    }
+    fn parse(data: &[u8]) -> Option<Header> {
+        if !is_fragment(data) {
+            return None;
+        }
+        Some(Header {
+            index: data[4],
+            count: data[5],
+            parity: data[6],
+            length: u32::from_be_bytes(data[7..11].try_into().ok()?),
+        })
+    }
+}
+
+/// Sizes of the coded stream for a payload of `length` bytes
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    codewords: usize,
+    data_per_codeword: usize,
+    parity: usize,
+}
+
+impl Layout {
+    fn new(length: usize, parity: usize) -> Layout {
+        if parity == 0 {
+            return Layout {
+                codewords: 1,
+                data_per_codeword: length,
+                parity,
+            };
+        }
+        let max_data = ReedSolomon::new(parity).map_or(1, |rs| rs.max_data());
+        let codewords = length.div_ceil(max_data).max(1);
+        Layout {
+            codewords,
+            // a codeword needs a data symbol, an empty payload is coded as a padding byte
+            data_per_codeword: length.div_ceil(codewords).max(1),
+            parity,
+        }
+    }
+    fn codeword_len(&self) -> usize {
+        self.data_per_codeword + self.parity
+    }
+    fn total(&self) -> usize {
+        self.codewords * self.codeword_len()
+    }
+    /// Range of the coded stream carried by fragment `index` out of `count`
+    fn fragment(&self, index: usize, count: usize) -> std::ops::Range<usize> {
+        index * self.total() / count..(index + 1) * self.total() / count
+    }
+}
+
+/// Codes `payload` with `parity` symbols per codeword, none when zero, and splits it in `count`
+/// fragments
+pub fn seal(payload: &[u8], parity: u8, count: u8) -> anyhow::Result<Vec<Vec<u8>>> {
+    ensure!(count > 0, "at least one fragment is needed");
+    ensure!(
+        parity < 255,
+        "parity symbols must be at most 254, or 0 for no error correction"
+    );
+    let length: u32 = payload
+        .len()
+        .try_into()
+        .context("payload is too large for an envelope")?;
+
+    let layout = Layout::new(payload.len(), parity as usize);
+    // an empty uncoded payload still takes a fragment, made of just the header
+    ensure!(
+        count as usize <= layout.total().max(1),
+        "payload is too small to be split in {} fragments",
+        count
+    );
+
+    let stream = if parity == 0 {
+        payload.to_vec()
+    } else {
+        let rs = ReedSolomon::new(parity as usize)?;
+        let mut padded = payload.to_vec();
+        padded.resize(layout.codewords * layout.data_per_codeword, 0);
+        let codewords: Vec<Vec<u8>> = padded
+            .chunks(layout.data_per_codeword.max(1))
+            .map(|data| rs.encode(data))
+            .chain(std::iter::repeat_with(|| rs.encode(&[])))
+            .take(layout.codewords)
+            .collect();
+
+        let mut stream = vec![0; layout.total()];
+        for (j, codeword) in codewords.iter().enumerate() {
+            for (i, &symbol) in codeword.iter().enumerate() {
+                stream[i * layout.codewords + j] = symbol;
+            }
+        }
+        stream
+    };
+
+    Ok((0..count)
+        .map(|index| {
+            let header = Header {
+                index,
+                count,
+                parity,
+                length,
+            };
+            let range = layout.fragment(index as usize, count as usize);
+            [header.as_bytes(), stream[range].to_vec()].concat()
+        })
+        .collect())
+}
+
+/// Payload recovered by `open`, along with what it took to recover it
+#[derive(Debug)]
+pub struct Opened {
+    pub payload: Vec<u8>,
+    pub fragments: usize,
+    /// Indexes of the fragments that were missing or unusable
+    pub missing: Vec<usize>,
+    /// Symbols repaired by the error correction, including the ones lost with missing fragments
+    pub corrected: usize,
+    pub erasures: usize,
+}
+
+/// Puts the fragments back together, ignoring data that is not a fragment, and corrects the
+/// payload when it was coded
+pub fn open<'a>(fragments: impl IntoIterator<Item = &'a [u8]>) -> anyhow::Result<Opened> {
+    let fragments: Vec<(Header, &[u8])> = fragments
+        .into_iter()
+        .filter_map(|data| Header::parse(data).map(|header| (header, &data[HEADER_SIZE..])))
+        .collect();
+    ensure!(!fragments.is_empty(), "no envelope fragments found");
+
+    // a fragment with a damaged header is dropped, so trust the settings most fragments agree on
+    let mut votes: HashMap<(u8, u8, u32), usize> = HashMap::new();
+    for (header, _) in &fragments {
+        *votes
+            .entry((header.count, header.parity, header.length))
+            .or_default() += 1;
+    }
+    let (count, parity, length) = votes
+        .into_iter()
+        .max_by_key(|&(settings, votes)| (votes, settings))
+        .map(|(settings, _)| settings)
+        .context("no envelope fragments found")?;
+    ensure!(count > 0, "invalid fragment count");
+
+    let layout = Layout::new(length as usize, parity as usize);
+    let longest = fragments.iter().map(|(_, data)| data.len()).max();
+    ensure!(
+        layout.total() <= (longest.unwrap_or(0) + 1) * count as usize,
+        "envelope length does not match its fragments"
+    );
+    let mut stream = vec![0; layout.total()];
+    let mut present = vec![false; count as usize];
+    for (header, data) in fragments {
+        let range = layout.fragment(header.index as usize, count as usize);
+        if (header.count, header.parity, header.length) != (count, parity, length)
+            || header.index >= count
+            || present[header.index as usize]
+            || data.len() != range.len()
+        {
+            continue;
+        }
+        stream[range].copy_from_slice(data);
+        present[header.index as usize] = true;
+    }
+    let missing: Vec<usize> = (0..count as usize).filter(|&i| !present[i]).collect();
+
+    if parity == 0 {
+        ensure!(
+            missing.is_empty(),
+            "{} of {} fragments are missing and the payload has no error correction",
+            missing.len(),
+            count
+        );
+        return Ok(Opened {
+            payload: stream,
+            fragments: count as usize,
+            missing,
+            corrected: 0,
+            erasures: 0,
+        });
+    }
+
+    let mut erased = vec![false; layout.total()];
+    for &index in &missing {
+        for position in layout.fragment(index, count as usize) {
+            erased[position] = true;
+        }
+    }
+
+    let rs = ReedSolomon::new(parity as usize)?;
+    let mut payload = Vec::with_capacity(layout.codewords * layout.data_per_codeword);
+    let mut corrected = 0;
+    let mut erasures = 0;
+    for j in 0..layout.codewords {
+        let positions = (0..layout.codeword_len()).map(|i| i * layout.codewords + j);
+        let mut codeword: Vec<u8> = positions.clone().map(|p| stream[p]).collect();
+        let lost: Vec<usize> = positions
+            .enumerate()
+            .filter(|&(_, p)| erased[p])
+            .map(|(i, _)| i)
+            .collect();
+
+        corrected += rs
+            .decode(&mut codeword, &lost)
+            .with_context(|| format!("codeword {} is too damaged to be recovered", j))?;
+        erasures += lost.len();
+        payload.extend_from_slice(&codeword[..layout.data_per_codeword]);
+    }
+    payload.truncate(length as usize);
+
+    Ok(Opened {
+        payload,
+        fragments: count as usize,
+        missing,
+        corrected,
+        erasures,
+    })
+}
+
+impl fmt::Display for Opened {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} fragments found",
+            self.fragments - self.missing.len(),
+            self.fragments
+        )?;
+        if self.corrected > 0 {
+            write!(
+                f,
+                ", {} symbols corrected ({} in missing fragments)",
+                self.corrected, self.erasures
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> Vec<u8> {
+        (0..1000u32).map(|x| (x * 31 % 251) as u8).collect()
+    }
+
+    fn open_all(fragments: &[Vec<u8>]) -> anyhow::Result<Opened> {
+        open(fragments.iter().map(|x| x.as_slice()))
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let fragments = seal(&payload(), 0, 1).unwrap();
+        assert_eq!(fragments.len(), 1);
+        assert!(is_fragment(&fragments[0]));
+        assert_eq!(fragments[0].len(), HEADER_SIZE + 1000);
+
+        let opened = open_all(&fragments).unwrap();
+        assert_eq!(opened.payload, payload());
+        assert_eq!(opened.corrected, 0);
+    }
+
+    #[test]
+    fn test_correct_damaged_bytes() {
+        let mut fragments = seal(&payload(), 16, 1).unwrap();
+        for byte in &mut fragments[0][HEADER_SIZE + 100..HEADER_SIZE + 130] {
+            *byte = !*byte;
+        }
+        let opened = open_all(&fragments).unwrap();
+        assert_eq!(opened.payload, payload());
+        assert_eq!(opened.corrected, 30);
+        assert_eq!(opened.erasures, 0);
+    }
+
+    #[test]
+    fn test_missing_fragments() {
+        let fragments = seal(&payload(), 64, 4).unwrap();
+        let opened = open_all(&[
+            fragments[0].clone(),
+            fragments[2].clone(),
+            fragments[3].clone(),
+        ])
+        .unwrap();
+        assert_eq!(opened.payload, payload());
+        assert_eq!(opened.missing, [1]);
+        assert!(opened.erasures > 0);
+
+        assert!(open_all(&fragments[..2]).is_err());
+        let uncoded = seal(&payload(), 0, 3).unwrap();
+        assert_eq!(open_all(&uncoded).unwrap().payload, payload());
+        assert!(open_all(&uncoded[1..]).is_err());
+    }
+
+    #[test]
+    fn test_damaged_header() {
+        let mut fragments = seal(b"short message", 8, 3).unwrap();
+        fragments[1][6] = 200;
+        fragments.reverse();
+        let opened = open_all(&fragments).unwrap();
+        assert_eq!(opened.payload, b"short message");
+        assert_eq!(opened.missing, [1]);
+    }
+
+    #[test]
+    fn test_empty_payload() {
+        let fragments = seal(&[], 0, 1).unwrap();
+        assert_eq!(fragments[0].len(), HEADER_SIZE);
+        assert!(open_all(&fragments).unwrap().payload.is_empty());
+
+        let fragments = seal(&[], 4, 2).unwrap();
+        assert!(open_all(&fragments[1..]).unwrap().payload.is_empty());
+        assert!(seal(&[], 0, 2).is_err());
+    }
+
+    #[test]
+    fn test_invalid_envelopes() {
+        assert!(seal(b"abc", 0, 4).is_err());
+        assert!(seal(b"abc", 0, 0).is_err());
+        assert!(seal(b"abc", 255, 1).is_err());
+        assert!(open([&b"not an envelope"[..]]).is_err());
+        assert!(!is_fragment(b"jpE"));
+    }
+}
@@ -0,0 +1,949 @@
+use anyhow::{bail, ensure, Context};
+use std::{collections::HashSet, fmt};
+
+/// TIFF structured metadata stored in the `eXIf` chunk (PNG 1.5+)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exif {
+    byte_order: ByteOrder,
+    primary: Ifd,
+    thumbnail: Option<Ifd>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    Little,
+    Big,
+}
+
+/// Image file directories reachable from the TIFF header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IfdKind {
+    Primary,
+    Exif,
+    Gps,
+    Interop,
+    Thumbnail,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Ifd {
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    tag: u16,
+    value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Byte(Vec<u8>),
+    Ascii(Vec<u8>),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>),
+    SByte(Vec<i8>),
+    Undefined(Vec<u8>),
+    SShort(Vec<i16>),
+    SLong(Vec<i32>),
+    SRational(Vec<(i32, i32)>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+    /// pointer to a nested directory (Exif, GPS or Interoperability)
+    Ifd(Ifd),
+    /// offset of the JPEG thumbnail, kept together with the bytes it points to
+    Thumbnail(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpsPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: Option<f64>,
+}
+
+const EXIF_POINTER: u16 = 0x8769;
+const GPS_POINTER: u16 = 0x8825;
+const INTEROP_POINTER: u16 = 0xA005;
+const THUMBNAIL_OFFSET: u16 = 0x0201;
+const THUMBNAIL_LENGTH: u16 = 0x0202;
+
+impl Exif {
+    pub fn byte_order(&self) -> ByteOrder {
+        self.byte_order
+    }
+    pub fn ifd(&self, kind: IfdKind) -> Option<&Ifd> {
+        match kind {
+            IfdKind::Primary => Some(&self.primary),
+            IfdKind::Thumbnail => self.thumbnail.as_ref(),
+            IfdKind::Exif => self.primary.sub_ifd(EXIF_POINTER),
+            IfdKind::Gps => self.primary.sub_ifd(GPS_POINTER),
+            IfdKind::Interop => self.ifd(IfdKind::Exif)?.sub_ifd(INTEROP_POINTER),
+        }
+    }
+    fn ifd_mut(&mut self, kind: IfdKind) -> Option<&mut Ifd> {
+        match kind {
+            IfdKind::Primary => Some(&mut self.primary),
+            IfdKind::Thumbnail => self.thumbnail.as_mut(),
+            IfdKind::Exif => self.primary.sub_ifd_mut(EXIF_POINTER),
+            IfdKind::Gps => self.primary.sub_ifd_mut(GPS_POINTER),
+            IfdKind::Interop => self.ifd_mut(IfdKind::Exif)?.sub_ifd_mut(INTEROP_POINTER),
+        }
+    }
+    pub fn get(&self, kind: IfdKind, tag: u16) -> Option<&Value> {
+        self.ifd(kind)?.get(tag)
+    }
+    /// Every tag in every directory, nested directory pointers excluded
+    pub fn entries(&self) -> Vec<(IfdKind, &Entry)> {
+        IfdKind::ALL
+            .iter()
+            .filter_map(|&kind| self.ifd(kind).map(|ifd| (kind, ifd)))
+            .flat_map(|(kind, ifd)| {
+                ifd.entries
+                    .iter()
+                    .filter(|entry| !matches!(entry.value, Value::Ifd(_)))
+                    .map(move |entry| (kind, entry))
+            })
+            .collect()
+    }
+    pub fn orientation(&self) -> Option<u16> {
+        self.get(IfdKind::Primary, 0x0112)?
+            .as_u32()
+            .map(|x| x as u16)
+    }
+    pub fn make(&self) -> Option<String> {
+        self.get(IfdKind::Primary, 0x010F)?.as_string()
+    }
+    pub fn model(&self) -> Option<String> {
+        self.get(IfdKind::Primary, 0x0110)?.as_string()
+    }
+    pub fn serial_number(&self) -> Option<String> {
+        self.get(IfdKind::Exif, 0xA431)?.as_string()
+    }
+    pub fn date_time(&self) -> Option<String> {
+        self.get(IfdKind::Primary, 0x0132)?.as_string()
+    }
+    pub fn date_time_original(&self) -> Option<String> {
+        self.get(IfdKind::Exif, 0x9003)?.as_string()
+    }
+    pub fn date_time_digitized(&self) -> Option<String> {
+        self.get(IfdKind::Exif, 0x9004)?.as_string()
+    }
+    pub fn gps(&self) -> Option<GpsPosition> {
+        let coordinate = |value_tag: u16, ref_tag: u16, negative: u8| -> Option<f64> {
+            let degrees = match self.get(IfdKind::Gps, value_tag)? {
+                Value::Rational(parts) if parts.len() == 3 => parts
+                    .iter()
+                    .zip([1.0, 60.0, 3600.0])
+                    .map(|(&(num, den), scale)| num as f64 / den as f64 / scale)
+                    .sum::<f64>(),
+                _ => return None,
+            };
+            match self.get(IfdKind::Gps, ref_tag) {
+                Some(Value::Ascii(text)) if text.first() == Some(&negative) => Some(-degrees),
+                _ => Some(degrees),
+            }
+        };
+        let altitude = match self.get(IfdKind::Gps, 0x0006) {
+            Some(Value::Rational(parts)) if parts.len() == 1 => {
+                let (num, den) = parts[0];
+                let below_sea_level = matches!(
+                    self.get(IfdKind::Gps, 0x0005),
+                    Some(Value::Byte(reference)) if reference.first() == Some(&1)
+                );
+                let altitude = num as f64 / den as f64;
+                Some(if below_sea_level { -altitude } else { altitude })
+            }
+            _ => None,
+        };
+
+        Some(GpsPosition {
+            latitude: coordinate(0x0002, 0x0001, b'S')?,
+            longitude: coordinate(0x0004, 0x0003, b'W')?,
+            altitude,
+        })
+    }
+    /// Removes `tag` from directory `kind`, nested directories are removed along with their pointer
+    /// and the thumbnail offset and length along with each other, as neither means anything alone
+    pub fn remove(&mut self, kind: IfdKind, tag: u16) -> Option<Value> {
+        let ifd = self.ifd_mut(kind)?;
+        let index = ifd.entries.iter().position(|entry| entry.tag == tag)?;
+        let value = ifd.entries.remove(index).value;
+        if matches!(tag, THUMBNAIL_OFFSET | THUMBNAIL_LENGTH) {
+            ifd.entries
+                .retain(|entry| !matches!(entry.tag, THUMBNAIL_OFFSET | THUMBNAIL_LENGTH));
+        }
+        Some(value)
+    }
+    /// Removes a tag given by name (`Model`) or number (`0x0110`), optionally prefixed by the
+    /// directory it is in (`GPS:0x0002`). Without a directory, names are removed from every
+    /// directory that knows them and numbers from IFD0, Exif and IFD1, the ones sharing the TIFF
+    /// tag numbers, as GPS and Interop numbers mean something else.
+    pub fn remove_tag(&mut self, spec: &str) -> anyhow::Result<usize> {
+        let (kinds, spec) = match spec.split_once(':') {
+            Some((name, spec)) => (vec![IfdKind::from_name(name)?], spec),
+            None => (IfdKind::ALL.to_vec(), spec),
+        };
+        let number = match spec.strip_prefix("0x").or_else(|| spec.strip_prefix("0X")) {
+            Some(hex) => Some(u16::from_str_radix(hex, 16).context("invalid tag number")?),
+            None => None,
+        };
+        ensure!(
+            number.is_some() || kinds.iter().any(|kind| kind.tag(spec).is_some()),
+            "unknown EXIF tag `{}`",
+            spec
+        );
+        let scoped = kinds.len() == 1;
+
+        let mut removed = 0;
+        // nested directories go first so removing their pointer does not hide a match
+        for kind in kinds.iter().rev() {
+            let tag = match number {
+                Some(_) if !scoped && kind.tag_names() != TIFF_TAGS => None,
+                Some(number) => Some(number),
+                None => kind.tag(spec),
+            };
+            if let Some(tag) = tag {
+                removed += self.remove(*kind, tag).is_some() as usize;
+            }
+        }
+        Ok(removed)
+    }
+    /// Drops the whole GPS directory, returns whether there was one
+    pub fn redact_gps(&mut self) -> bool {
+        self.remove(IfdKind::Primary, GPS_POINTER).is_some()
+    }
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let order = self.byte_order;
+        let mut out = match order {
+            ByteOrder::Little => b"II".to_vec(),
+            ByteOrder::Big => b"MM".to_vec(),
+        };
+        out.extend(order.put_u16(42));
+        out.extend(order.put_u32(8));
+
+        let next = Writer(order).ifd(&mut out, &self.primary, IfdKind::Primary);
+        if let Some(thumbnail) = &self.thumbnail {
+            align(&mut out);
+            let offset = out.len() as u32;
+            out[next..next + 4].copy_from_slice(&order.put_u32(offset));
+            Writer(order).ifd(&mut out, thumbnail, IfdKind::Thumbnail);
+        }
+        out
+    }
+}
+
+impl TryFrom<&[u8]> for Exif {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        // some writers keep the JPEG APP1 prefix, which is not part of the TIFF structure
+        let value = value.strip_prefix(b"Exif\0\0").unwrap_or(value);
+        ensure!(value.len() >= 8, "invalid EXIF size `{}`", value.len());
+
+        let byte_order = match &value[0..2] {
+            b"II" => ByteOrder::Little,
+            b"MM" => ByteOrder::Big,
+            _ => bail!("invalid EXIF byte order"),
+        };
+        ensure!(byte_order.u16(&value[2..4]) == 42, "invalid TIFF header");
+
+        let mut reader = Reader {
+            data: value,
+            order: byte_order,
+            visited: HashSet::new(),
+        };
+        let (primary, next) = reader.ifd(byte_order.u32(&value[4..8]), IfdKind::Primary)?;
+        let thumbnail = match next {
+            0 => None,
+            offset => Some(reader.ifd(offset, IfdKind::Thumbnail)?.0),
+        };
+
+        Ok(Exif {
+            byte_order,
+            primary,
+            thumbnail,
+        })
+    }
+}
+
+impl fmt::Display for Exif {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (kind, entry) in self.entries() {
+            writeln!(
+                f,
+                "{:<10} 0x{:04x} {:<28} {}",
+                kind,
+                entry.tag,
+                kind.tag_name(entry.tag).unwrap_or("?"),
+                entry.value
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Ifd {
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+    pub fn get(&self, tag: u16) -> Option<&Value> {
+        self.entries
+            .iter()
+            .find(|entry| entry.tag == tag)
+            .map(|entry| &entry.value)
+    }
+    fn sub_ifd(&self, tag: u16) -> Option<&Ifd> {
+        match self.get(tag)? {
+            Value::Ifd(ifd) => Some(ifd),
+            _ => None,
+        }
+    }
+    fn sub_ifd_mut(&mut self, tag: u16) -> Option<&mut Ifd> {
+        match self.entries.iter_mut().find(|entry| entry.tag == tag)? {
+            Entry {
+                value: Value::Ifd(ifd),
+                ..
+            } => Some(ifd),
+            _ => None,
+        }
+    }
+}
+
+impl Entry {
+    pub fn tag(&self) -> u16 {
+        self.tag
+    }
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+}
+
+impl Value {
+    fn field_type(&self) -> u16 {
+        match self {
+            Value::Byte(_) => 1,
+            Value::Ascii(_) => 2,
+            Value::Short(_) => 3,
+            Value::Long(_) | Value::Ifd(_) | Value::Thumbnail(_) => 4,
+            Value::Rational(_) => 5,
+            Value::SByte(_) => 6,
+            Value::Undefined(_) => 7,
+            Value::SShort(_) => 8,
+            Value::SLong(_) => 9,
+            Value::SRational(_) => 10,
+            Value::Float(_) => 11,
+            Value::Double(_) => 12,
+        }
+    }
+    fn count(&self) -> usize {
+        match self {
+            Value::Byte(x) | Value::Ascii(x) | Value::Undefined(x) => x.len(),
+            Value::Short(x) => x.len(),
+            Value::Long(x) => x.len(),
+            Value::Rational(x) => x.len(),
+            Value::SByte(x) => x.len(),
+            Value::SShort(x) => x.len(),
+            Value::SLong(x) => x.len(),
+            Value::SRational(x) => x.len(),
+            Value::Float(x) => x.len(),
+            Value::Double(x) => x.len(),
+            Value::Ifd(_) | Value::Thumbnail(_) => 1,
+        }
+    }
+    fn parse(
+        field_type: u16,
+        count: usize,
+        bytes: &[u8],
+        order: ByteOrder,
+    ) -> anyhow::Result<Value> {
+        let chunks = |size: usize| bytes.chunks_exact(size).take(count);
+        Ok(match field_type {
+            1 => Value::Byte(bytes.to_vec()),
+            2 => Value::Ascii(bytes.to_vec()),
+            3 => Value::Short(chunks(2).map(|x| order.u16(x)).collect()),
+            4 | 13 => Value::Long(chunks(4).map(|x| order.u32(x)).collect()),
+            5 => Value::Rational(
+                chunks(8)
+                    .map(|x| (order.u32(&x[..4]), order.u32(&x[4..])))
+                    .collect(),
+            ),
+            6 => Value::SByte(bytes.iter().map(|&x| x as i8).collect()),
+            7 => Value::Undefined(bytes.to_vec()),
+            8 => Value::SShort(chunks(2).map(|x| order.u16(x) as i16).collect()),
+            9 => Value::SLong(chunks(4).map(|x| order.u32(x) as i32).collect()),
+            10 => Value::SRational(
+                chunks(8)
+                    .map(|x| (order.u32(&x[..4]) as i32, order.u32(&x[4..]) as i32))
+                    .collect(),
+            ),
+            11 => Value::Float(chunks(4).map(|x| f32::from_bits(order.u32(x))).collect()),
+            12 => Value::Double(
+                chunks(8)
+                    .map(|x| {
+                        let (high, low) = match order {
+                            ByteOrder::Big => (order.u32(&x[..4]), order.u32(&x[4..])),
+                            ByteOrder::Little => (order.u32(&x[4..]), order.u32(&x[..4])),
+                        };
+                        f64::from_bits((high as u64) << 32 | low as u64)
+                    })
+                    .collect(),
+            ),
+            other => bail!("unsupported EXIF field type `{}`", other),
+        })
+    }
+    fn field_size(field_type: u16) -> Option<usize> {
+        match field_type {
+            1 | 2 | 6 | 7 => Some(1),
+            3 | 8 => Some(2),
+            4 | 9 | 11 | 13 => Some(4),
+            5 | 10 | 12 => Some(8),
+            _ => None,
+        }
+    }
+    fn encode(&self, order: ByteOrder) -> Vec<u8> {
+        match self {
+            Value::Byte(x) | Value::Ascii(x) | Value::Undefined(x) => x.clone(),
+            Value::Short(x) => x.iter().flat_map(|&x| order.put_u16(x)).collect(),
+            Value::Long(x) => x.iter().flat_map(|&x| order.put_u32(x)).collect(),
+            Value::Rational(x) => x
+                .iter()
+                .flat_map(|&(num, den)| [order.put_u32(num), order.put_u32(den)].concat())
+                .collect(),
+            Value::SByte(x) => x.iter().map(|&x| x as u8).collect(),
+            Value::SShort(x) => x.iter().flat_map(|&x| order.put_u16(x as u16)).collect(),
+            Value::SLong(x) => x.iter().flat_map(|&x| order.put_u32(x as u32)).collect(),
+            Value::SRational(x) => x
+                .iter()
+                .flat_map(|&(num, den)| {
+                    [order.put_u32(num as u32), order.put_u32(den as u32)].concat()
+                })
+                .collect(),
+            Value::Float(x) => x.iter().flat_map(|&x| order.put_u32(x.to_bits())).collect(),
+            Value::Double(x) => x
+                .iter()
+                .flat_map(|&x| {
+                    let bits = x.to_bits();
+                    match order {
+                        ByteOrder::Big => bits.to_be_bytes(),
+                        ByteOrder::Little => bits.to_le_bytes(),
+                    }
+                })
+                .collect(),
+            // patched once the pointed data is written
+            Value::Ifd(_) | Value::Thumbnail(_) => vec![0; 4],
+        }
+    }
+    pub fn as_string(&self) -> Option<String> {
+        match self {
+            Value::Ascii(text) => {
+                let text = text.split(|&x| x == 0).next().unwrap_or_default();
+                Some(String::from_utf8_lossy(text).trim().to_owned())
+            }
+            _ => None,
+        }
+    }
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Value::Byte(x) => x.first().map(|&x| x as u32),
+            Value::Short(x) => x.first().map(|&x| x as u32),
+            Value::Long(x) => x.first().copied(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list<T: fmt::Display>(f: &mut fmt::Formatter<'_>, values: &[T]) -> fmt::Result {
+            const MAX_SHOWN: usize = 8;
+            let shown: Vec<String> = values
+                .iter()
+                .take(MAX_SHOWN)
+                .map(|x| x.to_string())
+                .collect();
+            write!(f, "{}", shown.join(" "))?;
+            if values.len() > MAX_SHOWN {
+                write!(f, " ... ({} values)", values.len())?;
+            }
+            Ok(())
+        }
+        let ratios = |values: &[(i64, i64)]| -> Vec<String> {
+            values
+                .iter()
+                .map(|(num, den)| format!("{}/{}", num, den))
+                .collect()
+        };
+
+        match self {
+            Value::Ascii(_) => write!(f, "{:?}", self.as_string().unwrap_or_default()),
+            Value::Byte(x) | Value::Undefined(x) if x.len() > 16 => {
+                write!(f, "<{} bytes>", x.len())
+            }
+            Value::Byte(x) | Value::Undefined(x) => list(f, x),
+            Value::Short(x) => list(f, x),
+            Value::Long(x) => list(f, x),
+            Value::SByte(x) => list(f, x),
+            Value::SShort(x) => list(f, x),
+            Value::SLong(x) => list(f, x),
+            Value::Float(x) => list(f, x),
+            Value::Double(x) => list(f, x),
+            Value::Rational(x) => list(
+                f,
+                &ratios(
+                    &x.iter()
+                        .map(|&(n, d)| (n as i64, d as i64))
+                        .collect::<Vec<_>>(),
+                ),
+            ),
+            Value::SRational(x) => list(
+                f,
+                &ratios(
+                    &x.iter()
+                        .map(|&(n, d)| (n as i64, d as i64))
+                        .collect::<Vec<_>>(),
+                ),
+            ),
+            Value::Ifd(ifd) => write!(f, "<directory with {} entries>", ifd.entries.len()),
+            Value::Thumbnail(x) => write!(f, "<JPEG thumbnail, {} bytes>", x.len()),
+        }
+    }
+}
+
+impl IfdKind {
+    const ALL: [IfdKind; 5] = [
+        IfdKind::Primary,
+        IfdKind::Exif,
+        IfdKind::Gps,
+        IfdKind::Interop,
+        IfdKind::Thumbnail,
+    ];
+
+    fn tag_names(&self) -> &'static [(u16, &'static str)] {
+        match self {
+            IfdKind::Primary | IfdKind::Exif | IfdKind::Thumbnail => TIFF_TAGS,
+            IfdKind::Gps => GPS_TAGS,
+            IfdKind::Interop => INTEROP_TAGS,
+        }
+    }
+    /// Directory named like in the listing (`IFD0`, `Exif`, `GPS`, `Interop`, `IFD1`)
+    pub fn from_name(name: &str) -> anyhow::Result<IfdKind> {
+        IfdKind::ALL
+            .into_iter()
+            .find(|kind| kind.to_string().eq_ignore_ascii_case(name))
+            .with_context(|| format!("unknown EXIF directory `{}`", name))
+    }
+    pub fn tag_name(&self, tag: u16) -> Option<&'static str> {
+        self.tag_names()
+            .iter()
+            .find(|(number, _)| *number == tag)
+            .map(|(_, name)| *name)
+    }
+    pub fn tag(&self, name: &str) -> Option<u16> {
+        self.tag_names()
+            .iter()
+            .find(|(_, known)| known.eq_ignore_ascii_case(name))
+            .map(|(number, _)| *number)
+    }
+}
+
+impl fmt::Display for IfdKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            IfdKind::Primary => "IFD0",
+            IfdKind::Exif => "Exif",
+            IfdKind::Gps => "GPS",
+            IfdKind::Interop => "Interop",
+            IfdKind::Thumbnail => "IFD1",
+        };
+        // padding is only honoured by `pad`
+        f.pad(name)
+    }
+}
+
+impl ByteOrder {
+    fn u16(self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        match self {
+            ByteOrder::Little => u16::from_le_bytes(bytes),
+            ByteOrder::Big => u16::from_be_bytes(bytes),
+        }
+    }
+    fn u32(self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match self {
+            ByteOrder::Little => u32::from_le_bytes(bytes),
+            ByteOrder::Big => u32::from_be_bytes(bytes),
+        }
+    }
+    fn put_u16(self, value: u16) -> [u8; 2] {
+        match self {
+            ByteOrder::Little => value.to_le_bytes(),
+            ByteOrder::Big => value.to_be_bytes(),
+        }
+    }
+    fn put_u32(self, value: u32) -> [u8; 4] {
+        match self {
+            ByteOrder::Little => value.to_le_bytes(),
+            ByteOrder::Big => value.to_be_bytes(),
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    order: ByteOrder,
+    visited: HashSet<u32>,
+}
+
+impl<'a> Reader<'a> {
+    fn slice(&self, offset: usize, len: usize) -> anyhow::Result<&'a [u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .context("EXIF offset out of bounds")
+    }
+
+    /// Reads the directory at `offset`, returns it along with the offset of the next one
+    fn ifd(&mut self, offset: u32, kind: IfdKind) -> anyhow::Result<(Ifd, u32)> {
+        ensure!(self.visited.insert(offset), "EXIF directories form a loop");
+
+        let offset = offset as usize;
+        let count = self.order.u16(self.slice(offset, 2)?) as usize;
+        let table = self.slice(offset + 2, count * 12 + 4)?;
+
+        let mut raw = Vec::with_capacity(count);
+        for field in table.chunks_exact(12).take(count) {
+            let tag = self.order.u16(&field[0..2]);
+            let field_type = self.order.u16(&field[2..4]);
+            let count = self.order.u32(&field[4..8]) as usize;
+            let size = Value::field_size(field_type)
+                .and_then(|size| size.checked_mul(count))
+                .with_context(|| format!("invalid EXIF entry 0x{:04x}", tag))?;
+            let bytes = match size {
+                0..=4 => &field[8..8 + size],
+                _ => self.slice(self.order.u32(&field[8..12]) as usize, size)?,
+            };
+            raw.push((tag, field_type, count, bytes));
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        for &(tag, field_type, count, bytes) in &raw {
+            let value = Value::parse(field_type, count, bytes, self.order)?;
+            let pointer = value.as_u32().filter(|_| count == 1);
+            let value = match (kind.nested(tag), pointer) {
+                (Some(nested), Some(offset)) => Value::Ifd(self.ifd(offset, nested)?.0),
+                _ if tag == THUMBNAIL_OFFSET => {
+                    let length = raw
+                        .iter()
+                        .find(|(tag, ..)| *tag == THUMBNAIL_LENGTH)
+                        .and_then(|(_, field_type, count, bytes)| {
+                            Value::parse(*field_type, *count, bytes, self.order).ok()
+                        })
+                        .and_then(|length| length.as_u32())
+                        .context("EXIF thumbnail without length")?;
+                    let offset = pointer.context("invalid EXIF thumbnail offset")?;
+                    Value::Thumbnail(self.slice(offset as usize, length as usize)?.to_vec())
+                }
+                _ => value,
+            };
+            entries.push(Entry { tag, value });
+        }
+
+        let next = self.order.u32(&table[count * 12..]);
+        Ok((Ifd { entries }, next))
+    }
+}
+
+impl IfdKind {
+    /// Directory pointed by `tag` when found inside this directory
+    fn nested(&self, tag: u16) -> Option<IfdKind> {
+        match (self, tag) {
+            (IfdKind::Primary, EXIF_POINTER) => Some(IfdKind::Exif),
+            (IfdKind::Primary, GPS_POINTER) => Some(IfdKind::Gps),
+            (IfdKind::Exif, INTEROP_POINTER) => Some(IfdKind::Interop),
+            _ => None,
+        }
+    }
+}
+
+struct Writer(ByteOrder);
+
+impl Writer {
+    /// Appends `ifd` and everything it points to, returns the position of its next-IFD field
+    fn ifd(&self, out: &mut Vec<u8>, ifd: &Ifd, kind: IfdKind) -> usize {
+        let order = self.0;
+        let mut entries: Vec<&Entry> = ifd.entries.iter().collect();
+        entries.sort_by_key(|entry| entry.tag);
+
+        align(out);
+        let start = out.len();
+        out.extend(order.put_u16(entries.len() as u16));
+        out.resize(start + 2 + entries.len() * 12 + 4, 0);
+        let next = start + 2 + entries.len() * 12;
+
+        for (i, entry) in entries.iter().enumerate() {
+            let field = start + 2 + i * 12;
+            let bytes = entry.value.encode(order);
+            out[field..field + 2].copy_from_slice(&order.put_u16(entry.tag));
+            out[field + 2..field + 4].copy_from_slice(&order.put_u16(entry.value.field_type()));
+            out[field + 4..field + 8].copy_from_slice(&order.put_u32(entry.value.count() as u32));
+
+            let pointed = match &entry.value {
+                Value::Ifd(nested) => {
+                    align(out);
+                    let offset = out.len() as u32;
+                    self.ifd(out, nested, kind.nested(entry.tag).unwrap_or(kind));
+                    Some(offset)
+                }
+                Value::Thumbnail(data) => {
+                    align(out);
+                    let offset = out.len() as u32;
+                    out.extend(data);
+                    Some(offset)
+                }
+                _ if bytes.len() > 4 => {
+                    align(out);
+                    let offset = out.len() as u32;
+                    out.extend(&bytes);
+                    Some(offset)
+                }
+                _ => None,
+            };
+            match pointed {
+                Some(offset) => out[field + 8..field + 12].copy_from_slice(&order.put_u32(offset)),
+                None => out[field + 8..field + 8 + bytes.len()].copy_from_slice(&bytes),
+            }
+        }
+        next
+    }
+}
+
+/// TIFF offsets must be even
+fn align(out: &mut Vec<u8>) {
+    if out.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+#[rustfmt::skip]
+const TIFF_TAGS: &[(u16, &str)] = &[
+    (0x00FE, "NewSubfileType"), (0x0100, "ImageWidth"), (0x0101, "ImageLength"),
+    (0x0102, "BitsPerSample"), (0x0103, "Compression"), (0x0106, "PhotometricInterpretation"),
+    (0x010E, "ImageDescription"), (0x010F, "Make"), (0x0110, "Model"),
+    (0x0111, "StripOffsets"), (0x0112, "Orientation"), (0x0115, "SamplesPerPixel"),
+    (0x011A, "XResolution"), (0x011B, "YResolution"), (0x0128, "ResolutionUnit"),
+    (0x0131, "Software"), (0x0132, "DateTime"), (0x013B, "Artist"),
+    (0x013E, "WhitePoint"), (0x013F, "PrimaryChromaticities"),
+    (0x0201, "JPEGInterchangeFormat"), (0x0202, "JPEGInterchangeFormatLength"),
+    (0x0211, "YCbCrCoefficients"), (0x0213, "YCbCrPositioning"),
+    (0x0214, "ReferenceBlackWhite"), (0x8298, "Copyright"),
+    (0x829A, "ExposureTime"), (0x829D, "FNumber"), (0x8769, "ExifIFDPointer"),
+    (0x8822, "ExposureProgram"), (0x8825, "GPSInfoIFDPointer"), (0x8827, "ISOSpeedRatings"),
+    (0x9000, "ExifVersion"), (0x9003, "DateTimeOriginal"), (0x9004, "DateTimeDigitized"),
+    (0x9010, "OffsetTime"), (0x9011, "OffsetTimeOriginal"), (0x9012, "OffsetTimeDigitized"),
+    (0x9101, "ComponentsConfiguration"), (0x9201, "ShutterSpeedValue"),
+    (0x9202, "ApertureValue"), (0x9204, "ExposureBiasValue"), (0x9207, "MeteringMode"),
+    (0x9209, "Flash"), (0x920A, "FocalLength"), (0x927C, "MakerNote"),
+    (0x9286, "UserComment"), (0x9290, "SubSecTime"), (0x9291, "SubSecTimeOriginal"),
+    (0x9292, "SubSecTimeDigitized"), (0xA000, "FlashpixVersion"), (0xA001, "ColorSpace"),
+    (0xA002, "PixelXDimension"), (0xA003, "PixelYDimension"),
+    (0xA005, "InteroperabilityIFDPointer"), (0xA402, "ExposureMode"),
+    (0xA403, "WhiteBalance"), (0xA405, "FocalLengthIn35mmFilm"),
+    (0xA406, "SceneCaptureType"), (0xA420, "ImageUniqueID"), (0xA430, "CameraOwnerName"),
+    (0xA431, "BodySerialNumber"), (0xA432, "LensSpecification"), (0xA433, "LensMake"),
+    (0xA434, "LensModel"), (0xA435, "LensSerialNumber"),
+];
+
+#[rustfmt::skip]
+const GPS_TAGS: &[(u16, &str)] = &[
+    (0x0000, "GPSVersionID"), (0x0001, "GPSLatitudeRef"), (0x0002, "GPSLatitude"),
+    (0x0003, "GPSLongitudeRef"), (0x0004, "GPSLongitude"), (0x0005, "GPSAltitudeRef"),
+    (0x0006, "GPSAltitude"), (0x0007, "GPSTimeStamp"), (0x0008, "GPSSatellites"),
+    (0x0009, "GPSStatus"), (0x000A, "GPSMeasureMode"), (0x000B, "GPSDOP"),
+    (0x000C, "GPSSpeedRef"), (0x000D, "GPSSpeed"), (0x000E, "GPSTrackRef"),
+    (0x000F, "GPSTrack"), (0x0010, "GPSImgDirectionRef"), (0x0011, "GPSImgDirection"),
+    (0x0012, "GPSMapDatum"), (0x001B, "GPSProcessingMethod"), (0x001D, "GPSDateStamp"),
+];
+
+#[rustfmt::skip]
+const INTEROP_TAGS: &[(u16, &str)] = &[
+    (0x0001, "InteroperabilityIndex"), (0x0002, "InteroperabilityVersion"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_exif() -> Exif {
+        let gps = Ifd {
+            entries: vec![
+                Entry {
+                    tag: 0x0001,
+                    value: Value::Ascii(b"S\0".to_vec()),
+                },
+                Entry {
+                    tag: 0x0002,
+                    value: Value::Rational(vec![(23, 1), (30, 1), (36, 1)]),
+                },
+                Entry {
+                    tag: 0x0003,
+                    value: Value::Ascii(b"W\0".to_vec()),
+                },
+                Entry {
+                    tag: 0x0004,
+                    value: Value::Rational(vec![(46, 1), (37, 1), (48, 1)]),
+                },
+                Entry {
+                    tag: 0x0006,
+                    value: Value::Rational(vec![(760, 1)]),
+                },
+            ],
+        };
+        let exif = Ifd {
+            entries: vec![
+                Entry {
+                    tag: 0x9003,
+                    value: Value::Ascii(b"2022:08:01 10:00:00\0".to_vec()),
+                },
+                Entry {
+                    tag: 0xA431,
+                    value: Value::Ascii(b"SN-0042\0".to_vec()),
+                },
+            ],
+        };
+        let primary = Ifd {
+            entries: vec![
+                Entry {
+                    tag: 0x010F,
+                    value: Value::Ascii(b"Canon\0".to_vec()),
+                },
+                Entry {
+                    tag: 0x0110,
+                    value: Value::Ascii(b"EOS 5D\0".to_vec()),
+                },
+                Entry {
+                    tag: 0x0112,
+                    value: Value::Short(vec![6]),
+                },
+                Entry {
+                    tag: EXIF_POINTER,
+                    value: Value::Ifd(exif),
+                },
+                Entry {
+                    tag: GPS_POINTER,
+                    value: Value::Ifd(gps),
+                },
+            ],
+        };
+        let thumbnail = Ifd {
+            entries: vec![
+                Entry {
+                    tag: THUMBNAIL_OFFSET,
+                    value: Value::Thumbnail(vec![0xFF, 0xD8, 0xFF, 0xD9, 7]),
+                },
+                Entry {
+                    tag: THUMBNAIL_LENGTH,
+                    value: Value::Long(vec![5]),
+                },
+            ],
+        };
+
+        Exif {
+            byte_order: ByteOrder::Big,
+            primary,
+            thumbnail: Some(thumbnail),
+        }
+    }
+
+    #[test]
+    fn test_exif_round_trip() {
+        for byte_order in [ByteOrder::Big, ByteOrder::Little] {
+            let exif = Exif {
+                byte_order,
+                ..testing_exif()
+            };
+            let parsed = Exif::try_from(exif.as_bytes().as_slice()).unwrap();
+            assert_eq!(parsed, exif);
+            assert_eq!(parsed.as_bytes(), exif.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_exif_getters() {
+        let exif = testing_exif();
+        assert_eq!(exif.make().as_deref(), Some("Canon"));
+        assert_eq!(exif.model().as_deref(), Some("EOS 5D"));
+        assert_eq!(exif.orientation(), Some(6));
+        assert_eq!(exif.serial_number().as_deref(), Some("SN-0042"));
+        assert_eq!(
+            exif.date_time_original().as_deref(),
+            Some("2022:08:01 10:00:00")
+        );
+
+        let gps = exif.gps().unwrap();
+        assert!((gps.latitude + 23.51).abs() < 1e-9);
+        assert!((gps.longitude + 46.63).abs() < 1e-9);
+        assert_eq!(gps.altitude, Some(760.0));
+    }
+
+    #[test]
+    fn test_exif_with_app1_prefix() {
+        let bytes = [b"Exif\0\0".as_slice(), &testing_exif().as_bytes()].concat();
+        assert_eq!(Exif::try_from(bytes.as_slice()).unwrap(), testing_exif());
+    }
+
+    #[test]
+    fn test_remove_tag() {
+        let mut exif = testing_exif();
+        assert_eq!(exif.remove_tag("BodySerialNumber").unwrap(), 1);
+        assert_eq!(exif.remove_tag("0x0110").unwrap(), 1);
+        assert_eq!(exif.remove_tag("Model").unwrap(), 0);
+        assert!(exif.remove_tag("NotATag").is_err());
+
+        let parsed = Exif::try_from(exif.as_bytes().as_slice()).unwrap();
+        assert_eq!(parsed.serial_number(), None);
+        assert_eq!(parsed.model(), None);
+        assert_eq!(parsed.make().as_deref(), Some("Canon"));
+    }
+
+    #[test]
+    fn test_remove_scoped_tag() {
+        let mut exif = testing_exif();
+        // 0x0002 is GPSLatitude, only removed when the GPS directory is named
+        assert_eq!(exif.remove_tag("0x0002").unwrap(), 0);
+        assert_eq!(exif.remove_tag("GPS:0x0002").unwrap(), 1);
+        assert_eq!(exif.remove_tag("Exif:Model").unwrap(), 0);
+        assert!(exif.remove_tag("Nowhere:Model").is_err());
+        assert!(exif.get(IfdKind::Gps, 0x0004).is_some());
+
+        // the thumbnail offset is useless without its length
+        assert_eq!(exif.remove_tag("IFD1:0x0202").unwrap(), 1);
+        let parsed = Exif::try_from(exif.as_bytes().as_slice()).unwrap();
+        assert_eq!(parsed.get(IfdKind::Thumbnail, THUMBNAIL_OFFSET), None);
+        assert_eq!(parsed.make().as_deref(), Some("Canon"));
+    }
+
+    #[test]
+    fn test_redact_gps() {
+        let mut exif = testing_exif();
+        assert!(exif.redact_gps());
+        assert!(!exif.redact_gps());
+
+        let parsed = Exif::try_from(exif.as_bytes().as_slice()).unwrap();
+        assert_eq!(parsed.gps(), None);
+        assert!(parsed.ifd(IfdKind::Gps).is_none());
+        assert_eq!(parsed.orientation(), Some(6));
+    }
+
+    #[test]
+    fn test_invalid_exif() {
+        assert!(Exif::try_from(&b"XX\0*\0\0\0\x08"[..]).is_err());
+
+        // IFD0 pointing at itself through the Exif pointer
+        let mut bytes = b"MM\0*\0\0\0\x08\0\x01".to_vec();
+        bytes.extend([0x87, 0x69, 0, 4, 0, 0, 0, 1, 0, 0, 0, 8, 0, 0, 0, 0]);
+        assert!(Exif::try_from(bytes.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use anyhow::bail;
+
+/// Reverses the filter `filter_type` applied to `row` in place, `previous` is the already
+/// unfiltered row above it (all zeros for the first row)
+pub fn unfilter(
+    filter_type: u8,
+    row: &mut [u8],
+    previous: &[u8],
+    distance: usize,
+) -> anyhow::Result<()> {
+    match filter_type {
+        0 => {}
+        1 => {
+            for i in distance..row.len() {
+                row[i] = row[i].wrapping_add(row[i - distance]);
+            }
+        }
+        2 => {
+            for (x, &up) in row.iter_mut().zip(previous) {
+                *x = x.wrapping_add(up);
+            }
+        }
+        3 => {
+            for i in 0..row.len() {
+                let left = if i >= distance { row[i - distance] } else { 0 };
+                let average = ((left as u16 + previous[i] as u16) / 2) as u8;
+                row[i] = row[i].wrapping_add(average);
+            }
+        }
+        4 => {
+            for i in 0..row.len() {
+                let (left, upper_left) = if i >= distance {
+                    (row[i - distance], previous[i - distance])
+                } else {
+                    (0, 0)
+                };
+                row[i] = row[i].wrapping_add(paeth(left, previous[i], upper_left));
+            }
+        }
+        _ => bail!("invalid filter type `{}`", filter_type),
+    }
+    Ok(())
+}
+
+/// Applies the filter `filter_type` to `row`, the opposite of `unfilter`
+pub fn filter(
+    filter_type: u8,
+    row: &[u8],
+    previous: &[u8],
+    distance: usize,
+) -> anyhow::Result<Vec<u8>> {
+    let left = |i: usize| if i >= distance { row[i - distance] } else { 0 };
+    let upper_left = |i: usize| {
+        if i >= distance {
+            previous[i - distance]
+        } else {
+            0
+        }
+    };
+    let predictor: Box<dyn Fn(usize) -> u8> = match filter_type {
+        0 => Box::new(|_| 0),
+        1 => Box::new(left),
+        2 => Box::new(|i| previous[i]),
+        3 => Box::new(|i| ((left(i) as u16 + previous[i] as u16) / 2) as u8),
+        4 => Box::new(|i| paeth(left(i), previous[i], upper_left(i))),
+        _ => bail!("invalid filter type `{}`", filter_type),
+    };
+    Ok(row
+        .iter()
+        .enumerate()
+        .map(|(i, x)| x.wrapping_sub(predictor(i)))
+        .collect())
+}
+
+/// Filters `row` with whichever of `filter_types` gives the smallest sum of the filtered bytes
+/// taken as signed, the usual guess at which filter compresses best
+pub fn filter_best(
+    filter_types: impl IntoIterator<Item = u8>,
+    row: &[u8],
+    previous: &[u8],
+    distance: usize,
+) -> anyhow::Result<(u8, Vec<u8>)> {
+    let cost = |filtered: &[u8]| -> u64 {
+        filtered
+            .iter()
+            .map(|&x| (x as i8).unsigned_abs() as u64)
+            .sum()
+    };
+
+    let mut best: Option<(u8, Vec<u8>)> = None;
+    for filter_type in filter_types {
+        let filtered = filter(filter_type, row, previous, distance)?;
+        if best.as_ref().is_none_or(|(_, x)| cost(&filtered) < cost(x)) {
+            best = Some((filter_type, filtered));
+        }
+    }
+    best.ok_or_else(|| anyhow::anyhow!("no filter type to choose from"))
+}
+
+fn paeth(left: u8, up: u8, upper_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - upper_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_up = (estimate - up as i16).abs();
+    let distance_upper_left = (estimate - upper_left as i16).abs();
+
+    if distance_left <= distance_up && distance_left <= distance_upper_left {
+        left
+    } else if distance_up <= distance_upper_left {
+        up
+    } else {
+        upper_left
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unfilter() {
+        let previous = [10, 20, 30, 40];
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(1, &mut row, &previous, 2).unwrap();
+        assert_eq!(row, [1, 2, 4, 6]);
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(2, &mut row, &previous, 2).unwrap();
+        assert_eq!(row, [11, 22, 33, 44]);
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(3, &mut row, &previous, 2).unwrap();
+        assert_eq!(row, [6, 12, 21, 30]);
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(4, &mut row, &previous, 2).unwrap();
+        assert_eq!(row, [11, 22, 33, 44]);
+
+        assert!(unfilter(5, &mut row, &previous, 2).is_err());
+    }
+
+    #[test]
+    fn test_filter_round_trip() {
+        let previous = [10, 20, 30, 40, 250, 3];
+        let row = [1, 200, 3, 4, 255, 0];
+        for filter_type in 0..5 {
+            let mut filtered = filter(filter_type, &row, &previous, 2).unwrap();
+            unfilter(filter_type, &mut filtered, &previous, 2).unwrap();
+            assert_eq!(filtered, row);
+        }
+        assert!(filter(5, &row, &previous, 2).is_err());
+    }
+
+    #[test]
+    fn test_filter_best() {
+        let previous = [10, 20, 30, 40];
+        let (filter_type, filtered) = filter_best(0..5, &[10, 20, 30, 40], &previous, 2).unwrap();
+        assert_eq!((filter_type, filtered), (2, vec![0; 4]));
+        assert_eq!(filter_best([0], &[1, 2], &previous, 2).unwrap().0, 0);
+        assert!(filter_best([], &[1, 2], &previous, 2).is_err());
+    }
+
+    #[test]
+    fn test_paeth() {
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(10, 10, 20), 10);
+        assert_eq!(paeth(0, 0, 0), 0);
+    }
+}
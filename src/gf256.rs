@@ -0,0 +1,113 @@
+//! Arithmetic in GF(2^8) with the 0x11d reducing polynomial, the field used by QR codes and most
+//! Reed-Solomon implementations
+
+const POLYNOMIAL: u16 = 0x11d;
+
+/// Powers of the generator 2, repeated twice so products of logarithms never need reducing
+const EXP: [u8; 512] = build_exp();
+const LOG: [u8; 256] = build_log();
+
+const fn build_exp() -> [u8; 512] {
+    let mut table = [0; 512];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        table[i] = x as u8;
+        table[i + 255] = x as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= POLYNOMIAL;
+        }
+        i += 1;
+    }
+    table
+}
+
+const fn build_log() -> [u8; 256] {
+    let exp = build_exp();
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 255 {
+        table[exp[i] as usize] = i as u8;
+        i += 1;
+    }
+    table
+}
+
+/// `2^power`, the generator raised to `power`
+pub fn exp(power: usize) -> u8 {
+    EXP[power % 255]
+}
+
+pub fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    EXP[LOG[a as usize] as usize + LOG[b as usize] as usize]
+}
+
+/// Panics when `b` is zero
+pub fn div(a: u8, b: u8) -> u8 {
+    assert!(b != 0, "division by zero in GF(256)");
+    if a == 0 {
+        return 0;
+    }
+    EXP[LOG[a as usize] as usize + 255 - LOG[b as usize] as usize]
+}
+
+pub fn inv(a: u8) -> u8 {
+    div(1, a)
+}
+
+/// Evaluates `poly`, highest degree coefficient first, at `x`
+pub fn eval(poly: &[u8], x: u8) -> u8 {
+    poly.iter().fold(0, |acc, &coef| mul(acc, x) ^ coef)
+}
+
+/// Product of two polynomials, highest degree coefficients first
+pub fn poly_mul(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; a.len() + b.len() - 1];
+    for (i, &x) in a.iter().enumerate() {
+        for (j, &y) in b.iter().enumerate() {
+            result[i + j] ^= mul(x, y);
+        }
+    }
+    result
+}
+
+/// Sum of two polynomials, highest degree coefficients first
+pub fn poly_add(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let len = a.len().max(b.len());
+    let mut result = vec![0; len];
+    for (i, &x) in a.iter().enumerate() {
+        result[i + len - a.len()] = x;
+    }
+    for (i, &y) in b.iter().enumerate() {
+        result[i + len - b.len()] ^= y;
+    }
+    result
+}
+
+pub fn poly_scale(poly: &[u8], x: u8) -> Vec<u8> {
+    poly.iter().map(|&coef| mul(coef, x)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_arithmetic() {
+        assert_eq!(exp(8), 0x1d);
+        assert_eq!(mul(0x80, 2), 0x1d);
+        assert_eq!(mul(3, 7), 9);
+        for a in 1..=255 {
+            assert_eq!(mul(a, inv(a)), 1);
+            assert_eq!(div(mul(a, 0x37), 0x37), a);
+        }
+        assert_eq!(mul(0, 7), 0);
+        assert_eq!(eval(&[1, 0, 1], 2), mul(2, 2) ^ 1);
+        assert_eq!(poly_mul(&[1, 1], &[1, 1]), [1, 0, 1]);
+        assert_eq!(poly_add(&[1, 0, 0], &[1, 1]), [1, 1, 1]);
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::{bail, ensure};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale = 0,
+    Rgb = 2,
+    Indexed = 3,
+    GrayscaleAlpha = 4,
+    Rgba = 6,
+}
+
+impl ColorType {
+    pub fn channels(&self) -> usize {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+    pub fn bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+    pub fn has_alpha(&self) -> bool {
+        matches!(self, ColorType::GrayscaleAlpha | ColorType::Rgba)
+    }
+    /// Name of each sample in a pixel
+    pub fn channel_names(&self) -> &'static [&'static str] {
+        match self {
+            ColorType::Grayscale => &["gray"],
+            ColorType::Indexed => &["index"],
+            ColorType::GrayscaleAlpha => &["gray", "alpha"],
+            ColorType::Rgb => &["red", "green", "blue"],
+            ColorType::Rgba => &["red", "green", "blue", "alpha"],
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => ColorType::Grayscale,
+            2 => ColorType::Rgb,
+            3 => ColorType::Indexed,
+            4 => ColorType::GrayscaleAlpha,
+            6 => ColorType::Rgba,
+            _ => bail!("invalid color type `{}`", value),
+        })
+    }
+}
+
+impl fmt::Display for ColorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale+alpha",
+            ColorType::Rgba => "RGBA",
+        };
+        f.pad(name)
+    }
+}
+
+/// Contents of the `IHDR` chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub interlaced: bool,
+}
+
+impl Ihdr {
+    pub fn new(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: ColorType,
+    ) -> anyhow::Result<Ihdr> {
+        let ihdr = Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced: false,
+        };
+        ihdr.validate()?;
+        Ok(ihdr)
+    }
+    fn validate(&self) -> anyhow::Result<()> {
+        ensure!(
+            self.width > 0 && self.height > 0,
+            "image dimensions must not be zero"
+        );
+        ensure!(
+            self.width <= 1 << 31 && self.height <= 1 << 31,
+            "image dimensions are greater than 2 ^ 31"
+        );
+        ensure!(
+            self.color_type.bit_depths().contains(&self.bit_depth),
+            "invalid bit depth `{}` for {} images",
+            self.bit_depth,
+            self.color_type
+        );
+        Ok(())
+    }
+    pub fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+    /// Distance in bytes between a byte and the matching one of the previous pixel, used by filters
+    pub fn filter_distance(&self) -> usize {
+        (self.bits_per_pixel() / 8).max(1)
+    }
+    /// Bytes in a scanline `width` pixels wide, not counting its filter type byte
+    pub fn row_bytes(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel()).div_ceil(8)
+    }
+    pub fn as_bytes(&self) -> Vec<u8> {
+        [
+            &self.width.to_be_bytes()[..],
+            &self.height.to_be_bytes(),
+            &[
+                self.bit_depth,
+                self.color_type as u8,
+                0,
+                0,
+                self.interlaced as u8,
+            ],
+        ]
+        .concat()
+    }
+}
+
+impl TryFrom<&[u8]> for Ihdr {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        ensure!(value.len() == 13, "invalid IHDR size `{}`", value.len());
+        ensure!(value[10] == 0, "unknown compression method `{}`", value[10]);
+        ensure!(value[11] == 0, "unknown filter method `{}`", value[11]);
+        ensure!(value[12] <= 1, "unknown interlace method `{}`", value[12]);
+
+        let ihdr = Ihdr {
+            width: u32::from_be_bytes(value[0..4].try_into()?),
+            height: u32::from_be_bytes(value[4..8].try_into()?),
+            bit_depth: value[8],
+            color_type: value[9].try_into()?,
+            interlaced: value[12] == 1,
+        };
+        ihdr.validate()?;
+        Ok(ihdr)
+    }
+}
+
+impl fmt::Display for Ihdr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} {} {}-bit{}",
+            self.width,
+            self.height,
+            self.color_type,
+            self.bit_depth,
+            if self.interlaced { " interlaced" } else { "" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ihdr_from_bytes() {
+        let ihdr = Ihdr::try_from(&[0, 0, 0, 50, 0, 0, 0, 40, 8, 6, 0, 0, 0][..]).unwrap();
+        assert_eq!(ihdr.width, 50);
+        assert_eq!(ihdr.height, 40);
+        assert_eq!(ihdr.color_type, ColorType::Rgba);
+        assert_eq!(ihdr.row_bytes(ihdr.width), 200);
+        assert_eq!(ihdr.filter_distance(), 4);
+        assert_eq!(ihdr.as_bytes(), [0, 0, 0, 50, 0, 0, 0, 40, 8, 6, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_packed_rows() {
+        let ihdr = Ihdr::new(10, 1, 2, ColorType::Indexed).unwrap();
+        assert_eq!(ihdr.row_bytes(10), 3);
+        assert_eq!(ihdr.filter_distance(), 1);
+    }
+
+    #[test]
+    fn test_invalid_ihdr() {
+        assert!(Ihdr::new(0, 1, 8, ColorType::Rgb).is_err());
+        assert!(Ihdr::new(1, 1, 4, ColorType::Rgb).is_err());
+        assert!(Ihdr::new(1, 1, 16, ColorType::Indexed).is_err());
+        assert!(Ihdr::try_from(&[0, 0, 0, 1, 0, 0, 0, 1, 8, 5, 0, 0, 0][..]).is_err());
+        assert!(Ihdr::try_from(&[0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 2][..]).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! Rules of the specification on which chunks a png has and in what order, checked after chunks
+//! are moved, renamed, copied or removed by hand.
+
+use crate::{apng, color::ColorInfo, palette::PaletteChunks, png::Png};
+use std::collections::HashSet;
+
+const CRITICAL_CHUNKS: &[&[u8; 4]] = &[b"IHDR", b"PLTE", b"IDAT", b"IEND"];
+/// Chunks allowed at most once
+const UNIQUE_CHUNKS: &[&[u8; 4]] = &[
+    b"IHDR", b"PLTE", b"IEND", b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB", b"cICP", b"mDCV",
+    b"cLLI", b"tRNS", b"bKGD", b"hIST", b"pHYs", b"oFFs", b"pCAL", b"sCAL", b"tIME", b"eXIf",
+    b"acTL",
+];
+/// Chunks that must come before `IDAT`, besides the color chunks
+const BEFORE_DATA: &[&[u8; 4]] = &[
+    b"sBIT", b"tRNS", b"bKGD", b"hIST", b"pHYs", b"sPLT", b"oFFs", b"pCAL", b"sCAL",
+];
+
+/// Every rule of the specification the chunks of `png` break, including the ones on color,
+/// palette and animation chunks
+pub fn problems(png: &Png) -> Vec<String> {
+    let chunks = png.chunks();
+    let types: Vec<[u8; 4]> = chunks.iter().map(|x| x.chunk_type().bytes()).collect();
+    let first = |chunk_type: &[u8; 4]| types.iter().position(|x| x == chunk_type);
+    let mut problems = vec![];
+
+    if first(b"IHDR") != Some(0) {
+        problems.push("IHDR must be the first chunk".to_string());
+    }
+    if types.last() != Some(b"IEND") {
+        problems.push("IEND must be the last chunk".to_string());
+    }
+    for chunk_type in UNIQUE_CHUNKS {
+        if types.iter().filter(|x| x == chunk_type).count() > 1 {
+            problems.push(format!(
+                "more than one {} chunk",
+                String::from_utf8_lossy(*chunk_type)
+            ));
+        }
+    }
+    for chunk in chunks {
+        let chunk_type = chunk.chunk_type();
+        if !chunk_type.is_valid() {
+            problems.push(format!("{} has the reserved bit set", chunk_type));
+        } else if chunk_type.is_critical() && !CRITICAL_CHUNKS.contains(&&chunk_type.bytes()) {
+            problems.push(format!(
+                "{} is an unknown critical chunk, decoders will refuse the image",
+                chunk_type
+            ));
+        }
+    }
+
+    let data = first(b"IDAT");
+    match data {
+        None => problems.push("there is no IDAT chunk".to_string()),
+        Some(data) => {
+            let count = types.iter().filter(|x| *x == b"IDAT").count();
+            if types[data..data + count].iter().any(|x| x != b"IDAT") {
+                problems.push("IDAT chunks must be consecutive".to_string());
+            }
+        }
+    }
+    let data = data.unwrap_or(types.len());
+    let palette = first(b"PLTE");
+    if palette.is_some_and(|x| x > data) {
+        problems.push("PLTE must come before IDAT".to_string());
+    }
+    for (i, chunk_type) in types.iter().enumerate() {
+        if BEFORE_DATA.contains(&chunk_type) && i > data {
+            problems.push(format!(
+                "{} must come before IDAT",
+                String::from_utf8_lossy(chunk_type)
+            ));
+        }
+        if chunk_type == b"sBIT" && palette.is_some_and(|x| i > x) {
+            problems.push("sBIT must come before PLTE".to_string());
+        }
+    }
+
+    match png.ihdr() {
+        Ok(ihdr) => {
+            match ColorInfo::from_chunks(chunks) {
+                Ok(color) => problems.extend(color.problems(chunks, &ihdr)),
+                Err(error) => problems.push(error.to_string()),
+            }
+            match PaletteChunks::from_chunks(chunks, &ihdr) {
+                Ok(palette) => problems.extend(palette.problems(chunks, &ihdr)),
+                Err(error) => problems.push(error.to_string()),
+            }
+        }
+        Err(error) => problems.push(error.to_string()),
+    }
+    problems.extend(apng::problems(png));
+
+    // the checks on color and palette chunks repeat some of the ones above
+    let mut seen = HashSet::new();
+    problems.retain(|x| seen.insert(x.clone()));
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    fn testing_png(types: &[&str]) -> Png {
+        let ihdr = [0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0];
+        Png::from_chunks(
+            types
+                .iter()
+                .map(|x| {
+                    let data = match *x {
+                        "IHDR" => ihdr.to_vec(),
+                        "pHYs" => vec![0; 9],
+                        _ => vec![],
+                    };
+                    Chunk::new((*x).try_into().unwrap(), data)
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_valid_layout() {
+        let png = testing_png(&["IHDR", "pHYs", "IDAT", "IDAT", "tEXt", "IEND"]);
+        assert!(problems(&png).is_empty());
+    }
+
+    #[test]
+    fn test_broken_layout() {
+        let png = testing_png(&[
+            "pHYs", "IHDR", "IDAT", "tEXt", "IDAT", "pHYs", "IEND", "ABCD",
+        ]);
+        let problems = problems(&png);
+        for expected in [
+            "IHDR must be the first chunk",
+            "IEND must be the last chunk",
+            "more than one pHYs chunk",
+            "ABCD is an unknown critical chunk, decoders will refuse the image",
+            "IDAT chunks must be consecutive",
+            "pHYs must come before IDAT",
+        ] {
+            assert!(problems.iter().any(|x| x == expected), "{}", expected);
+        }
+    }
+}
@@ -1,11 +1,37 @@
 #![allow(dead_code, unused_variables)]
 
 use cli::Cli;
+mod apng;
+mod carve;
 mod chunk;
 mod chunk_type;
 mod cli;
+mod color;
 mod commands;
+mod decoder;
+mod decoy;
+mod encoder;
+mod encryption;
+mod envelope;
+mod exif;
+mod filter;
+mod gf256;
+mod ihdr;
+mod layout;
+mod optimize;
+mod padding;
+mod palette;
+mod passphrase;
+mod physical;
 mod png;
+mod polyglot;
+mod reed_solomon;
+mod scan;
+mod shamir;
+mod signature;
+mod steganalysis;
+mod stego;
+mod zlib;
 
 fn main() -> Result<(), anyhow::Error> {
     Cli::run()
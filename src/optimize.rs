@@ -0,0 +1,380 @@
+//! Lossless size reduction: every pixel is stored in the smallest color type and bit depth that
+//! holds it exactly, the image data is compressed harder, and the result is decoded again to
+//! make sure nothing changed.
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    decoder::{self, Color, Image},
+    encoder::{self, FilterStrategy},
+    ihdr::{ColorType, Ihdr},
+    png::Png,
+};
+use anyhow::ensure;
+use std::collections::HashMap;
+
+/// Chunks describing the image rather than how it looks, removed when stripping
+const METADATA_CHUNKS: &[&[u8; 4]] = &[
+    b"tEXt", b"zTXt", b"iTXt", b"tIME", b"pHYs", b"eXIf", b"bKGD", b"hIST", b"sPLT", b"oFFs",
+    b"pCAL", b"sCAL", b"gIFg", b"gIFx", b"dSIG",
+];
+/// Chunks whose contents depend on the color type or the palette
+const COLOR_DEPENDENT_CHUNKS: &[&[u8; 4]] = &[b"bKGD", b"hIST", b"sBIT"];
+/// Chunks that must come after `PLTE`
+const AFTER_PALETTE_CHUNKS: &[&[u8; 4]] = &[b"bKGD", b"hIST"];
+
+#[derive(Debug)]
+pub struct Optimized {
+    pub png: Png,
+    pub before: Ihdr,
+    pub after: Ihdr,
+    /// chunks removed because they only hold metadata
+    pub stripped: Vec<ChunkType>,
+    /// chunks removed because they no longer match the new color type or palette
+    pub dropped: Vec<ChunkType>,
+}
+
+/// Encoding of the pixels that may be smaller than the current one
+struct Candidate {
+    ihdr: Ihdr,
+    /// `PLTE` and `tRNS`
+    palette: Vec<Chunk>,
+    data: Vec<u8>,
+}
+
+/// Re-encodes `png` as small as it gets at zlib `level`, keeping every other chunk but the
+/// metadata ones when `strip` is set
+pub fn optimize(png: &Png, level: u8, strip: bool) -> anyhow::Result<Optimized> {
+    let before = png.ihdr()?;
+    let image = decoder::decode(png)?;
+    let pixels = image.rgba16()?;
+
+    // frames of animated images share the color type of the first one, and its interlacing, as
+    // the fdAT frames are only copied over
+    let animated = png.chunk_by_type("acTL").is_some();
+    let mut images = vec![image.clone()];
+    match (animated, before.interlaced) {
+        (false, _) => images.extend(reductions(&pixels, &before)?),
+        (true, true) => images.clear(),
+        (true, false) => {}
+    }
+    // an ICC profile only describes either gray or color images
+    if let Some(icc) = png.color()?.icc {
+        images.retain(|x| icc.check_color_type(x.ihdr().color_type).is_ok());
+    }
+
+    let original = Candidate {
+        ihdr: before,
+        palette: png
+            .chunks()
+            .iter()
+            .filter(|x| matches!(&x.chunk_type().bytes(), b"PLTE" | b"tRNS"))
+            .cloned()
+            .collect(),
+        data: png.image_data(),
+    };
+    let mut best = original;
+    for image in &images {
+        let encoded = encoder::encode(image, &Default::default())?;
+        let palette: Vec<Chunk> = encoded
+            .chunks()
+            .iter()
+            .filter(|x| matches!(&x.chunk_type().bytes(), b"PLTE" | b"tRNS"))
+            .cloned()
+            .collect();
+        for filter in [
+            FilterStrategy::Fixed(0),
+            FilterStrategy::Heuristic,
+            FilterStrategy::BruteForce,
+        ] {
+            let options = encoder::Options {
+                filter,
+                level,
+                ..Default::default()
+            };
+            let data = encoder::image_data(image, &options)?;
+            let size = |x: &Candidate| {
+                x.data.len() + x.palette.iter().map(|x| x.data().len() + 12).sum::<usize>()
+            };
+            let candidate = Candidate {
+                ihdr: encoded.ihdr()?,
+                palette: palette.clone(),
+                data,
+            };
+            // interlaced images always get written in plain order, even when bigger
+            if size(&candidate) < size(&best) || best.ihdr.interlaced {
+                best = candidate;
+            }
+        }
+    }
+
+    let colors_changed = best.ihdr.color_type != before.color_type
+        || best.ihdr.bit_depth != before.bit_depth
+        || png.chunk_by_type("PLTE").map(|x| x.data())
+            != best
+                .palette
+                .first()
+                .filter(|x| &x.chunk_type().bytes() == b"PLTE")
+                .map(|x| x.data());
+    let mut chunks = Vec::with_capacity(png.chunks().len());
+    let mut stripped = vec![];
+    let mut dropped = vec![];
+    let mut palette = Some(best.palette);
+    let mut data = Some(best.data);
+    for chunk in png.chunks() {
+        let chunk_type = chunk.chunk_type().bytes();
+        match &chunk_type {
+            b"IHDR" => chunks.push(Chunk::new(*chunk.chunk_type(), best.ihdr.as_bytes())),
+            b"PLTE" | b"tRNS" => chunks.extend(palette.take().unwrap_or_default()),
+            b"IDAT" => {
+                chunks.extend(palette.take().unwrap_or_default());
+                if let Some(data) = data.take() {
+                    ensure!(
+                        data.len() <= Chunk::MAX_DATA,
+                        "image data does not fit in a chunk"
+                    );
+                    chunks.push(Chunk::new(*chunk.chunk_type(), data));
+                }
+            }
+            _ if strip && METADATA_CHUNKS.contains(&&chunk_type) => {
+                stripped.push(*chunk.chunk_type())
+            }
+            _ if colors_changed && COLOR_DEPENDENT_CHUNKS.contains(&&chunk_type) => {
+                dropped.push(*chunk.chunk_type())
+            }
+            _ => {
+                if AFTER_PALETTE_CHUNKS.contains(&&chunk_type) {
+                    chunks.extend(palette.take().unwrap_or_default());
+                }
+                chunks.push(chunk.clone());
+            }
+        }
+    }
+
+    let mut optimized = Png::from_chunks(chunks);
+    optimized.set_trailer(png.trailer().to_vec());
+    ensure!(
+        decoder::decode(&optimized)?.rgba16()? == pixels,
+        "the optimized image does not match the original, it was left untouched"
+    );
+    Ok(Optimized {
+        after: optimized.ihdr()?,
+        png: optimized,
+        before,
+        stripped,
+        dropped,
+    })
+}
+
+/// The pixels in the smallest true color encoding that holds them, and as a palette when they
+/// have 256 colors or fewer
+fn reductions(pixels: &[[u16; 4]], ihdr: &Ihdr) -> anyhow::Result<Vec<Image>> {
+    let opaque = pixels.iter().all(|x| x[3] == 65535);
+    let gray = pixels.iter().all(|x| x[0] == x[1] && x[1] == x[2]);
+    let wide = pixels.iter().flatten().any(|&x| x % 257 != 0);
+
+    // fully transparent pixels of a single color no opaque pixel has can be marked in tRNS
+    let mut key = None;
+    if !opaque && pixels.iter().all(|x| x[3] == 0 || x[3] == 65535) {
+        let transparent = pixels
+            .iter()
+            .find(|x| x[3] == 0)
+            .map(|x| [x[0], x[1], x[2]]);
+        if pixels
+            .iter()
+            .all(|x| (x[3] == 0) == (Some([x[0], x[1], x[2]]) == transparent))
+        {
+            key = transparent;
+        }
+    }
+    let alpha = !opaque && key.is_none();
+
+    let color_type = match (gray, alpha) {
+        (true, false) => ColorType::Grayscale,
+        (true, true) => ColorType::GrayscaleAlpha,
+        (false, false) => ColorType::Rgb,
+        (false, true) => ColorType::Rgba,
+    };
+    let bit_depth = if wide {
+        16
+    } else if color_type == ColorType::Grayscale {
+        let values = pixels.iter().map(|x| x[0]).chain(key.map(|x| x[0]));
+        let values: Vec<u16> = values.collect();
+        [1, 2, 4, 8]
+            .into_iter()
+            .find(|&depth| {
+                values
+                    .iter()
+                    .all(|&x| x % (65535 / ((1 << depth) - 1)) == 0)
+            })
+            .unwrap_or(8)
+    } else {
+        8
+    };
+    let step = 65535 / ((1u32 << bit_depth) - 1) as u16;
+    let channels: &[usize] = match color_type {
+        ColorType::Grayscale => &[0],
+        ColorType::GrayscaleAlpha => &[0, 3],
+        ColorType::Rgb => &[0, 1, 2],
+        _ => &[0, 1, 2, 3],
+    };
+    let samples: Vec<u16> = pixels
+        .iter()
+        .flat_map(|x| channels.iter().map(move |&i| x[i] / step))
+        .collect();
+    let true_color = Ihdr::new(ihdr.width, ihdr.height, bit_depth, color_type)?;
+    let mut image = Image::from_samples(true_color, &samples)?;
+    if let Some(key) = key {
+        let key = channels.iter().map(|&i| key[i] / step).collect();
+        image = image.with_transparent(key);
+    }
+    let mut images = vec![image];
+
+    if !wide {
+        let mut colors: Vec<Color> = pixels.iter().map(|x| x.map(|x| (x / 257) as u8)).collect();
+        colors.sort_unstable();
+        colors.dedup();
+        if colors.len() <= 256 {
+            // transparent entries first keep tRNS short
+            colors.sort_by_key(|x| x[3] == 255);
+            let index: HashMap<Color, u16> = colors
+                .iter()
+                .enumerate()
+                .map(|(i, x)| (*x, i as u16))
+                .collect();
+            let bit_depth = [1, 2, 4, 8]
+                .into_iter()
+                .find(|&depth| colors.len() <= 1 << depth)
+                .unwrap_or(8);
+            let indices: Vec<u16> = pixels
+                .iter()
+                .map(|x| index[&x.map(|x| (x / 257) as u8)])
+                .collect();
+            let indexed = Ihdr::new(ihdr.width, ihdr.height, bit_depth, ColorType::Indexed)?;
+            images.push(Image::from_samples(indexed, &indices)?.with_palette(colors));
+        }
+    }
+    Ok(images)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apng, encoder::Options};
+
+    fn png(image: &Image, extra: Vec<Chunk>) -> Png {
+        let options = Options {
+            filter: FilterStrategy::Fixed(0),
+            level: 0,
+            idat_size: 100,
+        };
+        let mut png = encoder::encode(image, &options).unwrap();
+        for chunk in extra {
+            png.insert_before_end(chunk);
+        }
+        png
+    }
+
+    #[test]
+    fn test_opaque_rgba_to_palette() {
+        let ihdr = Ihdr::new(16, 16, 8, ColorType::Rgba).unwrap();
+        // a few colors in no particular order
+        let mut state = 7u32;
+        let samples: Vec<u16> = (0..256)
+            .flat_map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                let i = (state >> 16) as u16 % 15;
+                [(i % 3) * 100, 20, (i % 5) * 50, 255]
+            })
+            .collect();
+        let image = Image::from_samples(ihdr, &samples).unwrap();
+        let secret = Chunk::new("ruSt".try_into().unwrap(), b"secret".to_vec());
+        let text = Chunk::new("tEXt".try_into().unwrap(), b"Comment\0hi".to_vec());
+        let original = png(&image, vec![secret.clone(), text]);
+
+        let optimized = optimize(&original, 9, true).unwrap();
+        assert_eq!(optimized.after.color_type, ColorType::Indexed);
+        assert_eq!(optimized.after.bit_depth, 4);
+        assert!(optimized.png.as_bytes().len() < original.as_bytes().len());
+        assert_eq!(optimized.stripped.len(), 1);
+        assert_eq!(optimized.png.chunk_by_type("ruSt"), Some(&secret));
+        assert_eq!(
+            optimized
+                .png
+                .chunks()
+                .iter()
+                .filter(|x| &x.chunk_type().bytes() == b"IDAT")
+                .count(),
+            1
+        );
+        assert_eq!(
+            decoder::decode(&optimized.png).unwrap().rgba16().unwrap(),
+            image.rgba16().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_gray_and_transparency_reductions() {
+        // a gray RGB image with a single fully transparent color
+        let ihdr = Ihdr::new(8, 8, 16, ColorType::Rgba).unwrap();
+        let samples: Vec<u16> = (0..64)
+            .flat_map(|i| {
+                let value = (i % 4) * 21845;
+                let alpha = if i % 4 == 0 { 0 } else { 65535 };
+                [value, value, value, alpha]
+            })
+            .collect();
+        let image = Image::from_samples(ihdr, &samples).unwrap();
+        let reduced = reductions(&image.rgba16().unwrap(), &ihdr).unwrap();
+        assert_eq!(reduced[0].ihdr().color_type, ColorType::Grayscale);
+        assert_eq!(reduced[0].ihdr().bit_depth, 2);
+        assert_eq!(reduced[0].transparent(), Some(&[0][..]));
+        assert_eq!(reduced[0].rgba16().unwrap(), image.rgba16().unwrap());
+        assert_eq!(reduced[1].ihdr().color_type, ColorType::Indexed);
+        assert_eq!(reduced[1].rgba16().unwrap(), image.rgba16().unwrap());
+
+        let optimized = optimize(&png(&image, vec![]), 6, false).unwrap();
+        assert!(optimized.after.bit_depth <= 2);
+    }
+
+    #[test]
+    fn test_keeps_wide_samples() {
+        let ihdr = Ihdr::new(4, 4, 16, ColorType::Rgb).unwrap();
+        let samples: Vec<u16> = (0..48).map(|i| i * 1000 + 1).collect();
+        let image = Image::from_samples(ihdr, &samples).unwrap();
+        let bkgd = Chunk::new("bKGD".try_into().unwrap(), vec![0; 6]);
+        let optimized = optimize(&png(&image, vec![bkgd]), 9, false).unwrap();
+        assert_eq!(optimized.after, ihdr);
+        assert!(optimized.dropped.is_empty());
+        assert!(optimized.png.chunk_by_type("bKGD").is_some());
+    }
+
+    #[test]
+    fn test_keeps_interlaced_animation() {
+        // a single pixel is stored the same way with and without Adam7
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Rgba).unwrap();
+        let frames: Vec<Image> = [[1, 2, 3, 255], [4, 5, 6, 255]]
+            .iter()
+            .map(|x| Image::from_samples(ihdr, x).unwrap())
+            .collect();
+        let mut original = apng::assemble(&frames, 100, 0).unwrap();
+        let interlaced = Ihdr {
+            interlaced: true,
+            ..ihdr
+        };
+        original
+            .replace_chunk(Chunk::new(
+                "IHDR".try_into().unwrap(),
+                interlaced.as_bytes(),
+            ))
+            .unwrap();
+
+        let optimized = optimize(&original, 9, false).unwrap();
+        assert_eq!(optimized.after, interlaced);
+        assert_eq!(optimized.png.image_data(), original.image_data());
+        assert_eq!(
+            optimized.png.chunk_by_type("fdAT"),
+            original.chunk_by_type("fdAT")
+        );
+    }
+}
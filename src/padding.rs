@@ -0,0 +1,82 @@
+//! Pads payloads with random bytes so their size only tells which bucket the length is in.
+//!
+//! Buckets follow Padmé, which rounds lengths to values with a mantissa of `log2(log2(L))` bits,
+//! costing at most 12% of overhead. A padded payload is the magic `jpP`, a version byte, the
+//! payload length as 4 big endian bytes, the payload and then the padding.
+
+use anyhow::{ensure, Context};
+use rand::{rngs::OsRng, RngCore};
+
+const MAGIC: &[u8; 3] = b"jpP";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 8;
+/// Smallest padded size, so short messages all look alike
+const MIN_SIZE: usize = 256;
+
+pub fn is_padded(data: &[u8]) -> bool {
+    data.len() >= HEADER_SIZE && data.starts_with(MAGIC) && data[3] == VERSION
+}
+
+/// Size of the bucket holding `length` bytes
+pub fn padded_size(length: usize) -> usize {
+    if length <= MIN_SIZE {
+        return MIN_SIZE;
+    }
+    let exponent = length.ilog2();
+    let mantissa_bits = exponent.ilog2() + 1;
+    let mask = (1 << (exponent - mantissa_bits)) - 1;
+    (length + mask) & !mask
+}
+
+pub fn pad(payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let length: u32 = payload
+        .len()
+        .try_into()
+        .context("payload is too large to be padded")?;
+
+    let mut padded = [&MAGIC[..], &[VERSION], &length.to_be_bytes(), payload].concat();
+    let start = padded.len();
+    padded.resize(padded_size(start), 0);
+    OsRng.fill_bytes(&mut padded[start..]);
+    Ok(padded)
+}
+
+pub fn unpad(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    ensure!(is_padded(data), "payload is not padded");
+    let length = u32::from_be_bytes(data[4..8].try_into()?) as usize;
+    data.get(HEADER_SIZE..HEADER_SIZE + length)
+        .map(|x| x.to_vec())
+        .context("padded payload is shorter than its length")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_padded_size() {
+        assert_eq!(padded_size(0), 256);
+        assert_eq!(padded_size(256), 256);
+        assert_eq!(padded_size(257), 272);
+        assert_eq!(padded_size(1000), 1024);
+        assert_eq!(padded_size(1025), 1088);
+        for length in [300, 5000, 70_000, 1_000_000] {
+            let size = padded_size(length);
+            assert!(size >= length && size <= length + length / 8);
+        }
+    }
+
+    #[test]
+    fn test_pad_and_unpad() {
+        let message = b"This is where your secret message will be!";
+        let padded = pad(message).unwrap();
+        assert_eq!(padded.len(), 256);
+        assert!(is_padded(&padded));
+        assert_eq!(unpad(&padded).unwrap(), message);
+
+        // every message up to the bucket size looks the same
+        assert_eq!(pad(&[0; 200]).unwrap().len(), padded.len());
+        assert!(unpad(&padded[..20]).is_err());
+        assert!(unpad(message).is_err());
+    }
+}
@@ -0,0 +1,687 @@
+use crate::{
+    apng,
+    chunk::{latin1, read_once, u16_at, Chunk},
+    decoder::{self, Color, Image},
+    encoder::{self, Options},
+    ihdr::{ColorType, Ihdr},
+    png::Png,
+};
+use anyhow::{bail, ensure, Context};
+use std::collections::HashSet;
+
+/// Colors in `PLTE`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plte(pub Vec<[u8; 3]>);
+
+/// Transparency in `tRNS`, an alpha for each palette entry or a fully transparent color
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trns {
+    Alphas(Vec<u8>),
+    Gray(u16),
+    Rgb([u16; 3]),
+}
+
+/// Background color in `bKGD`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bkgd {
+    Index(u8),
+    Gray(u16),
+    Rgb([u16; 3]),
+}
+
+/// How often each palette entry is used in `hIST`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hist(pub Vec<u16>);
+
+/// Suggested palette in `sPLT`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Splt {
+    pub name: String,
+    /// 8 or 16, the size of the samples of every entry
+    pub depth: u8,
+    pub entries: Vec<SpltEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpltEntry {
+    /// red, green, blue and alpha
+    pub color: [u16; 4],
+    pub frequency: u16,
+}
+
+/// Every palette related chunk of an image
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PaletteChunks {
+    pub plte: Option<Plte>,
+    pub trns: Option<Trns>,
+    pub bkgd: Option<Bkgd>,
+    pub hist: Option<Hist>,
+    pub suggested: Vec<Splt>,
+}
+
+fn rgb_at(data: &[u8]) -> [u16; 3] {
+    [u16_at(data, 0), u16_at(data, 2), u16_at(data, 4)]
+}
+
+fn rgb_bytes(rgb: &[u16; 3]) -> Vec<u8> {
+    rgb.iter().flat_map(|x| x.to_be_bytes()).collect()
+}
+
+impl TryFrom<&[u8]> for Plte {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        ensure!(
+            data.len().is_multiple_of(3),
+            "PLTE holds whole colors, {} bytes is not",
+            data.len()
+        );
+        ensure!(
+            (1..=256).contains(&(data.len() / 3)),
+            "PLTE holds 1 to 256 colors, not {}",
+            data.len() / 3
+        );
+        Ok(Plte(data.chunks(3).map(|x| [x[0], x[1], x[2]]).collect()))
+    }
+}
+
+impl Plte {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.0.concat()
+    }
+}
+
+impl Trns {
+    pub fn from_bytes(data: &[u8], color_type: ColorType) -> anyhow::Result<Trns> {
+        Ok(match color_type {
+            ColorType::Indexed => Trns::Alphas(data.to_vec()),
+            ColorType::Grayscale => {
+                ensure!(data.len() == 2, "tRNS holds 2 bytes, not {}", data.len());
+                Trns::Gray(u16_at(data, 0))
+            }
+            ColorType::Rgb => {
+                ensure!(data.len() == 6, "tRNS holds 6 bytes, not {}", data.len());
+                Trns::Rgb(rgb_at(data))
+            }
+            _ => bail!("{} images can't have a tRNS chunk", color_type),
+        })
+    }
+    pub fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            Trns::Alphas(alphas) => alphas.clone(),
+            Trns::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Trns::Rgb(rgb) => rgb_bytes(rgb),
+        }
+    }
+}
+
+impl Bkgd {
+    pub fn from_bytes(data: &[u8], color_type: ColorType) -> anyhow::Result<Bkgd> {
+        let size = match color_type {
+            ColorType::Indexed => 1,
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb | ColorType::Rgba => 6,
+        };
+        ensure!(
+            data.len() == size,
+            "bKGD holds {} bytes, not {}",
+            size,
+            data.len()
+        );
+        Ok(match size {
+            1 => Bkgd::Index(data[0]),
+            2 => Bkgd::Gray(u16_at(data, 0)),
+            _ => Bkgd::Rgb(rgb_at(data)),
+        })
+    }
+    pub fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            Bkgd::Index(index) => vec![*index],
+            Bkgd::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Bkgd::Rgb(rgb) => rgb_bytes(rgb),
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for Hist {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        ensure!(data.len().is_multiple_of(2), "hIST holds 2 bytes per entry");
+        Ok(Hist(
+            (0..data.len())
+                .step_by(2)
+                .map(|i| u16_at(data, i))
+                .collect(),
+        ))
+    }
+}
+
+impl Hist {
+    /// Scales pixel `counts` down to 16 bits, keeping used entries above 0
+    pub fn from_counts(counts: &[u64]) -> Hist {
+        let max = counts.iter().copied().max().unwrap_or_default().max(1);
+        Hist(
+            counts
+                .iter()
+                .map(|&x| match x {
+                    0 => 0,
+                    x => (x * 65535 / max).max(1) as u16,
+                })
+                .collect(),
+        )
+    }
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.0.iter().flat_map(|x| x.to_be_bytes()).collect()
+    }
+}
+
+impl TryFrom<&[u8]> for Splt {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let end = data
+            .iter()
+            .position(|&x| x == 0)
+            .context("sPLT is missing the end of the palette name")?;
+        ensure!(
+            (1..=79).contains(&end),
+            "sPLT palette name is {} bytes",
+            end
+        );
+        let depth = *data.get(end + 1).context("sPLT is missing its depth")?;
+        let size = match depth {
+            8 => 6,
+            16 => 10,
+            _ => bail!("sPLT depth is 8 or 16, not {}", depth),
+        };
+        let entries = &data[end + 2..];
+        ensure!(
+            entries.len().is_multiple_of(size),
+            "sPLT entries of depth {} are {} bytes each",
+            depth,
+            size
+        );
+        let entries = entries
+            .chunks(size)
+            .map(|x| SpltEntry {
+                color: match depth {
+                    8 => [x[0], x[1], x[2], x[3]].map(u16::from),
+                    _ => [u16_at(x, 0), u16_at(x, 2), u16_at(x, 4), u16_at(x, 6)],
+                },
+                frequency: u16_at(x, size - 2),
+            })
+            .collect();
+        Ok(Splt {
+            name: latin1(&data[..end]),
+            depth,
+            entries,
+        })
+    }
+}
+
+impl PaletteChunks {
+    pub fn from_chunks(chunks: &[Chunk], ihdr: &Ihdr) -> anyhow::Result<PaletteChunks> {
+        let mut palette = PaletteChunks::default();
+        let mut trns = None;
+        let mut bkgd = None;
+        for chunk in chunks {
+            match &chunk.chunk_type().bytes() {
+                b"PLTE" => read_once(&mut palette.plte, chunk)?,
+                b"tRNS" => read_once::<Raw>(&mut trns, chunk)?,
+                b"bKGD" => read_once::<Raw>(&mut bkgd, chunk)?,
+                b"hIST" => read_once(&mut palette.hist, chunk)?,
+                b"sPLT" => palette
+                    .suggested
+                    .push(Splt::try_from(chunk.data()).context("invalid sPLT chunk")?),
+                _ => {}
+            }
+        }
+        palette.trns = trns
+            .map(|x| Trns::from_bytes(&x.0, ihdr.color_type))
+            .transpose()
+            .context("invalid tRNS chunk")?;
+        palette.bkgd = bkgd
+            .map(|x| Bkgd::from_bytes(&x.0, ihdr.color_type))
+            .transpose()
+            .context("invalid bKGD chunk")?;
+        Ok(palette)
+    }
+
+    /// Palette entries along with their alpha from `tRNS`
+    pub fn colors(&self) -> Vec<Color> {
+        let alphas = match &self.trns {
+            Some(Trns::Alphas(alphas)) => &alphas[..],
+            _ => &[],
+        };
+        let plte = self.plte.as_ref().map(|x| &x.0[..]).unwrap_or_default();
+        plte.iter()
+            .enumerate()
+            .map(|(i, x)| [x[0], x[1], x[2], alphas.get(i).copied().unwrap_or(255)])
+            .collect()
+    }
+
+    /// Ways the palette chunks of `chunks` disagree with each other or with `ihdr`
+    pub fn problems(&self, chunks: &[Chunk], ihdr: &Ihdr) -> Vec<String> {
+        let mut problems = vec![];
+        let entries = self.plte.as_ref().map(|x| x.0.len());
+        let max = ((1u32 << ihdr.bit_depth) - 1) as u16;
+        match (ihdr.color_type, entries) {
+            (ColorType::Indexed, None) => {
+                problems.push("indexed images need a PLTE chunk".to_string())
+            }
+            (ColorType::Indexed, Some(entries)) if entries > 1 << ihdr.bit_depth => {
+                problems.push(format!(
+                    "{}-bit images index up to {} colors, PLTE has {}",
+                    ihdr.bit_depth,
+                    1 << ihdr.bit_depth,
+                    entries
+                ))
+            }
+            (ColorType::Grayscale | ColorType::GrayscaleAlpha, Some(_)) => problems.push(format!(
+                "{} images can't have a PLTE chunk",
+                ihdr.color_type
+            )),
+            _ => {}
+        }
+
+        let entries = entries.unwrap_or_default();
+        match &self.trns {
+            Some(Trns::Alphas(alphas)) if alphas.len() > entries => problems.push(format!(
+                "tRNS has {} alphas for {} palette entries",
+                alphas.len(),
+                entries
+            )),
+            Some(Trns::Gray(gray)) if *gray > max => problems.push(format!(
+                "tRNS gray {} is beyond {} bits",
+                gray, ihdr.bit_depth
+            )),
+            Some(Trns::Rgb(rgb)) if rgb.iter().any(|x| *x > max) => problems.push(format!(
+                "tRNS color {:?} is beyond {} bits",
+                rgb, ihdr.bit_depth
+            )),
+            _ => {}
+        }
+        match self.bkgd {
+            Some(Bkgd::Index(index)) if index as usize >= entries => problems.push(format!(
+                "bKGD index {} is past the {} palette entries",
+                index, entries
+            )),
+            Some(Bkgd::Gray(gray)) if gray > max => problems.push(format!(
+                "bKGD gray {} is beyond {} bits",
+                gray, ihdr.bit_depth
+            )),
+            Some(Bkgd::Rgb(rgb)) if rgb.iter().any(|x| *x > max) => problems.push(format!(
+                "bKGD color {:?} is beyond {} bits",
+                rgb, ihdr.bit_depth
+            )),
+            _ => {}
+        }
+        match &self.hist {
+            Some(_) if self.plte.is_none() => problems.push("hIST needs a PLTE chunk".to_string()),
+            Some(hist) if hist.0.len() != entries => problems.push(format!(
+                "hIST has {} entries for {} palette entries",
+                hist.0.len(),
+                entries
+            )),
+            _ => {}
+        }
+
+        let mut names = HashSet::new();
+        for splt in &self.suggested {
+            if !names.insert(&splt.name) {
+                problems.push(format!("more than one sPLT named {:?}", splt.name));
+            }
+        }
+
+        let plte = chunks
+            .iter()
+            .position(|x| &x.chunk_type().bytes() == b"PLTE");
+        for (i, chunk) in chunks.iter().enumerate() {
+            let chunk_type = chunk.chunk_type().bytes();
+            if matches!(&chunk_type, b"tRNS" | b"bKGD" | b"hIST") && plte.is_some_and(|x| i < x) {
+                problems.push(format!("{} must come after PLTE", chunk.chunk_type()));
+            }
+        }
+        problems
+    }
+}
+
+/// Chunk data whose meaning depends on the color type, parsed once that is known
+struct Raw(Vec<u8>);
+
+impl TryFrom<&[u8]> for Raw {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        Ok(Raw(data.to_vec()))
+    }
+}
+
+/// How many pixels use each palette entry
+pub fn counts(image: &Image) -> Vec<u64> {
+    let mut counts = vec![0; image.palette().len()];
+    for index in image.samples() {
+        if let Some(count) = counts.get_mut(index as usize) {
+            *count += 1;
+        }
+    }
+    counts
+}
+
+/// Parses `#rrggbb` or `#rrggbbaa`
+pub fn parse_color(value: &str) -> anyhow::Result<Color> {
+    let hex = value.trim().strip_prefix('#').unwrap_or(value.trim());
+    ensure!(
+        matches!(hex.len(), 6 | 8) && hex.is_ascii(),
+        "expected a color like `#ff8000` or `#ff800080`, not {:?}",
+        value
+    );
+    let mut color = [255; 4];
+    for (i, x) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        *x = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+            .with_context(|| format!("invalid color {:?}", value))?;
+    }
+    Ok(color)
+}
+
+/// `#rrggbb`, with the alpha appended when it is not opaque
+pub fn format_color(color: &Color) -> String {
+    let [r, g, b, a] = *color;
+    match a {
+        255 => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        _ => format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+    }
+}
+
+/// JSON array of colors as written by `format_color`
+pub fn to_json(colors: &[Color]) -> String {
+    let colors: Vec<String> = colors
+        .iter()
+        .map(|x| format!("  \"{}\"", format_color(x)))
+        .collect();
+    format!("[\n{}\n]\n", colors.join(",\n"))
+}
+
+pub fn from_json(json: &str) -> anyhow::Result<Vec<Color>> {
+    let items = json
+        .trim()
+        .strip_prefix('[')
+        .and_then(|x| x.strip_suffix(']'))
+        .context("expected a JSON array of colors")?;
+    items
+        .split(',')
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| {
+            let color = x.trim().strip_prefix('"').and_then(|x| x.strip_suffix('"'));
+            parse_color(color.context("expected colors as JSON strings")?)
+        })
+        .collect()
+}
+
+/// GIMP palette, which has no transparency
+pub fn to_gpl(colors: &[Color], name: &str) -> String {
+    let mut gpl = format!("GIMP Palette\nName: {}\nColumns: 16\n#\n", name);
+    for (i, [r, g, b, _]) in colors.iter().enumerate() {
+        gpl.push_str(&format!("{:3} {:3} {:3}\tIndex {}\n", r, g, b, i));
+    }
+    gpl
+}
+
+pub fn from_gpl(gpl: &str) -> anyhow::Result<Vec<Color>> {
+    let mut lines = gpl.lines();
+    ensure!(
+        lines.next().map(str::trim) == Some("GIMP Palette"),
+        "not a GIMP palette"
+    );
+    let mut colors = Vec::new();
+    for line in lines {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.contains(':') {
+            continue;
+        }
+        let mut values = line.split_whitespace().map(|x| x.parse::<u8>());
+        let mut color = [255; 4];
+        for x in &mut color[..3] {
+            *x = values
+                .next()
+                .and_then(|x| x.ok())
+                .with_context(|| format!("invalid GIMP palette line {:?}", line))?;
+        }
+        colors.push(color);
+    }
+    Ok(colors)
+}
+
+/// Places `chunk` where the palette belongs, right before the chunks that depend on it
+fn insert_palette_chunk(png: &mut Png, chunk: Chunk, after: &[&[u8; 4]]) -> anyhow::Result<()> {
+    if png.chunk_by_type(&chunk.chunk_type().to_string()).is_some() {
+        png.replace_chunk(chunk)?;
+        return Ok(());
+    }
+    let mut chunks = png.chunks().to_vec();
+    let index = chunks
+        .iter()
+        .position(|x| after.contains(&&x.chunk_type().bytes()))
+        .unwrap_or(chunks.len().saturating_sub(1));
+    chunks.insert(index, chunk);
+    png.set_chunks(chunks);
+    Ok(())
+}
+
+/// Replaces the palette of `png` with `colors`, which has to hold every index in use
+pub fn set_colors(png: &mut Png, colors: &[Color]) -> anyhow::Result<()> {
+    let ihdr = png.ihdr()?;
+    ensure!(
+        matches!(
+            ihdr.color_type,
+            ColorType::Indexed | ColorType::Rgb | ColorType::Rgba
+        ),
+        "{} images can't have a palette",
+        ihdr.color_type
+    );
+    ensure!(
+        (1..=256).contains(&colors.len()),
+        "a palette holds 1 to 256 colors, not {}",
+        colors.len()
+    );
+    let palette = PaletteChunks::from_chunks(png.chunks(), &ihdr)?;
+
+    let mut hist = None;
+    if ihdr.color_type == ColorType::Indexed {
+        ensure!(
+            colors.len() <= 1 << ihdr.bit_depth,
+            "{}-bit images index up to {} colors",
+            ihdr.bit_depth,
+            1 << ihdr.bit_depth
+        );
+        let image = decoder::decode(png)?;
+        let used = image.samples().into_iter().max().unwrap_or_default() as usize;
+        ensure!(
+            used < colors.len(),
+            "the image uses index {}, the palette needs at least {} colors",
+            used,
+            used + 1
+        );
+        if let Some(Bkgd::Index(index)) = palette.bkgd {
+            ensure!(
+                (index as usize) < colors.len(),
+                "the background is index {}, the palette needs at least {} colors",
+                index,
+                index + 1
+            );
+        }
+        if palette.hist.is_some() {
+            let mut counts = counts(&image);
+            counts.resize(colors.len(), 0);
+            hist = Some(Hist::from_counts(&counts));
+        }
+    } else {
+        ensure!(
+            colors.iter().all(|x| x[3] == 255),
+            "only indexed images have transparent palette entries"
+        );
+    }
+
+    let plte = Plte(colors.iter().map(|x| [x[0], x[1], x[2]]).collect());
+    let before = [b"tRNS", b"bKGD", b"hIST", b"IDAT"];
+    insert_palette_chunk(
+        png,
+        Chunk::new("PLTE".try_into()?, plte.as_bytes()),
+        &before,
+    )?;
+
+    if ihdr.color_type == ColorType::Indexed {
+        let mut alphas: Vec<u8> = colors.iter().map(|x| x[3]).collect();
+        while alphas.last() == Some(&255) {
+            alphas.pop();
+        }
+        let chunk = Chunk::new("tRNS".try_into()?, alphas.clone());
+        match (alphas.is_empty(), palette.trns.is_some()) {
+            (true, true) => drop(png.remove_chunk("tRNS")?),
+            (false, _) => insert_palette_chunk(png, chunk, &[b"bKGD", b"hIST", b"IDAT"])?,
+            (true, false) => {}
+        }
+    }
+    if let Some(hist) = hist {
+        png.replace_chunk(Chunk::new("hIST".try_into()?, hist.as_bytes()))?;
+    }
+    Ok(())
+}
+
+/// Moves the pixels of each `(from, to)` palette index to the other one, rewriting the image data
+pub fn remap(png: &mut Png, pairs: &[(u8, u8)]) -> anyhow::Result<()> {
+    ensure!(
+        !apng::is_animated(png),
+        "only the default image would be remapped, the frames of an animated image would not"
+    );
+    let image = decoder::decode(png)?;
+    let ihdr = Ihdr {
+        interlaced: false,
+        ..*image.ihdr()
+    };
+    ensure!(
+        ihdr.color_type == ColorType::Indexed,
+        "only indexed images can be remapped"
+    );
+    let entries = image.palette().len();
+    for (from, to) in pairs {
+        ensure!(
+            (*from as usize) < entries && (*to as usize) < entries,
+            "the palette only has indices 0 to {}",
+            entries - 1
+        );
+    }
+
+    let mut table: Vec<u16> = (0..entries as u16).collect();
+    for (from, to) in pairs {
+        table[*from as usize] = *to as u16;
+    }
+    let samples: Vec<u16> = image
+        .samples()
+        .into_iter()
+        .map(|x| table.get(x as usize).copied().unwrap_or(x))
+        .collect();
+    let remapped = Image::from_samples(ihdr, &samples)?.with_palette(image.palette().to_vec());
+
+    // rows are written back in plain order
+    png.replace_chunk(Chunk::new("IHDR".try_into()?, ihdr.as_bytes()))?;
+    png.set_image_data(&encoder::image_data(&remapped, &Options::default())?)?;
+    if png.chunk_by_type("hIST").is_some() {
+        let hist = Hist::from_counts(&counts(&remapped));
+        png.replace_chunk(Chunk::new("hIST".try_into()?, hist.as_bytes()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indexed() -> Png {
+        let ihdr = Ihdr::new(4, 2, 2, ColorType::Indexed).unwrap();
+        let palette = vec![[0, 0, 0, 0], [255, 0, 0, 255], [0, 0, 255, 255]];
+        let image = Image::from_samples(ihdr, &[0, 1, 2, 1, 2, 2, 0, 0])
+            .unwrap()
+            .with_palette(palette);
+        let mut png = encoder::encode(&image, &Options::default()).unwrap();
+        let hist = Chunk::new("hIST".try_into().unwrap(), vec![0; 6]);
+        png.insert_before_data(hist);
+        png
+    }
+
+    #[test]
+    fn test_chunks_and_problems() {
+        let png = indexed();
+        let ihdr = png.ihdr().unwrap();
+        let palette = PaletteChunks::from_chunks(png.chunks(), &ihdr).unwrap();
+        assert_eq!(palette.colors()[0], [0, 0, 0, 0]);
+        assert_eq!(palette.colors()[2], [0, 0, 255, 255]);
+        assert_eq!(palette.trns, Some(Trns::Alphas(vec![0])));
+        assert!(palette.problems(png.chunks(), &ihdr).is_empty());
+
+        let broken = PaletteChunks {
+            trns: Some(Trns::Alphas(vec![0; 5])),
+            bkgd: Some(Bkgd::Index(3)),
+            hist: Some(Hist(vec![1])),
+            ..palette
+        };
+        assert_eq!(broken.problems(&[], &ihdr).len(), 3);
+
+        let gray = Ihdr::new(1, 1, 4, ColorType::Grayscale).unwrap();
+        let trns = Chunk::new("tRNS".try_into().unwrap(), vec![0, 16]);
+        let palette = PaletteChunks::from_chunks(&[trns], &gray).unwrap();
+        assert_eq!(palette.problems(&[], &gray).len(), 1);
+
+        let splt = [b"dither\0\x08".to_vec(), vec![1, 2, 3, 4, 0, 5]].concat();
+        let splt = Splt::try_from(&splt[..]).unwrap();
+        assert_eq!(splt.entries[0].color, [1, 2, 3, 4]);
+        assert_eq!(splt.entries[0].frequency, 5);
+    }
+
+    #[test]
+    fn test_formats() {
+        let colors = vec![[255, 128, 0, 255], [1, 2, 3, 4]];
+        let json = to_json(&colors);
+        assert!(json.contains("\"#ff8000\""));
+        assert_eq!(from_json(&json).unwrap(), colors);
+        assert!(from_json("[]").unwrap().is_empty());
+        assert!(from_json("[\"#12\"]").is_err());
+
+        let gpl = to_gpl(&colors, "test");
+        assert_eq!(
+            from_gpl(&gpl).unwrap(),
+            vec![[255, 128, 0, 255], [1, 2, 3, 255]]
+        );
+        assert!(from_gpl("JASC-PAL").is_err());
+    }
+
+    #[test]
+    fn test_edits() {
+        let mut png = indexed();
+        assert!(set_colors(&mut png, &[[0; 4], [0; 4]]).is_err());
+        set_colors(
+            &mut png,
+            &[[9, 9, 9, 255], [1, 1, 1, 128], [2, 2, 2, 255], [3; 4]],
+        )
+        .unwrap();
+        assert_eq!(
+            png.chunk_by_type("tRNS").unwrap().data(),
+            [255, 128, 255, 3]
+        );
+        assert_eq!(png.chunk_by_type("hIST").unwrap().data().len(), 8);
+        let image = decoder::decode(&png).unwrap();
+        assert_eq!(image.rgba8().unwrap()[0], [9, 9, 9, 255]);
+
+        remap(&mut png, &[(1, 3), (0, 2)]).unwrap();
+        let image = decoder::decode(&png).unwrap();
+        assert_eq!(image.samples(), [2, 3, 2, 3, 2, 2, 2, 2]);
+        let hist = Hist::try_from(png.chunk_by_type("hIST").unwrap().data()).unwrap();
+        assert_eq!(hist.0, [0, 0, 65535, 21845]);
+        assert!(remap(&mut png, &[(0, 4)]).is_err());
+
+        let actl = Chunk::new("acTL".try_into().unwrap(), vec![0, 0, 0, 1, 0, 0, 0, 0]);
+        png.insert_before_data(actl);
+        assert!(remap(&mut png, &[(2, 3)]).is_err());
+    }
+}
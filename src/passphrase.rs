@@ -0,0 +1,97 @@
+use crate::chunk_type::ChunkType;
+use anyhow::{anyhow, ensure};
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, KeyInit, Nonce};
+use rand::{rngs::OsRng, RngCore};
+
+/// Salt of the passphrase derivation, fixed so the same passphrase always finds its chunks
+const SALT: &[u8] = b"just_png chunk type";
+const NONCE_SIZE: usize = 12;
+/// Bytes `seal` adds to the data, its nonce and authentication tag
+pub const SEAL_OVERHEAD: usize = NONCE_SIZE + 16;
+
+/// Chunk type and cipher key derived from a passphrase shared by sender and receiver.
+///
+/// Chunks sealed with it look like random bytes, so they can't be told apart from decoys.
+pub struct Passphrase {
+    chunk_type: ChunkType,
+    cipher: ChaCha20Poly1305,
+}
+
+impl Passphrase {
+    /// Runs scrypt on `passphrase`, the first 16 bytes pick the chunk type and the next 32 are
+    /// the cipher key
+    pub fn derive(passphrase: &str) -> Passphrase {
+        let params = scrypt::Params::new(14, 8, 1, 48).expect("valid scrypt parameters");
+        let mut output = [0; 48];
+        scrypt::scrypt(passphrase.as_bytes(), SALT, &params, &mut output)
+            .expect("valid scrypt output length");
+
+        let seed: [u8; 16] = output[..16].try_into().unwrap();
+        let passphrase = Passphrase {
+            chunk_type: ChunkType::hidden(&seed),
+            cipher: ChaCha20Poly1305::new_from_slice(&output[16..]).expect("valid key length"),
+        };
+        output.fill(0);
+        passphrase
+    }
+    pub fn chunk_type(&self) -> ChunkType {
+        self.chunk_type
+    }
+    /// Encrypts `data` behind a random nonce
+    pub fn seal(&self, data: &[u8]) -> Vec<u8> {
+        let mut nonce = [0; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), data)
+            .expect("message fits in a chunk");
+        [&nonce[..], &ciphertext].concat()
+    }
+    /// Decrypts data written by `seal`, failing when it was made with another passphrase or was
+    /// altered
+    pub fn open(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        ensure!(data.len() >= NONCE_SIZE, "sealed data is too short");
+        let (nonce, ciphertext) = data.split_at(NONCE_SIZE);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("wrong passphrase or damaged data"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derive_chunk_type() {
+        let passphrase = Passphrase::derive("correct horse battery staple");
+        let chunk_type = passphrase.chunk_type();
+        assert_eq!(
+            chunk_type,
+            Passphrase::derive("correct horse battery staple").chunk_type()
+        );
+        assert_ne!(chunk_type, Passphrase::derive("Tr0ub4dor&3").chunk_type());
+        assert!(!chunk_type.is_critical());
+        assert!(!chunk_type.is_public());
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let passphrase = Passphrase::derive("hunter2");
+        let sealed = passphrase.seal(b"This is where your secret message will be!");
+        assert_ne!(
+            sealed,
+            passphrase.seal(b"This is where your secret message will be!")
+        );
+        assert_eq!(sealed.len(), 42 + SEAL_OVERHEAD);
+        assert_eq!(
+            passphrase.open(&sealed).unwrap(),
+            b"This is where your secret message will be!"
+        );
+
+        assert!(Passphrase::derive("hunter3").open(&sealed).is_err());
+        let mut altered = sealed.clone();
+        altered[20] ^= 1;
+        assert!(passphrase.open(&altered).is_err());
+    }
+}
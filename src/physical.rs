@@ -0,0 +1,417 @@
+use crate::chunk::{read_once, u32_at, Chunk};
+use anyhow::{bail, ensure, Context};
+use std::{
+    fmt,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Chunks describing the physical size of the image and when it changed
+pub const PHYSICAL_CHUNKS: &[&[u8; 4]] = &[b"pHYs", b"tIME", b"oFFs", b"sCAL"];
+
+const METERS_PER_INCH: f64 = 0.0254;
+
+/// Intended pixel size in `pHYs`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelDimensions {
+    pub x: u32,
+    pub y: u32,
+    /// whether `x` and `y` are pixels per meter, otherwise they only give the aspect ratio
+    pub meters: bool,
+}
+
+/// Last modification in `tIME`, in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Time {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+/// Position of the image on a page in `oFFs`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Offset {
+    pub x: i32,
+    pub y: i32,
+    /// whether `x` and `y` are micrometers, otherwise they are pixels
+    pub micrometers: bool,
+}
+
+/// Physical size of a pixel in `sCAL`, kept as written since it is stored as text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scale {
+    width: String,
+    height: String,
+    /// whether the size is in radians, otherwise it is in meters
+    radians: bool,
+}
+
+/// Every physical and timing chunk of an image
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Physical {
+    pub dimensions: Option<PixelDimensions>,
+    pub time: Option<Time>,
+    pub offset: Option<Offset>,
+    pub scale: Option<Scale>,
+}
+
+/// Parses `x,y` followed by one of `units`, returning the index of the unit found
+fn pair<T: FromStr>(value: &str, units: &[&str]) -> anyhow::Result<(T, T, usize)> {
+    let (number, unit) = units
+        .iter()
+        .enumerate()
+        .rev()
+        .find_map(|(i, unit)| value.strip_suffix(unit).map(|x| (x, i)))
+        .context("unknown unit")?;
+    let (x, y) = number.split_once(',').context("expected `x,y`")?;
+    let parse = |x: &str| x.trim().parse::<T>().ok().context("invalid number");
+    Ok((parse(x)?, parse(y)?, unit))
+}
+
+impl PixelDimensions {
+    pub fn from_dpi(dpi: u32) -> anyhow::Result<PixelDimensions> {
+        ensure!(dpi > 0, "the resolution can't be 0");
+        let ppm = (dpi as f64 / METERS_PER_INCH).round() as u32;
+        Ok(PixelDimensions {
+            x: ppm,
+            y: ppm,
+            meters: true,
+        })
+    }
+    /// Horizontal and vertical dots per inch, when the unit is known
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        self.meters.then_some((
+            self.x as f64 * METERS_PER_INCH,
+            self.y as f64 * METERS_PER_INCH,
+        ))
+    }
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = [self.x.to_be_bytes(), self.y.to_be_bytes()].concat();
+        bytes.push(self.meters as u8);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for PixelDimensions {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        ensure!(data.len() == 9, "pHYs holds 9 bytes, not {}", data.len());
+        ensure!(data[8] <= 1, "unknown pHYs unit {}", data[8]);
+        Ok(PixelDimensions {
+            x: u32_at(data, 0),
+            y: u32_at(data, 4),
+            meters: data[8] == 1,
+        })
+    }
+}
+
+impl Time {
+    pub fn now() -> anyhow::Result<Time> {
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let days = (seconds / 86400) as i64;
+        let seconds = seconds % 86400;
+
+        // civil date from days since 1970-01-01, in eras of 400 years starting on March 1st
+        let days = days + 719468;
+        let era = days / 146097;
+        let day_of_era = days - era * 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month + 2) / 5 + 1;
+        let month = if month < 10 { month + 3 } else { month - 9 };
+        let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+        Time::new(
+            year.try_into()?,
+            month as u8,
+            day as u8,
+            (seconds / 3600) as u8,
+            (seconds / 60 % 60) as u8,
+            (seconds % 60) as u8,
+        )
+    }
+    pub fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> anyhow::Result<Time> {
+        ensure!((1..=12).contains(&month), "invalid month {}", month);
+        ensure!((1..=31).contains(&day), "invalid day {}", day);
+        ensure!(hour <= 23, "invalid hour {}", hour);
+        ensure!(minute <= 59, "invalid minute {}", minute);
+        // 60 allows for leap seconds
+        ensure!(second <= 60, "invalid second {}", second);
+        Ok(Time {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        })
+    }
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.year.to_be_bytes().to_vec();
+        bytes.extend([self.month, self.day, self.hour, self.minute, self.second]);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Time {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        ensure!(data.len() == 7, "tIME holds 7 bytes, not {}", data.len());
+        Time::new(
+            u16::from_be_bytes([data[0], data[1]]),
+            data[2],
+            data[3],
+            data[4],
+            data[5],
+            data[6],
+        )
+    }
+}
+
+/// Parses `YYYY-MM-DD HH:MM:SS`, with either a space or a `T` in between
+impl FromStr for Time {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = || -> Option<[u16; 6]> {
+            let (date, time) = s.split_once([' ', 'T'])?;
+            let mut values = date.split('-').chain(time.trim_end_matches('Z').split(':'));
+            let mut fields = [0; 6];
+            for field in &mut fields {
+                *field = values.next()?.parse().ok()?;
+            }
+            values.next().is_none().then_some(fields)
+        };
+        let [year, month, day, hour, minute, second] =
+            parse().context("expected a time like `2024-05-17 13:45:00`")?;
+        let byte = |x: u16| u8::try_from(x).context("invalid time");
+        Time::new(
+            year,
+            byte(month)?,
+            byte(day)?,
+            byte(hour)?,
+            byte(minute)?,
+            byte(second)?,
+        )
+    }
+}
+
+impl Offset {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = [self.x.to_be_bytes(), self.y.to_be_bytes()].concat();
+        bytes.push(self.micrometers as u8);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Offset {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        ensure!(data.len() == 9, "oFFs holds 9 bytes, not {}", data.len());
+        ensure!(data[8] <= 1, "unknown oFFs unit {}", data[8]);
+        Ok(Offset {
+            x: u32_at(data, 0) as i32,
+            y: u32_at(data, 4) as i32,
+            micrometers: data[8] == 1,
+        })
+    }
+}
+
+/// Parses `x,y` in pixels, or in micrometers when followed by `um`
+impl FromStr for Offset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (x, y, unit) = pair(s, &["", "um"]).context("expected an offset like `10,-20um`")?;
+        Ok(Offset {
+            x,
+            y,
+            micrometers: unit == 1,
+        })
+    }
+}
+
+impl Scale {
+    pub fn new(width: &str, height: &str, radians: bool) -> anyhow::Result<Scale> {
+        for value in [width, height] {
+            ensure!(
+                value
+                    .chars()
+                    .all(|x| x.is_ascii_digit() || ".eE+-".contains(x)),
+                "invalid sCAL size {:?}",
+                value
+            );
+            let number: f64 = value
+                .parse()
+                .context(format!("invalid sCAL size {:?}", value))?;
+            ensure!(
+                number > 0.0 && number.is_finite(),
+                "sCAL sizes must be positive, not {}",
+                value
+            );
+        }
+        Ok(Scale {
+            width: width.to_string(),
+            height: height.to_string(),
+            radians,
+        })
+    }
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![if self.radians { 2 } else { 1 }];
+        bytes.extend(self.width.as_bytes());
+        bytes.push(0);
+        bytes.extend(self.height.as_bytes());
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Scale {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let (&unit, sizes) = data.split_first().context("empty sCAL chunk")?;
+        let radians = match unit {
+            1 => false,
+            2 => true,
+            other => bail!("unknown sCAL unit {}", other),
+        };
+        let sizes = std::str::from_utf8(sizes).context("sCAL sizes are not text")?;
+        let (width, height) = sizes
+            .split_once('\0')
+            .context("sCAL is missing its height")?;
+        Scale::new(width, height, radians)
+    }
+}
+
+/// Parses `width,height` in meters, or in radians when followed by `rad`
+impl FromStr for Scale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height, unit) =
+            pair::<String>(s, &["", "m", "rad"]).context("expected a scale like `0.001,0.001m`")?;
+        Scale::new(&width, &height, unit == 2)
+    }
+}
+
+impl Physical {
+    pub fn from_chunks(chunks: &[Chunk]) -> anyhow::Result<Physical> {
+        let mut physical = Physical::default();
+        for chunk in chunks {
+            match &chunk.chunk_type().bytes() {
+                b"pHYs" => read_once(&mut physical.dimensions, chunk)?,
+                b"tIME" => read_once(&mut physical.time, chunk)?,
+                b"oFFs" => read_once(&mut physical.offset, chunk)?,
+                b"sCAL" => read_once(&mut physical.scale, chunk)?,
+                _ => {}
+            }
+        }
+        Ok(physical)
+    }
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+impl fmt::Display for Physical {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(x) = self.dimensions {
+            match x.dpi() {
+                Some((dpi_x, dpi_y)) => writeln!(
+                    f,
+                    "pHYs  {}x{} pixels per meter ({:.0}x{:.0} dpi)",
+                    x.x, x.y, dpi_x, dpi_y
+                )?,
+                None => writeln!(f, "pHYs  aspect ratio {}:{}", x.x, x.y)?,
+            }
+        }
+        if let Some(x) = self.offset {
+            let unit = if x.micrometers { "µm" } else { "pixels" };
+            writeln!(f, "oFFs  {}, {} {}", x.x, x.y, unit)?;
+        }
+        if let Some(x) = &self.scale {
+            let unit = if x.radians { "radians" } else { "meters" };
+            writeln!(f, "sCAL  {} x {} {} per pixel", x.width, x.height, unit)?;
+        }
+        if let Some(x) = self.time {
+            writeln!(f, "tIME  {}", x)?;
+        }
+        if *self == Physical::default() {
+            writeln!(f, "no physical or timing information")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trips() {
+        let dimensions = PixelDimensions::from_dpi(300).unwrap();
+        assert_eq!(dimensions.x, 11811);
+        assert_eq!(dimensions.dpi().unwrap().0.round(), 300.0);
+        let bytes = dimensions.as_bytes();
+        assert_eq!(PixelDimensions::try_from(&bytes[..]).unwrap(), dimensions);
+
+        let time: Time = "2024-02-29T23:59:60Z".parse().unwrap();
+        assert_eq!(time.to_string(), "2024-02-29 23:59:60 UTC");
+        assert_eq!(Time::try_from(&time.as_bytes()[..]).unwrap(), time);
+        assert!("2024-13-01 00:00:00".parse::<Time>().is_err());
+        assert!("2024-01-01".parse::<Time>().is_err());
+        assert!(Time::now().unwrap().year >= 2024);
+
+        let offset: Offset = "10,-20um".parse().unwrap();
+        assert_eq!((offset.x, offset.y, offset.micrometers), (10, -20, true));
+        assert_eq!(Offset::try_from(&offset.as_bytes()[..]).unwrap(), offset);
+        assert!(!"3,4".parse::<Offset>().unwrap().micrometers);
+
+        let scale: Scale = "0.25,1e-3rad".parse().unwrap();
+        assert_eq!(scale.as_bytes(), b"\x020.25\x001e-3");
+        assert_eq!(Scale::try_from(&scale.as_bytes()[..]).unwrap(), scale);
+        assert!("0,1m".parse::<Scale>().is_err());
+        assert!("-1,1m".parse::<Scale>().is_err());
+    }
+
+    #[test]
+    fn test_from_chunks() {
+        let chunk = |chunk_type: &str, data: &[u8]| {
+            Chunk::new(chunk_type.try_into().unwrap(), data.to_vec())
+        };
+        let chunks = [
+            chunk("pHYs", &[0, 0, 0, 1, 0, 0, 0, 2, 0]),
+            chunk("tIME", &[7, 232, 1, 2, 3, 4, 5]),
+        ];
+        let physical = Physical::from_chunks(&chunks).unwrap();
+        assert_eq!(physical.dimensions.unwrap().dpi(), None);
+        assert_eq!(physical.time.unwrap().year, 2024);
+        assert!(physical.offset.is_none());
+
+        assert!(Physical::from_chunks(&[chunk("tIME", &[0; 7])]).is_err());
+        let twice = [chunk("pHYs", &[0; 9]), chunk("pHYs", &[0; 9])];
+        assert!(Physical::from_chunks(&twice).is_err());
+    }
+}
@@ -1,47 +1,438 @@
-use crate::{chunk::Chunk, chunk_type::ChunkType};
+use crate::{
+    apng,
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    color::ColorInfo,
+    exif::Exif,
+    ihdr::Ihdr,
+    physical::{Physical, PixelDimensions, Time},
+    signature::{self, Verification},
+};
 use anyhow::{ensure, Context};
 use core::result::Result::Ok;
-use std::fmt;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use std::{fmt, str::FromStr};
 
 #[derive(Debug)]
-pub struct Png(Vec<Chunk>);
+pub struct Png {
+    chunks: Vec<Chunk>,
+    /// bytes after `IEND` that are not chunks
+    trailer: Vec<u8>,
+}
+
+/// Where a chunk goes among the others of a png
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    /// right after `IHDR`
+    Start,
+    /// right before the first `IDAT`
+    BeforeData,
+    /// right after the last `IDAT`
+    AfterData,
+    /// right before `IEND`
+    End,
+    /// at this index among every chunk
+    Index(usize),
+}
+
+impl FromStr for Position {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "start" => Position::Start,
+            "before-data" => Position::BeforeData,
+            "after-data" => Position::AfterData,
+            "end" => Position::End,
+            _ => {
+                Position::Index(s.parse().context(
+                    "expected start, before-data, after-data, end or the index of a chunk",
+                )?)
+            }
+        })
+    }
+}
 
 impl Png {
-    const STANDARD_HEADER: &'static [u8; 8] = &[137, 80, 78, 71, 13, 10, 26, 10];
+    pub(crate) const STANDARD_HEADER: &'static [u8; 8] = &[137, 80, 78, 71, 13, 10, 26, 10];
 
-    fn from_chunks(chunks: Vec<Chunk>) -> Png {
-        Png(chunks.into())
+    pub(crate) fn from_chunks(chunks: Vec<Chunk>) -> Png {
+        Png {
+            chunks,
+            trailer: vec![],
+        }
+    }
+    /// Replaces every chunk, keeping the trailer
+    pub(crate) fn set_chunks(&mut self, chunks: Vec<Chunk>) {
+        self.chunks = chunks;
     }
     pub(crate) fn append_chunk(&mut self, chunk: Chunk) {
-        self.0.push(chunk);
+        self.chunks.push(chunk);
     }
     pub(crate) fn remove_chunk(&mut self, chunk_type: &str) -> anyhow::Result<Chunk> {
         let chunk_type = TryInto::<ChunkType>::try_into(chunk_type)?;
-
+        let index = self.find_chunk(&chunk_type.to_string(), 0)?;
+        self.remove_at(index)
+    }
+    /// Removes the chunk at `index` among every chunk
+    pub(crate) fn remove_at(&mut self, index: usize) -> anyhow::Result<Chunk> {
+        ensure!(
+            index < self.chunks.len(),
+            "there are only {} chunks",
+            self.chunks.len()
+        );
+        let chunk = self.chunks.remove(index);
+        // the remaining frame chunks of an animation must keep counting up from 0
+        if apng::is_animation_chunk(&chunk) {
+            apng::renumber(self)?;
+        }
+        Ok(chunk)
+    }
+    /// Removes every chunk matching `predicate` and returns them
+    pub(crate) fn remove_chunks(
+        &mut self,
+        mut predicate: impl FnMut(&Chunk) -> bool,
+    ) -> anyhow::Result<Vec<Chunk>> {
+        let (removed, kept) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition::<Vec<_>, _>(|x| predicate(x));
+        self.chunks = kept;
+        if removed.iter().any(apng::is_animation_chunk) {
+            apng::renumber(self)?;
+        }
+        Ok(removed)
+    }
+    /// Moves the chunk at `index` to `position`, counted once it left its place, and returns its
+    /// new index
+    pub(crate) fn move_chunk(&mut self, index: usize, position: Position) -> anyhow::Result<usize> {
+        ensure!(
+            index < self.chunks.len(),
+            "there are only {} chunks",
+            self.chunks.len()
+        );
+        let chunk = self.chunks.remove(index);
+        match self.position(position) {
+            Ok(to) => {
+                let animation = apng::is_animation_chunk(&chunk);
+                self.chunks.insert(to, chunk);
+                // frame chunks are numbered in the order they appear
+                if animation {
+                    apng::renumber(self)?;
+                }
+                Ok(to)
+            }
+            Err(error) => {
+                self.chunks.insert(index, chunk);
+                Err(error)
+            }
+        }
+    }
+    /// Changes the type of the chunk at `index`, keeping its data
+    pub(crate) fn rename_chunk(
+        &mut self,
+        index: usize,
+        chunk_type: ChunkType,
+    ) -> anyhow::Result<()> {
+        let chunk = self
+            .chunks
+            .get_mut(index)
+            .context("no chunk at this index")?;
+        let animation = apng::is_animation_chunk(chunk);
+        *chunk = Chunk::new(chunk_type, chunk.data().to_vec());
+        if animation || apng::is_animation_chunk(chunk) {
+            apng::renumber(self)?;
+        }
+        Ok(())
+    }
+    /// Inserts a copy of the chunk at `index` at `position` and returns the index of the copy
+    pub(crate) fn copy_chunk(&mut self, index: usize, position: Position) -> anyhow::Result<usize> {
+        let chunk = self.chunks.get(index).context("no chunk at this index")?;
+        self.insert_chunk(chunk.clone(), position)
+    }
+    /// Inserts `chunk` right before `IEND`, or last when there is no `IEND`
+    pub(crate) fn insert_before_end(&mut self, chunk: Chunk) {
         let index = self
-            .0
+            .chunks
             .iter()
-            .map(|x| x.chunk_type())
-            .position(|x| x == &chunk_type)
-            .context(format!("chunk of type {} not found", chunk_type))?;
+            .position(|x| &x.chunk_type().bytes() == b"IEND")
+            .unwrap_or(self.chunks.len());
+        self.chunks.insert(index, chunk);
+    }
+    /// Signs the image header and data plus every chunk of the `extra` types, replacing any
+    /// previous signature
+    pub(crate) fn sign(&mut self, key: &SigningKey, extra: &[ChunkType]) -> anyhow::Result<()> {
+        self.chunks
+            .retain(|x| &x.chunk_type().bytes() != signature::SIGNATURE_CHUNK);
+        let chunk = signature::sign(&self.chunks, key, extra)?;
+        self.insert_before_end(chunk);
+        Ok(())
+    }
+    /// Reports which chunks the signature covers and whether any of them changed
+    pub(crate) fn verify(&self, keys: &[VerifyingKey]) -> anyhow::Result<Verification> {
+        signature::verify(&self.chunks, keys)
+    }
+    pub(crate) fn ihdr(&self) -> anyhow::Result<Ihdr> {
+        let chunk = self.chunk_by_type("IHDR").context("missing IHDR chunk")?;
+        Ihdr::try_from(chunk.data())
+    }
+    /// Data of every `IDAT` chunk, in order
+    pub(crate) fn image_data(&self) -> Vec<u8> {
+        self.chunks
+            .iter()
+            .filter(|x| &x.chunk_type().bytes() == b"IDAT")
+            .flat_map(|x| x.data().iter().copied())
+            .collect()
+    }
+    /// Replaces the `IDAT` chunks with `data`, split in chunks as large as the largest current one
+    /// but at least 8192 bytes
+    pub(crate) fn set_image_data(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        let is_idat = |x: &Chunk| &x.chunk_type().bytes() == b"IDAT";
+        let first = self
+            .chunks
+            .iter()
+            .position(is_idat)
+            .context("missing IDAT chunk")?;
+        let size = self
+            .chunks
+            .iter()
+            .filter(|x| is_idat(x))
+            .map(|x| x.data().len())
+            .max()
+            .unwrap_or(0)
+            .max(8192);
 
-        Ok(self.0.remove(index))
+        self.chunks.retain(|x| !is_idat(x));
+        let chunk_type: ChunkType = "IDAT".try_into()?;
+        let chunks: Vec<Chunk> = data
+            .chunks(size)
+            .map(|x| Chunk::new(chunk_type, x.to_vec()))
+            .collect();
+        self.chunks.splice(first..first, chunks);
+        Ok(())
     }
     fn header(&self) -> &[u8; 8] {
         Png::STANDARD_HEADER
     }
     pub(crate) fn chunks(&self) -> &[Chunk] {
-        &self.0
+        &self.chunks
     }
     pub(crate) fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-        self.0
+        self.chunks
             .iter()
             .find(|x| x.chunk_type().bytes() == chunk_type.as_bytes())
     }
+    /// Replaces the first chunk with the same type as `chunk`, keeping its position
+    pub(crate) fn replace_chunk(&mut self, chunk: Chunk) -> anyhow::Result<Chunk> {
+        let index = self
+            .chunks
+            .iter()
+            .position(|x| x.chunk_type() == chunk.chunk_type())
+            .context(format!("chunk of type {} not found", chunk.chunk_type()))?;
+
+        Ok(std::mem::replace(&mut self.chunks[index], chunk))
+    }
+    pub(crate) fn exif(&self) -> anyhow::Result<Option<Exif>> {
+        self.chunk_by_type("eXIf")
+            .map(|chunk| Exif::try_from(chunk.data()))
+            .transpose()
+    }
+    /// Writes `exif` to the existing `eXIf` chunk or to a new one placed before the image data
+    pub(crate) fn set_exif(&mut self, exif: &Exif) -> anyhow::Result<()> {
+        self.set_chunk(Chunk::new("eXIf".try_into()?, exif.as_bytes()))
+    }
+    pub(crate) fn physical(&self) -> anyhow::Result<Physical> {
+        Physical::from_chunks(&self.chunks)
+    }
+    /// Writes the resolution in `dpi` dots per inch to `pHYs`
+    pub(crate) fn set_dpi(&mut self, dpi: u32) -> anyhow::Result<()> {
+        let dimensions = PixelDimensions::from_dpi(dpi)?;
+        self.place_before_data(Chunk::new("pHYs".try_into()?, dimensions.as_bytes()));
+        Ok(())
+    }
+    /// Writes `time` to `tIME` as the last modification
+    pub(crate) fn set_time(&mut self, time: Time) -> anyhow::Result<()> {
+        self.place_before_data(Chunk::new("tIME".try_into()?, time.as_bytes()));
+        Ok(())
+    }
+    /// Sets the last modification in `tIME` to now
+    pub(crate) fn touch(&mut self) -> anyhow::Result<()> {
+        self.set_time(Time::now().context("can't read the current time")?)
+    }
+    /// Removes every chunk with the type of `chunk` and places it before the image data
+    fn place_before_data(&mut self, chunk: Chunk) {
+        self.chunks.retain(|x| x.chunk_type() != chunk.chunk_type());
+        self.insert_before_data(chunk);
+    }
+    /// Replaces the chunk with the same type as `chunk` or places it before the image data
+    pub(crate) fn set_chunk(&mut self, chunk: Chunk) -> anyhow::Result<()> {
+        if self
+            .chunks
+            .iter()
+            .any(|x| x.chunk_type() == chunk.chunk_type())
+        {
+            self.replace_chunk(chunk)?;
+        } else {
+            self.insert_before_data(chunk);
+        }
+        Ok(())
+    }
+    pub(crate) fn color(&self) -> anyhow::Result<ColorInfo> {
+        ColorInfo::from_chunks(&self.chunks)
+    }
+    /// Replaces the chunk with the same type as `chunk` or places it before `PLTE` and the image
+    /// data, where color chunks belong
+    pub(crate) fn set_color_chunk(&mut self, chunk: Chunk) -> anyhow::Result<()> {
+        if self
+            .chunks
+            .iter()
+            .any(|x| x.chunk_type() == chunk.chunk_type())
+        {
+            self.replace_chunk(chunk)?;
+            return Ok(());
+        }
+        let index = self
+            .chunks
+            .iter()
+            .position(|x| matches!(&x.chunk_type().bytes(), b"PLTE" | b"IDAT"))
+            .unwrap_or(self.chunks.len().saturating_sub(1));
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
+    /// Inserts `chunk` right before the image data, or before the last chunk when there is none
+    pub(crate) fn insert_before_data(&mut self, chunk: Chunk) {
+        let index = self
+            .chunks
+            .iter()
+            .position(|x| &x.chunk_type().bytes() == b"IDAT")
+            .unwrap_or(self.chunks.len().saturating_sub(1));
+        self.chunks.insert(index, chunk);
+    }
+    /// Index among every chunk of `position`, where an inserted chunk would go
+    pub(crate) fn position(&self, position: Position) -> anyhow::Result<usize> {
+        let find = |chunk_type: &[u8; 4]| {
+            self.chunks
+                .iter()
+                .position(|x| &x.chunk_type().bytes() == chunk_type)
+        };
+        let end = match self.chunks.last() {
+            Some(x) if &x.chunk_type().bytes() == b"IEND" => self.chunks.len() - 1,
+            _ => self.chunks.len(),
+        };
+        Ok(match position {
+            Position::Start => find(b"IHDR").map_or(0, |x| x + 1),
+            Position::BeforeData => find(b"IDAT").unwrap_or(end),
+            Position::AfterData => self
+                .chunks
+                .iter()
+                .rposition(|x| &x.chunk_type().bytes() == b"IDAT")
+                .map_or(end, |x| x + 1),
+            Position::End => end,
+            Position::Index(index) => {
+                ensure!(
+                    index <= self.chunks.len(),
+                    "there are only {} chunks",
+                    self.chunks.len()
+                );
+                index
+            }
+        })
+    }
+    /// Index among every chunk of the `nth` chunk of type `chunk_type`, counting from 0
+    pub(crate) fn find_chunk(&self, chunk_type: &str, nth: usize) -> anyhow::Result<usize> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.chunk_type().bytes() == chunk_type.as_bytes())
+            .nth(nth)
+            .map(|(i, _)| i)
+            .with_context(|| match nth {
+                0 => format!("chunk of type {} not found", chunk_type),
+                _ => format!("no chunk {} of type {}", nth, chunk_type),
+            })
+    }
+    /// Inserts `chunk` at `position` and returns its index
+    pub(crate) fn insert_chunk(
+        &mut self,
+        chunk: Chunk,
+        position: Position,
+    ) -> anyhow::Result<usize> {
+        let index = self.position(position)?;
+        let animation = apng::is_animation_chunk(&chunk);
+        self.chunks.insert(index, chunk);
+        if animation {
+            apng::renumber(self)?;
+        }
+        Ok(index)
+    }
+    /// Parses a possibly corrupted file, keeping chunks whose CRC does not match and dropping
+    /// the ones with an invalid type, and returns how many chunks were damaged
+    pub(crate) fn from_damaged(value: &[u8]) -> anyhow::Result<(Png, usize)> {
+        ensure!(value.starts_with(Png::STANDARD_HEADER), "invalid header");
+
+        let mut chunks = vec![];
+        let mut damaged = 0;
+        let mut i: usize = 8;
+
+        // stops at the first length that does not fit, as nothing after it can be located
+        while let Some(length) = value.get(i..(i + 4)) {
+            // past IEND anything that is not an intact chunk is the trailer, not damage
+            let after_end = chunks
+                .iter()
+                .any(|x: &Chunk| &x.chunk_type().bytes() == b"IEND");
+            if after_end && read_chunk(value, i).is_err() {
+                break;
+            }
+            let length = u32::from_be_bytes(length.try_into()?) as usize;
+            let Some(chunk) = value.get(i..(i + 12).saturating_add(length)) else {
+                damaged += 1;
+                break;
+            };
+            let data = &chunk[8..8 + length];
+            let crc = u32::from_be_bytes(chunk[8 + length..].try_into()?);
+
+            match ChunkType::try_from(&chunk[4..8]) {
+                Ok(chunk_type) => {
+                    let chunk = Chunk::new(chunk_type, data.to_vec());
+                    if chunk.crc() != crc {
+                        damaged += 1;
+                    }
+                    chunks.push(chunk);
+                }
+                Err(_) => damaged += 1,
+            }
+            i += 12 + length;
+        }
+
+        // a length that does not fit after IEND is part of the trailer too
+        let trailer = match chunks.iter().any(|x| &x.chunk_type().bytes() == b"IEND") {
+            true => value.get(i..).unwrap_or_default().to_vec(),
+            false => vec![],
+        };
+        Ok((Png { chunks, trailer }, damaged))
+    }
+    pub(crate) fn trailer(&self) -> &[u8] {
+        &self.trailer
+    }
+    pub(crate) fn set_trailer(&mut self, trailer: Vec<u8>) {
+        self.trailer = trailer;
+    }
+    /// Adds `data` at the end of the trailer, after every chunk
+    pub(crate) fn append(&mut self, data: &[u8]) {
+        self.trailer.extend(data);
+    }
     pub(crate) fn as_bytes(&self) -> Vec<u8> {
-        let chunk_bytes: Vec<u8> = self.0.iter().flat_map(|chunk| chunk.as_bytes()).collect();
+        let chunk_bytes: Vec<u8> = self
+            .chunks
+            .iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
 
-        [Png::STANDARD_HEADER.to_vec(), chunk_bytes].concat()
+        [
+            Png::STANDARD_HEADER.to_vec(),
+            chunk_bytes,
+            self.trailer.clone(),
+        ]
+        .concat()
     }
 }
 
@@ -54,23 +445,38 @@ impl TryFrom<&[u8]> for Png {
 
         let mut chunks = vec![];
         let mut i: usize = 8;
+        let mut after_end = false;
 
         while i < value.len() {
-            let length = value.get(i..(i + 4)).context("invalid chunk")?;
-            let length = TryInto::<[u8; 4]>::try_into(length)?;
-            let length = u32::from_be_bytes(length);
-            let end = i + 12 + length as usize;
-
-            let chunk = value.get(i..end).context("invalid chunk")?;
-            let chunk = TryInto::<Chunk>::try_into(chunk)?;
+            let chunk = read_chunk(value, i);
+            // whatever follows IEND and is not a chunk is appended data
+            if after_end && chunk.is_err() {
+                break;
+            }
+            let (chunk, end) = chunk?;
+            after_end |= &chunk.chunk_type().bytes() == b"IEND";
             chunks.push(chunk);
             i = end;
         }
 
-        Ok(Png(chunks))
+        Ok(Png {
+            chunks,
+            trailer: value[i..].to_vec(),
+        })
     }
 }
 
+/// The chunk starting at `i` and where it ends
+fn read_chunk(value: &[u8], i: usize) -> anyhow::Result<(Chunk, usize)> {
+    let length = value.get(i..(i + 4)).context("invalid chunk")?;
+    let length = TryInto::<[u8; 4]>::try_into(length)?;
+    let length = u32::from_be_bytes(length);
+    let end = (i + 12).saturating_add(length as usize);
+
+    let chunk = value.get(i..end).context("invalid chunk")?;
+    Ok((TryInto::<Chunk>::try_into(chunk)?, end))
+}
+
 impl fmt::Display for Png {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let result: String = self.chunks().iter().map(|it| format!("{}\n", it)).collect();
@@ -84,16 +490,16 @@ mod tests {
     use super::*;
     use crate::chunk::Chunk;
     use crate::chunk_type::ChunkType;
+    use crate::{decoder::Image, ihdr::ColorType};
     use std::convert::TryFrom;
+    use std::str::FromStr;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -209,17 +615,238 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_replace_chunk() {
+        let mut png = testing_png();
+        let old = png
+            .replace_chunk(chunk_from_strings("miDl", "Replaced").unwrap())
+            .unwrap();
+        assert_eq!(&old.data_as_string().unwrap(), "I am another chunk");
+        assert_eq!(&png.chunks()[1].data_as_string().unwrap(), "Replaced");
+        assert!(png
+            .replace_chunk(chunk_from_strings("TeSt", "Message").unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_sign_image_file() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let key = SigningKey::from_bytes(&[1; 32]);
+        png.sign(&key, &[]).unwrap();
+        png.sign(&key, &[ChunkType::from_str("RuSt").unwrap()])
+            .unwrap();
+
+        let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        let signatures = png
+            .chunks()
+            .iter()
+            .filter(|x| &x.chunk_type().bytes() == signature::SIGNATURE_CHUNK)
+            .count();
+        assert_eq!(signatures, 1);
+        assert_eq!(
+            &png.chunks()[png.chunks().len() - 1].chunk_type().bytes(),
+            b"IEND"
+        );
+
+        let verification = png.verify(&[key.verifying_key()]).unwrap();
+        assert!(verification.is_intact());
+        assert_eq!(verification.chunks.len(), 3);
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
         assert!(png.is_ok());
     }
 
+    #[test]
+    fn test_png_from_damaged_file() {
+        let mut bytes = testing_png().as_bytes();
+        // flip a byte of the second chunk data, then truncate the last chunk
+        bytes[8 + 32 + 10] ^= 1;
+        bytes.truncate(bytes.len() - 3);
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+
+        let (png, damaged) = Png::from_damaged(&bytes).unwrap();
+        assert_eq!(damaged, 2);
+        assert_eq!(png.chunks().len(), 2);
+        assert_eq!(png.chunks()[0].as_bytes(), testing_chunks()[0].as_bytes());
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "miDl");
+    }
+
+    #[test]
+    fn test_positions() {
+        let mut png = Png::from_chunks(
+            ["IHDR", "tEXt", "IDAT", "IDAT", "IEND"]
+                .iter()
+                .map(|x| chunk_from_strings(x, "").unwrap())
+                .collect(),
+        );
+        assert_eq!(png.position(Position::Start).unwrap(), 1);
+        assert_eq!(png.position(Position::BeforeData).unwrap(), 2);
+        assert_eq!(png.position(Position::AfterData).unwrap(), 4);
+        assert_eq!(png.position(Position::End).unwrap(), 4);
+        assert!(png.position(Position::Index(6)).is_err());
+        assert_eq!(
+            Position::from_str("after-data").unwrap(),
+            Position::AfterData
+        );
+        assert_eq!(Position::from_str("3").unwrap(), Position::Index(3));
+        assert!(Position::from_str("middle").is_err());
+
+        assert_eq!(png.find_chunk("IDAT", 1).unwrap(), 3);
+        assert!(png.find_chunk("IDAT", 2).is_err());
+
+        let chunk = chunk_from_strings("teSt", "data").unwrap();
+        assert_eq!(png.insert_chunk(chunk, Position::Start).unwrap(), 1);
+        assert_eq!(png.chunks()[1].data(), b"data");
+    }
+
+    #[test]
+    fn test_edit_chunks() {
+        let mut png = Png::from_chunks(
+            ["IHDR", "IDAT", "tEXt", "zTXt", "IEND"]
+                .iter()
+                .map(|x| chunk_from_strings(x, x).unwrap())
+                .collect(),
+        );
+        let types = |png: &Png| {
+            png.chunks()
+                .iter()
+                .map(|x| x.chunk_type().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(png.move_chunk(3, Position::BeforeData).unwrap(), 1);
+        assert_eq!(types(&png), ["IHDR", "zTXt", "IDAT", "tEXt", "IEND"]);
+        assert!(png.move_chunk(3, Position::Index(5)).is_err());
+        assert_eq!(types(&png), ["IHDR", "zTXt", "IDAT", "tEXt", "IEND"]);
+
+        png.rename_chunk(3, ChunkType::from_str("teXt").unwrap())
+            .unwrap();
+        assert_eq!(png.chunks()[3].data(), b"tEXt");
+        assert_eq!(
+            png.chunks()[3].crc(),
+            chunk_from_strings("teXt", "tEXt").unwrap().crc()
+        );
+
+        assert_eq!(png.copy_chunk(1, Position::End).unwrap(), 4);
+        let removed = png
+            .remove_chunks(|x| &x.chunk_type().bytes() == b"zTXt")
+            .unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(png.remove_at(2).unwrap().chunk_type().to_string(), "teXt");
+        assert_eq!(types(&png), ["IHDR", "IDAT", "IEND"]);
+        assert!(png.remove_at(3).is_err());
+
+        // frame chunks keep counting up from 0 in the order they appear
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Rgb).unwrap();
+        let frames: Vec<Image> = [[1, 2, 3], [4, 5, 6]]
+            .iter()
+            .map(|x| Image::from_samples(ihdr, x).unwrap())
+            .collect();
+        let mut png = apng::assemble(&frames, 100, 0).unwrap();
+        let sequence = |png: &Png| {
+            png.chunks()
+                .iter()
+                .filter(|x| apng::is_animation_chunk(x))
+                .map(|x| u32::from_be_bytes(x.data()[..4].try_into().unwrap()))
+                .collect::<Vec<_>>()
+        };
+        let frame_count = |png: &Png| png.chunk_by_type("acTL").unwrap().data()[3];
+
+        png.copy_chunk(png.find_chunk("fdAT", 0).unwrap(), Position::End)
+            .unwrap();
+        assert_eq!(sequence(&png), [0, 1, 2, 3]);
+        png.rename_chunk(png.find_chunk("fcTL", 1).unwrap(), "ruSt".parse().unwrap())
+            .unwrap();
+        assert_eq!(sequence(&png), [0, 1, 2]);
+        assert_eq!(frame_count(&png), 1);
+        let moved = png
+            .move_chunk(png.find_chunk("fcTL", 0).unwrap(), Position::End)
+            .unwrap();
+        assert_eq!(sequence(&png), [0, 1, 2]);
+        assert_eq!(png.chunks()[moved].data()[..4], [0, 0, 0, 2]);
+        png.rename_chunk(png.find_chunk("ruSt", 0).unwrap(), "fcTL".parse().unwrap())
+            .unwrap();
+        assert_eq!(frame_count(&png), 2);
+    }
+
+    #[test]
+    fn test_trailer() {
+        let mut chunks = testing_chunks();
+        chunks.push(chunk_from_strings("IEND", "").unwrap());
+        let mut bytes = Png::from_chunks(chunks.clone()).as_bytes();
+        bytes.extend(b"PK\x03\x04 appended");
+        let mut png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png.trailer(), b"PK\x03\x04 appended");
+        assert_eq!(png.as_bytes(), bytes);
+
+        png.append(b" and more");
+        png.set_chunks(chunks);
+        assert_eq!(png.trailer(), b"PK\x03\x04 appended and more");
+
+        let (png, damaged) = Png::from_damaged(&png.as_bytes()).unwrap();
+        assert_eq!(damaged, 0);
+        assert_eq!(png.trailer(), b"PK\x03\x04 appended and more");
+    }
+
+    #[test]
+    fn test_chunk_after_end() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("IEND", "").unwrap());
+        png.append_chunk(chunk_from_strings("teXt", "old").unwrap());
+        let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(png.chunks().len(), 5);
+        assert!(png.trailer().is_empty());
+    }
+
+    #[test]
+    fn test_physical_chunks() {
+        let mut png = Png::from_chunks(
+            ["IHDR", "IDAT", "pHYs", "IEND"]
+                .iter()
+                .map(|x| chunk_from_strings(x, "").unwrap())
+                .collect(),
+        );
+        png.set_dpi(72).unwrap();
+        png.touch().unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|x| x.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "pHYs", "tIME", "IDAT", "IEND"]);
+
+        png.set_dpi(300).unwrap();
+        png.set_time(Time::new(2024, 5, 17, 13, 45, 0).unwrap())
+            .unwrap();
+        assert_eq!(png.chunks().len(), 5);
+        let physical = png.physical().unwrap();
+        assert_eq!(
+            physical.dimensions,
+            Some(PixelDimensions::from_dpi(300).unwrap())
+        );
+        assert_eq!(physical.time.unwrap().year, 2024);
+        assert!(png.set_dpi(0).is_err());
+    }
+
+    #[test]
+    fn test_decode_image_file() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let image = crate::decoder::decode(&png).unwrap();
+        let pixel = (25 * 50 + 25) * 4;
+
+        assert_eq!(image.data().len(), 50 * 50 * 4);
+        assert_eq!(&image.data()[pixel..pixel + 4], &[240, 240, 240, 255]);
+        assert_eq!(crate::zlib::adler32(image.data()), 4117389930);
+    }
+
     #[test]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
         let actual = png.as_bytes();
-        let expected: Vec<u8> = PNG_FILE.iter().copied().collect();
+        let expected: Vec<u8> = PNG_FILE.to_vec();
         assert_eq!(actual.len(), expected.len());
         assert_eq!(actual, expected);
     }
@@ -0,0 +1,303 @@
+//! Files that are a png and a ZIP archive at once. Decoders read a png from its start and stop at
+//! `IEND`, while ZIP readers look for the end of central directory record at the end of the file,
+//! so an archive appended after `IEND` opens with both as long as its offsets count from the start
+//! of the file rather than from the start of the archive.
+
+use crate::{chunk::HDLC, png::Png, zlib};
+use anyhow::{bail, ensure, Context};
+
+const LOCAL_HEADER: &[u8; 4] = b"PK\x03\x04";
+const CENTRAL_HEADER: &[u8; 4] = b"PK\x01\x02";
+const END_OF_DIRECTORY: &[u8; 4] = b"PK\x05\x06";
+/// Size of the end of central directory record without its comment
+const END_SIZE: usize = 22;
+/// Size of a central directory header without its name, extra field and comment
+const CENTRAL_SIZE: usize = 46;
+/// Size of a local file header without its name and extra field
+const LOCAL_SIZE: usize = 30;
+/// ZIP 2.0, the first version with deflate
+const VERSION: u16 = 20;
+/// Names are UTF-8
+const UTF8_FLAG: u16 = 0x0800;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+/// 1980-01-01 00:00 in MS-DOS format, the earliest date a ZIP entry can have
+const DOS_DATE: u16 = 0x0021;
+
+/// Signatures of archives worth reporting when found inside a png
+pub const ARCHIVE_SIGNATURES: &[(&[u8], &str)] = &[
+    (LOCAL_HEADER, "ZIP archive"),
+    (END_OF_DIRECTORY, "ZIP central directory"),
+    (b"Rar!\x1a\x07", "RAR archive"),
+    (b"7z\xbc\xaf\x27\x1c", "7z archive"),
+];
+
+/// A file in a ZIP archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    /// offset of the local file header from the start of the file
+    pub offset: u32,
+    pub size: u32,
+    pub compressed_size: u32,
+}
+
+/// Where each kind of archive first appears in `data`
+pub fn find_archives(data: &[u8]) -> Vec<(usize, &'static str)> {
+    ARCHIVE_SIGNATURES
+        .iter()
+        .filter_map(|(signature, kind)| {
+            data.windows(signature.len())
+                .position(|x| x == *signature)
+                .map(|offset| (offset, *kind))
+        })
+        .collect()
+}
+
+/// Writes a ZIP archive of `files`, named by their first element, deflating the ones that shrink
+pub fn zip(files: &[(String, Vec<u8>)]) -> anyhow::Result<Vec<u8>> {
+    ensure!(
+        files.len() < u16::MAX as usize,
+        "too many files for a ZIP archive"
+    );
+    let mut archive = vec![];
+    let mut directory = vec![];
+
+    for (i, (name, data)) in files.iter().enumerate() {
+        ensure!(
+            files[..i].iter().all(|(x, _)| x != name),
+            "more than one file named {}",
+            name
+        );
+        let name_length: u16 = name.len().try_into().context("file name too long")?;
+        let size: u32 = data
+            .len()
+            .try_into()
+            .context("file too large for a ZIP archive")?;
+        let offset: u32 = archive.len().try_into().context("archive too large")?;
+
+        // a raw deflate stream is the zlib stream without its 2 byte header and adler32
+        let deflated = zlib::compress_level(data, 9);
+        let (method, stored) = match &deflated[2..deflated.len() - 4] {
+            x if x.len() < data.len() => (DEFLATED, x),
+            _ => (STORED, &data[..]),
+        };
+        let crc = HDLC.checksum(data);
+
+        // both headers share everything from the version needed to the name length
+        let mut common = vec![];
+        common.extend(VERSION.to_le_bytes());
+        common.extend(UTF8_FLAG.to_le_bytes());
+        common.extend(method.to_le_bytes());
+        common.extend(0u16.to_le_bytes());
+        common.extend(DOS_DATE.to_le_bytes());
+        common.extend(crc.to_le_bytes());
+        common.extend((stored.len() as u32).to_le_bytes());
+        common.extend(size.to_le_bytes());
+        common.extend(name_length.to_le_bytes());
+        common.extend(0u16.to_le_bytes());
+
+        archive.extend(LOCAL_HEADER);
+        archive.extend(&common);
+        archive.extend(name.as_bytes());
+        archive.extend(stored);
+
+        directory.extend(CENTRAL_HEADER);
+        directory.extend(VERSION.to_le_bytes());
+        directory.extend(&common);
+        // comment length, disk, internal and external attributes
+        directory.extend([0; 10]);
+        directory.extend(offset.to_le_bytes());
+        directory.extend(name.as_bytes());
+    }
+
+    let directory_offset: u32 = archive.len().try_into().context("archive too large")?;
+    archive.extend(&directory);
+    archive.extend(END_OF_DIRECTORY);
+    archive.extend([0; 4]);
+    archive.extend((files.len() as u16).to_le_bytes());
+    archive.extend((files.len() as u16).to_le_bytes());
+    archive.extend((directory.len() as u32).to_le_bytes());
+    archive.extend(directory_offset.to_le_bytes());
+    archive.extend([0; 2]);
+    Ok(archive)
+}
+
+/// Where the end of central directory record of the archive ending `data` starts
+fn end_of_directory(data: &[u8]) -> anyhow::Result<usize> {
+    ensure!(
+        data.len() >= END_SIZE,
+        "no ZIP archive at the end of the file"
+    );
+    // the record is followed by a comment of at most 65535 bytes
+    let start = data.len().saturating_sub(END_SIZE + u16::MAX as usize);
+    (start..=data.len().saturating_sub(END_SIZE))
+        .rev()
+        .find(|&i| {
+            let comment = u16::from_le_bytes([data[i + 20], data[i + 21]]) as usize;
+            &data[i..i + 4] == END_OF_DIRECTORY && i + END_SIZE + comment == data.len()
+        })
+        .context("no ZIP archive at the end of the file")
+}
+
+fn u16_at(data: &[u8], i: usize) -> anyhow::Result<u16> {
+    let bytes = data.get(i..i + 2).context("truncated ZIP archive")?;
+    Ok(u16::from_le_bytes(bytes.try_into()?))
+}
+
+fn u32_at(data: &[u8], i: usize) -> anyhow::Result<u32> {
+    let bytes = data.get(i..i + 4).context("truncated ZIP archive")?;
+    Ok(u32::from_le_bytes(bytes.try_into()?))
+}
+
+/// Central directory of the archive ending `data`: its offset, size and where each header starts
+fn directory(data: &[u8]) -> anyhow::Result<(usize, Vec<usize>)> {
+    let end = end_of_directory(data)?;
+    let count = u16_at(data, end + 10)? as usize;
+    let size = u32_at(data, end + 12)? as usize;
+    let offset = u32_at(data, end + 16)? as usize;
+    if offset == u32::MAX as usize {
+        bail!("ZIP64 archives are not supported");
+    }
+    ensure!(
+        offset + size <= end,
+        "the central directory is outside of the archive"
+    );
+
+    let mut headers = vec![];
+    let mut i = offset;
+    for _ in 0..count {
+        ensure!(
+            data.get(i..i + 4) == Some(CENTRAL_HEADER),
+            "invalid central directory header"
+        );
+        headers.push(i);
+        i += CENTRAL_SIZE
+            + u16_at(data, i + 28)? as usize
+            + u16_at(data, i + 30)? as usize
+            + u16_at(data, i + 32)? as usize;
+    }
+    Ok((end, headers))
+}
+
+/// Every file of the ZIP archive at the end of `data`
+pub fn list(data: &[u8]) -> anyhow::Result<Vec<Entry>> {
+    let (_, headers) = directory(data)?;
+    headers
+        .into_iter()
+        .map(|i| {
+            let name_length = u16_at(data, i + 28)? as usize;
+            let name = data
+                .get(i + CENTRAL_SIZE..i + CENTRAL_SIZE + name_length)
+                .context("truncated ZIP archive")?;
+            let entry = Entry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                offset: u32_at(data, i + 42)?,
+                size: u32_at(data, i + 24)?,
+                compressed_size: u32_at(data, i + 20)?,
+            };
+            ensure!(
+                data.get(entry.offset as usize..entry.offset as usize + 4) == Some(LOCAL_HEADER),
+                "no local header for {}",
+                entry.name
+            );
+            Ok(entry)
+        })
+        .collect()
+}
+
+/// Moves the offsets of the ZIP `archive` forward by `prefix` bytes, for it to be read after them
+pub fn relocate(archive: &[u8], prefix: usize) -> anyhow::Result<Vec<u8>> {
+    let (end, headers) = directory(archive)?;
+    let prefix: u32 = prefix.try_into().context("too large for a ZIP archive")?;
+    let shift = |data: &mut Vec<u8>, i: usize| -> anyhow::Result<()> {
+        let offset = u32_at(data, i)?
+            .checked_add(prefix)
+            .filter(|&x| x != u32::MAX)
+            .context("too large for a ZIP archive")?;
+        data[i..i + 4].copy_from_slice(&offset.to_le_bytes());
+        Ok(())
+    };
+
+    let mut relocated = archive.to_vec();
+    for i in headers {
+        shift(&mut relocated, i + 42)?;
+    }
+    shift(&mut relocated, end + 16)?;
+    Ok(relocated)
+}
+
+/// Appends the ZIP `archive` after every byte of `png`, which then opens as both
+pub fn append(png: &mut Png, archive: &[u8]) -> anyhow::Result<()> {
+    ensure!(
+        png.chunks()
+            .last()
+            .is_some_and(|x| &x.chunk_type().bytes() == b"IEND"),
+        "the png needs to end with IEND"
+    );
+    let archive = relocate(archive, png.as_bytes().len())?;
+    png.append(&archive);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    fn testing_files() -> Vec<(String, Vec<u8>)> {
+        vec![
+            (
+                "notes.txt".to_owned(),
+                b"hello hello hello hello hello".to_vec(),
+            ),
+            ("key.bin".to_owned(), vec![7, 1]),
+        ]
+    }
+
+    #[test]
+    fn test_zip() {
+        let archive = zip(&testing_files()).unwrap();
+        let entries = list(&archive).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "notes.txt");
+        assert_eq!(entries[0].offset, 0);
+        assert_eq!(entries[0].size, 29);
+        assert!(entries[0].compressed_size < 29);
+        assert_eq!(entries[1].compressed_size, 2);
+
+        // the deflated data inflates back to the file
+        let start = LOCAL_SIZE + "notes.txt".len();
+        let (data, _) = zlib::inflate(&archive[start..]).unwrap();
+        assert_eq!(data, testing_files()[0].1);
+
+        let twice = [testing_files(), testing_files()].concat();
+        assert!(zip(&twice).is_err());
+    }
+
+    #[test]
+    fn test_polyglot() {
+        let mut png = Png::from_chunks(vec![
+            Chunk::new("IHDR".try_into().unwrap(), vec![0; 13]),
+            Chunk::new("IEND".try_into().unwrap(), vec![]),
+        ]);
+        let prefix = png.as_bytes().len();
+        append(&mut png, &zip(&testing_files()).unwrap()).unwrap();
+
+        let bytes = png.as_bytes();
+        let entries = list(&bytes).unwrap();
+        assert_eq!(entries[0].offset as usize, prefix);
+        assert!(list(&bytes[prefix..]).is_err());
+
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png.chunks().len(), 2);
+        assert_eq!(find_archives(png.trailer())[0], (0, "ZIP archive"));
+    }
+
+    #[test]
+    fn test_find_archives() {
+        assert!(find_archives(b"nothing here").is_empty());
+        let found = find_archives(b"..Rar!\x1a\x07\x01..PK\x03\x04");
+        assert_eq!(found, vec![(11, "ZIP archive"), (2, "RAR archive")]);
+    }
+}
@@ -0,0 +1,265 @@
+use crate::gf256;
+use anyhow::{bail, ensure};
+
+/// Longest codeword, data and parity symbols together
+pub const MAX_CODEWORD: usize = 255;
+
+/// Systematic Reed-Solomon code over GF(256) with `parity` check symbols, which corrects any mix
+/// of `e` errors and `f` erasures in a codeword as long as `2e + f <= parity`
+#[derive(Debug, Clone)]
+pub struct ReedSolomon {
+    parity: usize,
+    generator: Vec<u8>,
+}
+
+impl ReedSolomon {
+    pub fn new(parity: usize) -> anyhow::Result<ReedSolomon> {
+        ensure!(
+            (1..MAX_CODEWORD).contains(&parity),
+            "parity symbols must be between 1 and {}",
+            MAX_CODEWORD - 1
+        );
+        let generator = (0..parity).fold(vec![1], |g, i| gf256::poly_mul(&g, &[1, gf256::exp(i)]));
+        Ok(ReedSolomon { parity, generator })
+    }
+    pub fn parity(&self) -> usize {
+        self.parity
+    }
+    /// Most data symbols that fit in a codeword
+    pub fn max_data(&self) -> usize {
+        MAX_CODEWORD - self.parity
+    }
+    /// Returns `data` followed by its parity symbols
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        assert!(
+            data.len() <= self.max_data(),
+            "data too long for a codeword"
+        );
+
+        let mut codeword = [data, &vec![0; self.parity]].concat();
+        for i in 0..data.len() {
+            let coef = codeword[i];
+            if coef != 0 {
+                for (j, &g) in self.generator.iter().enumerate().skip(1) {
+                    codeword[i + j] ^= gf256::mul(g, coef);
+                }
+            }
+        }
+        codeword[..data.len()].copy_from_slice(data);
+        codeword
+    }
+    /// Corrects `codeword` in place, given the positions of symbols known to be lost, and returns
+    /// how many symbols were repaired
+    pub fn decode(&self, codeword: &mut [u8], erasures: &[usize]) -> anyhow::Result<usize> {
+        ensure!(
+            codeword.len() > self.parity && codeword.len() <= MAX_CODEWORD,
+            "invalid codeword length `{}`",
+            codeword.len()
+        );
+        ensure!(
+            erasures.len() <= self.parity,
+            "too many erasures to correct"
+        );
+        for &position in erasures {
+            codeword[position] = 0;
+        }
+
+        let syndromes = self.syndromes(codeword);
+        if syndromes.iter().all(|&x| x == 0) {
+            return Ok(erasures.len());
+        }
+
+        let forney = self.forney_syndromes(&syndromes, erasures, codeword.len());
+        let locator = self.error_locator(&forney, erasures.len())?;
+        let mut errata = erasures.to_vec();
+        errata.extend(find_errors(&locator, codeword.len())?);
+
+        self.correct(codeword, &syndromes, &errata)?;
+        if self.syndromes(codeword).iter().any(|&x| x != 0) {
+            bail!("codeword is too damaged to be corrected");
+        }
+        Ok(errata.len())
+    }
+    /// Syndromes of the received word, with a leading zero used by the errata evaluator
+    fn syndromes(&self, codeword: &[u8]) -> Vec<u8> {
+        std::iter::once(0)
+            .chain((0..self.parity).map(|i| gf256::eval(codeword, gf256::exp(i))))
+            .collect()
+    }
+    /// Syndromes with the contribution of the erasures removed, so only errors remain to locate
+    fn forney_syndromes(&self, syndromes: &[u8], erasures: &[usize], len: usize) -> Vec<u8> {
+        let mut forney = syndromes[1..].to_vec();
+        for &position in erasures {
+            let x = gf256::exp(len - 1 - position);
+            for j in 0..forney.len() - 1 {
+                forney[j] = gf256::mul(forney[j], x) ^ forney[j + 1];
+            }
+        }
+        forney
+    }
+    /// Berlekamp-Massey, returning the error locator polynomial highest degree first
+    fn error_locator(&self, syndromes: &[u8], erasure_count: usize) -> anyhow::Result<Vec<u8>> {
+        let mut locator = vec![1];
+        let mut old = vec![1];
+
+        for k in 0..self.parity - erasure_count {
+            let mut delta = syndromes[k];
+            for j in 1..locator.len() {
+                delta ^= gf256::mul(locator[locator.len() - 1 - j], syndromes[k - j]);
+            }
+            old.push(0);
+            if delta != 0 {
+                if old.len() > locator.len() {
+                    let new = gf256::poly_scale(&old, delta);
+                    old = gf256::poly_scale(&locator, gf256::inv(delta));
+                    locator = new;
+                }
+                locator = gf256::poly_add(&locator, &gf256::poly_scale(&old, delta));
+            }
+        }
+
+        let leading = locator.iter().take_while(|&&x| x == 0).count();
+        locator.drain(..leading);
+        let errors = locator.len() - 1;
+        ensure!(
+            errors * 2 + erasure_count <= self.parity,
+            "codeword is too damaged to be corrected"
+        );
+        Ok(locator)
+    }
+    /// Computes the errata magnitudes with the Forney algorithm and applies them
+    fn correct(
+        &self,
+        codeword: &mut [u8],
+        syndromes: &[u8],
+        errata: &[usize],
+    ) -> anyhow::Result<()> {
+        let len = codeword.len();
+        let coefficients: Vec<usize> = errata.iter().map(|&p| len - 1 - p).collect();
+
+        let locator = coefficients.iter().fold(vec![1], |acc, &c| {
+            gf256::poly_mul(&acc, &[gf256::exp(c), 1])
+        });
+        let reversed: Vec<u8> = syndromes.iter().rev().copied().collect();
+        let product = gf256::poly_mul(&reversed, &locator);
+        let evaluator = &product[product.len().saturating_sub(locator.len())..];
+
+        let locations: Vec<u8> = coefficients.iter().map(|&c| gf256::exp(c)).collect();
+        for (i, &x) in locations.iter().enumerate() {
+            let x_inv = gf256::inv(x);
+            let derivative = locations
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .fold(1, |acc, (_, &other)| {
+                    gf256::mul(acc, 1 ^ gf256::mul(x_inv, other))
+                });
+            ensure!(derivative != 0, "codeword is too damaged to be corrected");
+
+            let y = gf256::mul(x, gf256::eval(evaluator, x_inv));
+            codeword[errata[i]] ^= gf256::div(y, derivative);
+        }
+        Ok(())
+    }
+}
+
+/// Positions whose locations are roots of the error locator, found by exhaustive search
+fn find_errors(locator: &[u8], len: usize) -> anyhow::Result<Vec<usize>> {
+    let reversed: Vec<u8> = locator.iter().rev().copied().collect();
+    let positions: Vec<usize> = (0..len)
+        .filter(|&i| gf256::eval(&reversed, gf256::exp(i)) == 0)
+        .map(|i| len - 1 - i)
+        .collect();
+    ensure!(
+        positions.len() == locator.len() - 1,
+        "codeword is too damaged to be corrected"
+    );
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message() -> Vec<u8> {
+        b"This is where your secret message will be!".to_vec()
+    }
+
+    #[test]
+    fn test_encode_is_systematic() {
+        let rs = ReedSolomon::new(10).unwrap();
+        let codeword = rs.encode(&message());
+        assert_eq!(codeword.len(), message().len() + 10);
+        assert_eq!(&codeword[..message().len()], message());
+        assert!(rs.syndromes(&codeword).iter().all(|&x| x == 0));
+    }
+
+    #[test]
+    fn test_correct_errors() {
+        let rs = ReedSolomon::new(10).unwrap();
+        let mut codeword = rs.encode(&message());
+        for i in [0, 7, 20, 41, 50] {
+            codeword[i] ^= 0x5a;
+        }
+        assert_eq!(rs.decode(&mut codeword, &[]).unwrap(), 5);
+        assert_eq!(codeword, rs.encode(&message()));
+    }
+
+    #[test]
+    fn test_correct_errors_and_erasures() {
+        let rs = ReedSolomon::new(10).unwrap();
+        let mut codeword = rs.encode(&message());
+        let erasures = [1, 2, 3, 30, 45, 51];
+        for &i in &erasures {
+            codeword[i] = 0xff;
+        }
+        codeword[10] ^= 1;
+        codeword[33] ^= 0x80;
+        assert_eq!(rs.decode(&mut codeword, &erasures).unwrap(), 8);
+        assert_eq!(codeword, rs.encode(&message()));
+    }
+
+    #[test]
+    fn test_full_length_codeword() {
+        let rs = ReedSolomon::new(32).unwrap();
+        let data: Vec<u8> = (0..rs.max_data()).map(|x| (x * 7) as u8).collect();
+        let mut codeword = rs.encode(&data);
+        let erasures: Vec<usize> = (100..120).collect();
+        for i in (100..120).chain([0, 200, 254]) {
+            codeword[i] = !codeword[i];
+        }
+        assert_eq!(rs.decode(&mut codeword, &erasures).unwrap(), 23);
+        assert_eq!(&codeword[..data.len()], data);
+    }
+
+    #[test]
+    fn test_too_many_errors() {
+        // the code is linear and its lightest codewords differ in 5 symbols, so applying 3 of
+        // them leaves the word 2 symbols away from the other codeword, which it decodes to
+        let rs = ReedSolomon::new(4).unwrap();
+        let mut unit = vec![0; message().len()];
+        unit[0] = 1;
+        let difference = rs.encode(&unit);
+        let positions: Vec<usize> = (0..difference.len())
+            .filter(|&i| difference[i] != 0)
+            .collect();
+        assert_eq!(positions.len(), 5);
+
+        let mut codeword = rs.encode(&message());
+        let wrong: Vec<u8> = codeword
+            .iter()
+            .zip(&difference)
+            .map(|(x, y)| x ^ y)
+            .collect();
+        for &i in &positions[..3] {
+            codeword[i] ^= difference[i];
+        }
+        assert_eq!(rs.decode(&mut codeword, &[]).unwrap(), 2);
+        assert_eq!(codeword, wrong);
+
+        let mut codeword = rs.encode(&message());
+        assert!(rs.decode(&mut codeword, &[0, 1, 2, 3, 4]).is_err());
+        assert!(ReedSolomon::new(0).is_err());
+        assert!(ReedSolomon::new(255).is_err());
+    }
+}
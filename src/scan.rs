@@ -0,0 +1,512 @@
+use crate::{
+    chunk::{Chunk, HDLC},
+    chunk_type::ChunkType,
+    png::Png,
+    polyglot,
+};
+use anyhow::ensure;
+use std::fmt;
+
+/// Ancillary chunks bigger than this are unusual outside of image data and color profiles
+const LARGE_ANCILLARY: usize = 64 * 1024;
+/// Compressed text bigger than this rarely holds just a comment
+const LARGE_TEXT: usize = 8 * 1024;
+/// Entropy, in bits per byte, above which uncompressed data looks encrypted or compressed
+const HIGH_ENTROPY: f64 = 7.2;
+/// Below this size the entropy estimate is too noisy to mean anything
+const MIN_ENTROPY_SAMPLE: usize = 256;
+
+const KNOWN_CHUNKS: &[&[u8; 4]] = &[
+    b"IHDR", b"PLTE", b"IDAT", b"IEND", b"tRNS", b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB",
+    b"cICP", b"mDCv", b"cLLi", b"tEXt", b"zTXt", b"iTXt", b"bKGD", b"hIST", b"pHYs", b"sPLT",
+    b"eXIf", b"tIME", b"acTL", b"fcTL", b"fdAT", b"oFFs", b"pCAL", b"sCAL", b"gIFg", b"gIFx",
+    b"sTER", b"dSIG",
+];
+/// Chunks whose data is compressed by design, so high entropy is expected
+const COMPRESSED_CHUNKS: &[&[u8; 4]] = &[b"IDAT", b"fdAT", b"zTXt", b"iCCP", b"iTXt"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Risk {
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug)]
+pub struct ChunkInfo {
+    pub offset: usize,
+    pub chunk_type: ChunkType,
+    pub length: usize,
+    pub crc_ok: bool,
+}
+
+#[derive(Debug)]
+pub struct Finding {
+    pub offset: usize,
+    pub risk: Risk,
+    pub kind: FindingKind,
+}
+
+#[derive(Debug)]
+pub enum FindingKind {
+    PrivateChunk(ChunkType),
+    UnknownChunk(ChunkType),
+    ChunkAfterIend(ChunkType),
+    TrailingData {
+        length: usize,
+    },
+    LargeAncillary {
+        chunk_type: ChunkType,
+        length: usize,
+    },
+    LargeText {
+        chunk_type: ChunkType,
+        length: usize,
+    },
+    HighEntropy {
+        chunk_type: ChunkType,
+        entropy: f64,
+    },
+    /// the signature of an archive, in a chunk or after `IEND` when there is no chunk type
+    EmbeddedArchive {
+        chunk_type: Option<ChunkType>,
+        kind: &'static str,
+    },
+    CrcMismatch(ChunkType),
+    Malformed(String),
+}
+
+/// Everything suspicious found in a file, see [`scan`]
+#[derive(Debug)]
+pub struct ScanReport {
+    pub size: usize,
+    pub chunks: Vec<ChunkInfo>,
+    pub findings: Vec<Finding>,
+}
+
+impl ScanReport {
+    /// The highest risk among the findings, several medium findings together count as high
+    pub fn risk(&self) -> Risk {
+        let medium = self
+            .findings
+            .iter()
+            .filter(|finding| finding.risk == Risk::Medium)
+            .count();
+        let highest = self
+            .findings
+            .iter()
+            .map(|finding| finding.risk)
+            .max()
+            .unwrap_or(Risk::None);
+
+        if medium >= 3 {
+            Risk::High
+        } else {
+            highest
+        }
+    }
+}
+
+/// Walks the raw chunk layout of `bytes` looking for places where a payload could hide.
+///
+/// Unlike `Png::try_from` this never gives up on a file after its signature was checked:
+/// anything after `IEND` or that does not parse as a chunk is reported instead.
+pub fn scan(bytes: &[u8]) -> anyhow::Result<ScanReport> {
+    ensure!(
+        bytes.len() >= 8 && &bytes[0..8] == Png::STANDARD_HEADER,
+        "invalid header"
+    );
+
+    let mut report = ScanReport {
+        size: bytes.len(),
+        chunks: vec![],
+        findings: vec![],
+    };
+    let mut i = 8;
+    let mut after_iend = false;
+
+    while i < bytes.len() {
+        let chunk = match read_chunk(bytes, i) {
+            Ok(chunk) => chunk,
+            Err(reason) => {
+                // whatever follows IEND and is not a chunk is just appended data
+                if after_iend {
+                    report.findings.push(Finding {
+                        offset: i,
+                        risk: Risk::High,
+                        kind: FindingKind::TrailingData {
+                            length: bytes.len() - i,
+                        },
+                    });
+                    find_archives(&bytes[i..], i, None, &mut report.findings);
+                } else {
+                    report.findings.push(Finding {
+                        offset: i,
+                        risk: Risk::Medium,
+                        kind: FindingKind::Malformed(reason),
+                    });
+                }
+                break;
+            }
+        };
+        let data = &bytes[i + 8..i + 8 + chunk.length];
+        inspect(&chunk, data, after_iend, &mut report.findings);
+
+        after_iend |= &chunk.chunk_type.bytes() == b"IEND";
+        i += chunk.length + 12;
+        report.chunks.push(chunk);
+    }
+
+    if !after_iend
+        && !report
+            .findings
+            .iter()
+            .any(|x| matches!(x.kind, FindingKind::Malformed(_)))
+    {
+        report.findings.push(Finding {
+            offset: bytes.len(),
+            risk: Risk::Low,
+            kind: FindingKind::Malformed("missing IEND chunk".to_owned()),
+        });
+    }
+
+    Ok(report)
+}
+
+/// The chunk at `offset`, failing when it does not fit in `bytes`
+pub fn read_chunk(bytes: &[u8], offset: usize) -> Result<ChunkInfo, String> {
+    let header = bytes
+        .get(offset..offset + 8)
+        .ok_or_else(|| "truncated chunk header".to_owned())?;
+    let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let chunk_type =
+        ChunkType::try_from(&header[4..8]).map_err(|_| "invalid chunk type".to_owned())?;
+    let end = (offset + 12)
+        .checked_add(length)
+        .filter(|&end| length <= Chunk::MAX_DATA && end <= bytes.len())
+        .ok_or_else(|| format!("chunk {} is truncated", chunk_type))?;
+
+    let crc = u32::from_be_bytes(bytes[end - 4..end].try_into().unwrap());
+    let crc_ok = HDLC.checksum(&bytes[offset + 4..end - 4]) == crc;
+
+    Ok(ChunkInfo {
+        offset,
+        chunk_type,
+        length,
+        crc_ok,
+    })
+}
+
+fn inspect(chunk: &ChunkInfo, data: &[u8], after_iend: bool, findings: &mut Vec<Finding>) {
+    let chunk_type = chunk.chunk_type;
+    let type_bytes = chunk.chunk_type.bytes();
+    find_archives(data, chunk.offset + 8, Some(chunk_type), findings);
+    let mut push = |risk: Risk, kind: FindingKind| {
+        findings.push(Finding {
+            offset: chunk.offset,
+            risk,
+            kind,
+        })
+    };
+
+    if after_iend {
+        push(Risk::High, FindingKind::ChunkAfterIend(chunk_type));
+    }
+    if !chunk.crc_ok {
+        push(Risk::Medium, FindingKind::CrcMismatch(chunk_type));
+    }
+    if !chunk.chunk_type.is_public() {
+        push(Risk::Medium, FindingKind::PrivateChunk(chunk_type));
+    } else if !KNOWN_CHUNKS.contains(&&type_bytes) {
+        push(Risk::Medium, FindingKind::UnknownChunk(chunk_type));
+    }
+
+    if chunk.chunk_type.is_critical() {
+        return;
+    }
+    if (&type_bytes == b"zTXt" || &type_bytes == b"iTXt") && chunk.length > LARGE_TEXT {
+        push(
+            Risk::Medium,
+            FindingKind::LargeText {
+                chunk_type,
+                length: chunk.length,
+            },
+        );
+    } else if chunk.length > LARGE_ANCILLARY && &type_bytes != b"iCCP" && &type_bytes != b"fdAT" {
+        push(
+            Risk::Medium,
+            FindingKind::LargeAncillary {
+                chunk_type,
+                length: chunk.length,
+            },
+        );
+    }
+    if data.len() >= MIN_ENTROPY_SAMPLE && !COMPRESSED_CHUNKS.contains(&&type_bytes) {
+        let entropy = entropy(data);
+        if entropy > HIGH_ENTROPY {
+            push(
+                Risk::High,
+                FindingKind::HighEntropy {
+                    chunk_type,
+                    entropy,
+                },
+            );
+        }
+    }
+}
+
+/// Reports archive signatures in `data`, which starts `offset` bytes into the file
+fn find_archives(
+    data: &[u8],
+    offset: usize,
+    chunk_type: Option<ChunkType>,
+    findings: &mut Vec<Finding>,
+) {
+    for (i, kind) in polyglot::find_archives(data) {
+        findings.push(Finding {
+            offset: offset + i,
+            risk: Risk::High,
+            kind: FindingKind::EmbeddedArchive { chunk_type, kind },
+        });
+    }
+}
+
+/// Shannon entropy of `data` in bits per byte
+pub fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+    let total = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+impl fmt::Display for Risk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Risk::None => "none",
+            Risk::Low => "low",
+            Risk::Medium => "medium",
+            Risk::High => "high",
+        };
+        f.pad(name)
+    }
+}
+
+impl fmt::Display for FindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FindingKind::PrivateChunk(chunk_type) => write!(f, "private chunk {}", chunk_type),
+            FindingKind::UnknownChunk(chunk_type) => {
+                write!(f, "unknown public chunk {}", chunk_type)
+            }
+            FindingKind::ChunkAfterIend(chunk_type) => write!(f, "chunk {} after IEND", chunk_type),
+            FindingKind::TrailingData { length } => {
+                write!(f, "{} bytes appended after IEND", length)
+            }
+            FindingKind::LargeAncillary { chunk_type, length } => {
+                write!(
+                    f,
+                    "unusually large ancillary chunk {} ({} bytes)",
+                    chunk_type, length
+                )
+            }
+            FindingKind::LargeText { chunk_type, length } => {
+                write!(f, "oversized text chunk {} ({} bytes)", chunk_type, length)
+            }
+            FindingKind::HighEntropy {
+                chunk_type,
+                entropy,
+            } => write!(
+                f,
+                "high entropy data in {} ({:.2} bits/byte)",
+                chunk_type, entropy
+            ),
+            FindingKind::EmbeddedArchive {
+                chunk_type: Some(chunk_type),
+                kind,
+            } => write!(f, "{} signature in chunk {}", kind, chunk_type),
+            FindingKind::EmbeddedArchive {
+                chunk_type: None,
+                kind,
+            } => write!(f, "{} signature after IEND", kind),
+            FindingKind::CrcMismatch(chunk_type) => {
+                write!(f, "CRC mismatch in chunk {}", chunk_type)
+            }
+            FindingKind::Malformed(reason) => write!(f, "malformed file: {}", reason),
+        }
+    }
+}
+
+impl fmt::Display for ScanReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} bytes, {} chunks, risk: {}",
+            self.size,
+            self.chunks.len(),
+            self.risk()
+        )?;
+        for finding in &self.findings {
+            writeln!(
+                f,
+                "  [{:<6}] offset {:>8}: {}",
+                finding.risk, finding.offset, finding.kind
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Vec<u8> {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()).as_bytes()
+    }
+
+    fn testing_file(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend(chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]));
+        bytes.extend(chunk("IDAT", &[120, 156, 99, 0, 0, 0, 2, 0, 1]));
+        for chunk in chunks {
+            bytes.extend(chunk);
+        }
+        bytes
+    }
+
+    fn pseudo_random(len: usize) -> Vec<u8> {
+        let mut state: u32 = 0x1234_5678;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_file() {
+        let report = scan(&testing_file(&[
+            chunk("tEXt", b"Title\0dice"),
+            chunk("IEND", &[]),
+        ]))
+        .unwrap();
+        assert!(report.findings.is_empty());
+        assert_eq!(report.chunks.len(), 4);
+        assert_eq!(report.risk(), Risk::None);
+    }
+
+    #[test]
+    fn test_private_chunk() {
+        let report = scan(&testing_file(&[
+            chunk("ruSt", b"secret"),
+            chunk("IEND", &[]),
+        ]))
+        .unwrap();
+        assert!(matches!(
+            report.findings[0].kind,
+            FindingKind::PrivateChunk(_)
+        ));
+        assert_eq!(report.risk(), Risk::Medium);
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut bytes = testing_file(&[chunk("IEND", &[])]);
+        let iend_end = bytes.len();
+        bytes.extend(b"PK\x03\x04 appended archive");
+
+        let report = scan(&bytes).unwrap();
+        assert_eq!(report.findings.len(), 2);
+        assert_eq!(report.findings[0].offset, iend_end);
+        assert!(matches!(
+            report.findings[0].kind,
+            FindingKind::TrailingData { length: 21 }
+        ));
+        assert!(matches!(
+            report.findings[1].kind,
+            FindingKind::EmbeddedArchive {
+                chunk_type: None,
+                kind: "ZIP archive"
+            }
+        ));
+        assert_eq!(report.risk(), Risk::High);
+    }
+
+    #[test]
+    fn test_archive_in_chunk() {
+        let report = scan(&testing_file(&[
+            chunk("tEXt", b"a\0b 7z\xbc\xaf\x27\x1c"),
+            chunk("IEND", &[]),
+        ]))
+        .unwrap();
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].offset, report.chunks[2].offset + 8 + 4);
+        assert!(matches!(
+            report.findings[0].kind,
+            FindingKind::EmbeddedArchive {
+                chunk_type: Some(_),
+                kind: "7z archive"
+            }
+        ));
+    }
+
+    #[test]
+    fn test_chunk_after_iend() {
+        let report = scan(&testing_file(&[chunk("IEND", &[]), chunk("tEXt", b"a\0b")])).unwrap();
+        assert!(matches!(
+            report.findings[0].kind,
+            FindingKind::ChunkAfterIend(_)
+        ));
+    }
+
+    #[test]
+    fn test_high_entropy_and_large_chunks() {
+        let noise = pseudo_random(1024);
+        let report = scan(&testing_file(&[chunk("tEXt", &noise), chunk("IEND", &[])])).unwrap();
+        assert!(matches!(
+            report.findings[0].kind,
+            FindingKind::HighEntropy { .. }
+        ));
+
+        let text = vec![b'a'; LARGE_TEXT + 1];
+        let report = scan(&testing_file(&[chunk("zTXt", &text), chunk("IEND", &[])])).unwrap();
+        assert!(matches!(
+            report.findings[0].kind,
+            FindingKind::LargeText { .. }
+        ));
+
+        let blob = vec![0; LARGE_ANCILLARY + 1];
+        let report = scan(&testing_file(&[chunk("tEXt", &blob), chunk("IEND", &[])])).unwrap();
+        assert!(matches!(
+            report.findings[0].kind,
+            FindingKind::LargeAncillary { .. }
+        ));
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(&[7; 100]), 0.0);
+        assert!((entropy(&[0, 1, 0, 1]) - 1.0).abs() < 1e-9);
+        assert!(entropy(&pseudo_random(4096)) > 7.9);
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        assert!(scan(&[1, 2, 3]).is_err());
+    }
+}
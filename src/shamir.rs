@@ -0,0 +1,336 @@
+//! Shamir's secret sharing over GF(256), one random polynomial per byte of the secret.
+//!
+//! Every share starts with a small header:
+//!
+//! | bytes | field                                              |
+//! |-------|----------------------------------------------------|
+//! | 3     | magic `jpS`                                        |
+//! | 1     | version                                            |
+//! | 4     | set id, random and shared by the shares of a split |
+//! | 1     | shares needed to reconstruct the secret            |
+//! | 1     | shares in the set                                  |
+//! | 1     | share index, from 1                                |
+//! | 4     | first bytes of the SHA-256 of the key and secret   |
+//!
+//! The checksum is keyed by 16 random bytes shared along with the secret, so it can only be
+//! checked once enough shares are combined and a single share tells nothing about the secret.
+
+use crate::{gf256, signature::hex};
+use anyhow::{bail, ensure, Context};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use std::{fmt, str::FromStr};
+
+const MAGIC: &[u8; 3] = b"jpS";
+const VERSION: u8 = 2;
+const HEADER_SIZE: usize = 15;
+/// Random bytes shared before the secret, keying its checksum
+const KEY_SIZE: usize = 16;
+
+/// Whether `data` is a share, as written by `split`
+pub fn is_share(data: &[u8]) -> bool {
+    data.len() >= HEADER_SIZE && data.starts_with(MAGIC) && data[3] == VERSION
+}
+
+/// How many shares are made and how many of them are needed, written as `3-of-5`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Threshold {
+    pub required: u8,
+    pub total: u8,
+}
+
+impl FromStr for Threshold {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (required, total) = s
+            .split_once("-of-")
+            .context("shares must be written as `<required>-of-<total>`, like `3-of-5`")?;
+        let threshold = Threshold {
+            required: required
+                .parse()
+                .context("invalid number of required shares")?,
+            total: total.parse().context("invalid number of shares")?,
+        };
+        ensure!(
+            threshold.required > 0 && threshold.required <= threshold.total,
+            "required shares must be between 1 and the number of shares"
+        );
+        Ok(threshold)
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-of-{}", self.required, self.total)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Share {
+    set_id: [u8; 4],
+    threshold: Threshold,
+    index: u8,
+    checksum: [u8; 4],
+    data: Vec<u8>,
+}
+
+impl Share {
+    pub fn set_id(&self) -> [u8; 4] {
+        self.set_id
+    }
+    pub fn threshold(&self) -> Threshold {
+        self.threshold
+    }
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+    pub fn as_bytes(&self) -> Vec<u8> {
+        [
+            &MAGIC[..],
+            &[VERSION],
+            &self.set_id,
+            &[self.threshold.required, self.threshold.total, self.index],
+            &self.checksum,
+            &self.data,
+        ]
+        .concat()
+    }
+}
+
+impl TryFrom<&[u8]> for Share {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        ensure!(is_share(value), "not a secret share");
+        let threshold = Threshold {
+            required: value[8],
+            total: value[9],
+        };
+        let index = value[10];
+        ensure!(
+            threshold.required > 0 && threshold.required <= threshold.total,
+            "invalid share threshold {}",
+            threshold
+        );
+        ensure!(
+            index > 0 && index <= threshold.total,
+            "invalid share index `{}`",
+            index
+        );
+        Ok(Share {
+            set_id: value[4..8].try_into()?,
+            threshold,
+            index,
+            checksum: value[11..15].try_into()?,
+            data: value[HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+impl fmt::Display for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "share {} of set {} ({})",
+            self.index,
+            hex(&self.set_id),
+            self.threshold
+        )
+    }
+}
+
+fn checksum(key: &[u8], secret: &[u8]) -> [u8; 4] {
+    let digest = Sha256::new()
+        .chain_update(key)
+        .chain_update(secret)
+        .finalize();
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+/// Splits `secret` so that any `threshold.required` of the returned shares reconstruct it
+pub fn split(secret: &[u8], threshold: Threshold) -> Vec<Share> {
+    let mut set_id = [0; 4];
+    OsRng.fill_bytes(&mut set_id);
+    let mut key = [0; KEY_SIZE];
+    OsRng.fill_bytes(&mut key);
+    let mut shared = [&key[..], secret].concat();
+
+    let mut shares: Vec<Share> = (1..=threshold.total)
+        .map(|index| Share {
+            set_id,
+            threshold,
+            index,
+            checksum: checksum(&key, secret),
+            data: Vec::with_capacity(shared.len()),
+        })
+        .collect();
+
+    // coefficients highest degree first, the secret byte being the constant term
+    let mut polynomial = vec![0; threshold.required as usize];
+    for &byte in &shared {
+        OsRng.fill_bytes(&mut polynomial);
+        *polynomial.last_mut().unwrap() = byte;
+        for share in &mut shares {
+            share.data.push(gf256::eval(&polynomial, share.index));
+        }
+    }
+    polynomial.fill(0);
+    shared.fill(0);
+    key.fill(0);
+    shares
+}
+
+/// Reconstructs the secret from shares of the same set, failing when they are too few or do not
+/// add up to the original secret
+pub fn combine(shares: &[Share]) -> anyhow::Result<Vec<u8>> {
+    let first = shares.first().context("no shares to combine")?;
+
+    let mut sets: Vec<String> = shares.iter().map(|x| hex(&x.set_id)).collect();
+    sets.sort();
+    sets.dedup();
+    if sets.len() > 1 {
+        bail!("shares belong to different sets: {}", sets.join(", "));
+    }
+    ensure!(
+        shares.iter().all(|x| x.threshold == first.threshold
+            && x.checksum == first.checksum
+            && x.data.len() == first.data.len()),
+        "shares of set {} do not agree on the secret, one of them is damaged",
+        hex(&first.set_id)
+    );
+
+    let mut unique: Vec<&Share> = Vec::new();
+    for share in shares {
+        if !unique.iter().any(|x| x.index == share.index) {
+            unique.push(share);
+        }
+    }
+    let required = first.threshold.required as usize;
+    ensure!(
+        unique.len() >= required,
+        "{} of the {} shares needed were found",
+        unique.len(),
+        required
+    );
+    let unique = &unique[..required];
+
+    // Lagrange interpolation at zero, where the weights only depend on the share indexes
+    let weights: Vec<u8> = unique
+        .iter()
+        .map(|share| {
+            unique
+                .iter()
+                .filter(|other| other.index != share.index)
+                .fold(1, |acc, other| {
+                    gf256::mul(acc, gf256::div(other.index, other.index ^ share.index))
+                })
+        })
+        .collect();
+    let mut shared: Vec<u8> = (0..first.data.len())
+        .map(|i| {
+            unique
+                .iter()
+                .zip(&weights)
+                .fold(0, |acc, (share, &weight)| {
+                    acc ^ gf256::mul(share.data[i], weight)
+                })
+        })
+        .collect();
+
+    ensure!(
+        shared.len() >= KEY_SIZE
+            && checksum(&shared[..KEY_SIZE], &shared[KEY_SIZE..]) == first.checksum,
+        "shares of set {} do not reconstruct the secret, one of them is damaged",
+        hex(&first.set_id)
+    );
+    let secret = shared.split_off(KEY_SIZE);
+    shared.fill(0);
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secret() -> Vec<u8> {
+        b"This is where your secret message will be!".to_vec()
+    }
+
+    fn three_of_five() -> Threshold {
+        "3-of-5".parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_threshold() {
+        assert_eq!(
+            three_of_five(),
+            Threshold {
+                required: 3,
+                total: 5
+            }
+        );
+        assert_eq!(three_of_five().to_string(), "3-of-5");
+        assert!("5-of-3".parse::<Threshold>().is_err());
+        assert!("0-of-3".parse::<Threshold>().is_err());
+        assert!("3/5".parse::<Threshold>().is_err());
+        assert!("3-of-300".parse::<Threshold>().is_err());
+    }
+
+    #[test]
+    fn test_split_and_combine() {
+        let shares = split(&secret(), three_of_five());
+        assert_eq!(shares.len(), 5);
+        assert!(shares.iter().all(|x| x.data != secret()));
+
+        for subset in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+            let subset: Vec<Share> = subset.iter().map(|&i| shares[i].clone()).collect();
+            assert_eq!(combine(&subset).unwrap(), secret());
+        }
+        assert_eq!(combine(&shares).unwrap(), secret());
+    }
+
+    #[test]
+    fn test_too_few_shares() {
+        let shares = split(&secret(), three_of_five());
+        let result = combine(&[shares[0].clone(), shares[3].clone(), shares[3].clone()]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_share_round_trip() {
+        let share = split(&secret(), three_of_five()).remove(1);
+        let bytes = share.as_bytes();
+        assert!(is_share(&bytes));
+        assert_eq!(Share::try_from(bytes.as_slice()).unwrap(), share);
+        assert_eq!(share.index(), 2);
+        assert!(Share::try_from(&bytes[..10]).is_err());
+    }
+
+    #[test]
+    fn test_checksum_is_keyed() {
+        let shares = split(&secret(), three_of_five());
+        let again = split(&secret(), three_of_five());
+        assert_ne!(shares[0].checksum, checksum(&[], &secret()));
+        assert_ne!(shares[0].checksum, again[0].checksum);
+        assert_eq!(shares[0].data.len(), KEY_SIZE + secret().len());
+    }
+
+    #[test]
+    fn test_mismatched_or_damaged_shares() {
+        let shares = split(&secret(), three_of_five());
+        let other = split(&secret(), three_of_five());
+        let mixed = [shares[0].clone(), shares[1].clone(), other[2].clone()];
+        assert!(combine(&mixed)
+            .unwrap_err()
+            .to_string()
+            .contains("different sets"));
+
+        let mut damaged = shares[..3].to_vec();
+        damaged[1].data[5] ^= 1;
+        assert!(combine(&damaged)
+            .unwrap_err()
+            .to_string()
+            .contains("damaged"));
+    }
+}
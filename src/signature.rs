@@ -0,0 +1,457 @@
+use crate::{chunk::Chunk, chunk_type::ChunkType};
+use anyhow::{bail, ensure, Context};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use sha2::{Digest, Sha256};
+use std::{fmt, fs, io::Write, path::Path};
+
+/// Private and unsafe to copy, so editors that do not know it drop it when changing the image
+pub const SIGNATURE_CHUNK: &[u8; 4] = b"jsIG";
+/// Chunks every signature covers, other types can be added when signing
+pub const ALWAYS_COVERED: &[&[u8; 4]] = &[b"IHDR", b"PLTE", b"IDAT"];
+
+const CONTEXT: &[u8] = b"just_png signature\0";
+const VERSION: u8 = 1;
+
+pub type KeyId = [u8; 8];
+
+/// First bytes of the SHA-256 of the public key
+pub fn key_id(key: &VerifyingKey) -> KeyId {
+    let digest = Sha256::digest(key.as_bytes());
+    digest[..8].try_into().unwrap()
+}
+
+/// What a signature chunk holds: digests of the covered chunks and the signature over them
+#[derive(Debug, Clone)]
+struct Manifest {
+    key_id: KeyId,
+    covered: Vec<ChunkType>,
+    digests: Vec<(ChunkType, [u8; 32])>,
+    signature: Signature,
+}
+
+impl Manifest {
+    /// Canonical serialization of everything the signature covers
+    fn signed_bytes(
+        key_id: &KeyId,
+        covered: &[ChunkType],
+        digests: &[(ChunkType, [u8; 32])],
+    ) -> Vec<u8> {
+        let mut out = vec![VERSION];
+        out.extend(key_id);
+        out.push(covered.len() as u8);
+        out.extend(covered.iter().flat_map(|x| x.bytes()));
+        out.extend((digests.len() as u32).to_be_bytes());
+        for (chunk_type, digest) in digests {
+            out.extend(chunk_type.bytes());
+            out.extend(digest);
+        }
+        out
+    }
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut out = Manifest::signed_bytes(&self.key_id, &self.covered, &self.digests);
+        out.extend(self.signature.to_bytes());
+        out
+    }
+}
+
+impl TryFrom<&[u8]> for Manifest {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let mut reader = value;
+        let mut take = |len: usize| -> anyhow::Result<&[u8]> {
+            ensure!(reader.len() >= len, "signature chunk is truncated");
+            let (head, tail) = reader.split_at(len);
+            reader = tail;
+            Ok(head)
+        };
+
+        ensure!(take(1)?[0] == VERSION, "unsupported signature version");
+        let key_id: KeyId = take(8)?.try_into()?;
+        let count = take(1)?[0] as usize;
+        let covered = take(count * 4)?
+            .chunks_exact(4)
+            .map(ChunkType::try_from)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let count = u32::from_be_bytes(take(4)?.try_into()?) as usize;
+        let mut digests = Vec::with_capacity(count.min(value.len() / 36));
+        for _ in 0..count {
+            let entry = take(36)?;
+            digests.push((ChunkType::try_from(&entry[..4])?, entry[4..].try_into()?));
+        }
+        let signature = Signature::from_bytes(take(64)?.try_into()?);
+        ensure!(reader.is_empty(), "unexpected data after the signature");
+
+        Ok(Manifest {
+            key_id,
+            covered,
+            digests,
+            signature,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkStatus {
+    Intact,
+    Altered,
+    /// signed, but no longer in the file
+    Missing,
+    /// of a covered type, but added after signing
+    Added,
+}
+
+#[derive(Debug, Clone)]
+pub struct CoveredChunk {
+    pub chunk_type: ChunkType,
+    /// position among the chunks of the same type
+    pub occurrence: usize,
+    pub status: ChunkStatus,
+}
+
+/// Outcome of checking a signature against the current chunks
+#[derive(Debug, Clone)]
+pub struct Verification {
+    pub key_id: KeyId,
+    pub signature_valid: bool,
+    pub chunks: Vec<CoveredChunk>,
+}
+
+impl Verification {
+    /// Whether the signature holds and no covered chunk changed since signing
+    pub fn is_intact(&self) -> bool {
+        self.signature_valid
+            && self
+                .chunks
+                .iter()
+                .all(|chunk| chunk.status == ChunkStatus::Intact)
+    }
+}
+
+/// Builds the signature chunk over `chunks` of the always covered types plus `extra`
+pub fn sign(chunks: &[Chunk], key: &SigningKey, extra: &[ChunkType]) -> anyhow::Result<Chunk> {
+    let mut covered: Vec<ChunkType> = ALWAYS_COVERED
+        .iter()
+        .map(|&&x| ChunkType::try_from(x))
+        .collect::<anyhow::Result<_>>()?;
+    for chunk_type in extra {
+        ensure!(
+            &chunk_type.bytes() != SIGNATURE_CHUNK,
+            "the signature chunk cannot sign itself"
+        );
+        if !covered.contains(chunk_type) {
+            covered.push(*chunk_type);
+        }
+    }
+    ensure!(
+        covered.len() <= u8::MAX as usize,
+        "too many chunk types to sign"
+    );
+
+    let digests: Vec<_> = chunks
+        .iter()
+        .filter(|chunk| covered.contains(chunk.chunk_type()))
+        .map(|chunk| (*chunk.chunk_type(), digest(chunk)))
+        .collect();
+    let key_id = key_id(&key.verifying_key());
+    let message = [
+        CONTEXT,
+        &Manifest::signed_bytes(&key_id, &covered, &digests),
+    ]
+    .concat();
+
+    let manifest = Manifest {
+        key_id,
+        covered,
+        digests,
+        signature: key.sign(&message),
+    };
+    Ok(Chunk::new(
+        ChunkType::try_from(*SIGNATURE_CHUNK)?,
+        manifest.as_bytes(),
+    ))
+}
+
+/// Checks the signature chunk found in `chunks` with whichever of `keys` signed it
+pub fn verify(chunks: &[Chunk], keys: &[VerifyingKey]) -> anyhow::Result<Verification> {
+    let chunk = chunks
+        .iter()
+        .find(|chunk| &chunk.chunk_type().bytes() == SIGNATURE_CHUNK)
+        .context("this png is not signed")?;
+    let manifest = Manifest::try_from(chunk.data())?;
+
+    let key = keys
+        .iter()
+        .find(|key| key_id(key) == manifest.key_id)
+        .with_context(|| format!("signed with unknown key {}", hex(&manifest.key_id)))?;
+    let message = [
+        CONTEXT,
+        &Manifest::signed_bytes(&manifest.key_id, &manifest.covered, &manifest.digests),
+    ]
+    .concat();
+    let signature_valid = key.verify_strict(&message, &manifest.signature).is_ok();
+
+    let mut report = vec![];
+    for chunk_type in &manifest.covered {
+        let signed = manifest
+            .digests
+            .iter()
+            .filter(|(x, _)| x == chunk_type)
+            .map(|(_, digest)| Some(digest));
+        let current = chunks
+            .iter()
+            .filter(|chunk| chunk.chunk_type() == chunk_type)
+            .map(|chunk| Some(digest(chunk)));
+        let count = signed.clone().count().max(current.clone().count());
+
+        let pairs = signed
+            .chain(std::iter::repeat(None))
+            .zip(current.chain(std::iter::repeat(None)))
+            .take(count);
+        for (occurrence, (signed, current)) in pairs.enumerate() {
+            let status = match (signed, current) {
+                (Some(signed), Some(current)) if *signed == current => ChunkStatus::Intact,
+                (Some(_), Some(_)) => ChunkStatus::Altered,
+                (Some(_), None) => ChunkStatus::Missing,
+                _ => ChunkStatus::Added,
+            };
+            report.push(CoveredChunk {
+                chunk_type: *chunk_type,
+                occurrence,
+                status,
+            });
+        }
+    }
+
+    Ok(Verification {
+        key_id: manifest.key_id,
+        signature_valid,
+        chunks: report,
+    })
+}
+
+fn digest(chunk: &Chunk) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(chunk.chunk_type().bytes());
+    hasher.update((chunk.data().len() as u32).to_be_bytes());
+    hasher.update(chunk.data());
+    hasher.finalize().into()
+}
+
+pub fn generate_key() -> SigningKey {
+    SigningKey::generate(&mut rand::rngs::OsRng)
+}
+
+/// Writes the secret key to `path`, readable only by its owner
+pub fn write_signing_key(path: &Path, key: &SigningKey) -> anyhow::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("could not create key file {:?}", path))?;
+    writeln!(
+        file,
+        "# just_png ed25519 secret key {}",
+        hex(&key_id(&key.verifying_key()))
+    )?;
+    writeln!(file, "{}", hex(key.as_bytes()))?;
+    Ok(())
+}
+
+pub fn write_verifying_key(path: &Path, key: &VerifyingKey) -> anyhow::Result<()> {
+    let content = format!(
+        "# just_png ed25519 public key {}\n{}\n",
+        hex(&key_id(key)),
+        hex(key.as_bytes())
+    );
+    fs::write(path, content).with_context(|| format!("could not create key file {:?}", path))
+}
+
+pub fn read_signing_key(path: &Path) -> anyhow::Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&read_key_file(path)?))
+}
+
+pub fn read_verifying_key(path: &Path) -> anyhow::Result<VerifyingKey> {
+    VerifyingKey::from_bytes(&read_key_file(path)?)
+        .with_context(|| format!("invalid public key in {:?}", path))
+}
+
+/// The 32 hex encoded bytes of a key file, comments start with `#`
+fn read_key_file(path: &Path) -> anyhow::Result<[u8; 32]> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("could not read key file {:?}", path))?;
+    let line = content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .with_context(|| format!("empty key file {:?}", path))?;
+
+    let bytes = from_hex(line)?;
+    match bytes.try_into() {
+        Ok(key) => Ok(key),
+        Err(_) => bail!("invalid key length in {:?}", path),
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+fn from_hex(text: &str) -> anyhow::Result<Vec<u8>> {
+    ensure!(text.len().is_multiple_of(2), "invalid hex string");
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .context("invalid hex string")
+        })
+        .collect()
+}
+
+impl fmt::Display for ChunkStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ChunkStatus::Intact => "intact",
+            ChunkStatus::Altered => "ALTERED",
+            ChunkStatus::Missing => "MISSING",
+            ChunkStatus::Added => "ADDED",
+        };
+        f.pad(name)
+    }
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "signed by key {}: signature {}",
+            hex(&self.key_id),
+            if self.signature_valid {
+                "valid"
+            } else {
+                "INVALID"
+            }
+        )?;
+        for chunk in &self.chunks {
+            writeln!(
+                f,
+                "  {} #{:<3} {}",
+                chunk.chunk_type, chunk.occurrence, chunk.status
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(
+            ChunkType::from_str(chunk_type).unwrap(),
+            data.as_bytes().to_vec(),
+        )
+    }
+
+    fn testing_chunks() -> Vec<Chunk> {
+        vec![
+            chunk("IHDR", "header"),
+            chunk("tEXt", "Comment\0hello"),
+            chunk("IDAT", "first"),
+            chunk("IDAT", "second"),
+            chunk("ruSt", "secret"),
+            chunk("IEND", ""),
+        ]
+    }
+
+    fn testing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    fn signed_chunks() -> Vec<Chunk> {
+        let mut chunks = testing_chunks();
+        let extra = [ChunkType::from_str("ruSt").unwrap()];
+        let signature = sign(&chunks, &testing_key(), &extra).unwrap();
+        chunks.insert(5, signature);
+        chunks
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let chunks = signed_chunks();
+        let verification = verify(&chunks, &[testing_key().verifying_key()]).unwrap();
+
+        assert!(verification.is_intact());
+        let covered: Vec<String> = verification
+            .chunks
+            .iter()
+            .map(|x| x.chunk_type.to_string())
+            .collect();
+        assert_eq!(covered, ["IHDR", "IDAT", "IDAT", "ruSt"]);
+    }
+
+    #[test]
+    fn test_altered_chunks() {
+        let mut chunks = signed_chunks();
+        chunks[3] = chunk("IDAT", "tampered");
+        chunks.remove(4);
+        chunks.insert(1, chunk("PLTE", "palette"));
+        // not covered, so free to change
+        chunks[2] = chunk("tEXt", "Comment\0bye");
+
+        let verification = verify(&chunks, &[testing_key().verifying_key()]).unwrap();
+        assert!(verification.signature_valid);
+        assert!(!verification.is_intact());
+
+        let statuses: Vec<(String, usize, ChunkStatus)> = verification
+            .chunks
+            .iter()
+            .map(|x| (x.chunk_type.to_string(), x.occurrence, x.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("IHDR".to_owned(), 0, ChunkStatus::Intact),
+                ("PLTE".to_owned(), 0, ChunkStatus::Added),
+                ("IDAT".to_owned(), 0, ChunkStatus::Intact),
+                ("IDAT".to_owned(), 1, ChunkStatus::Altered),
+                ("ruSt".to_owned(), 0, ChunkStatus::Missing),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_forged_manifest() {
+        let mut chunks = signed_chunks();
+        let mut data = chunks[5].data().to_vec();
+        // flip a bit of the last digest
+        let index = data.len() - 65;
+        data[index] ^= 1;
+        chunks[5] = Chunk::new(ChunkType::try_from(*SIGNATURE_CHUNK).unwrap(), data);
+
+        let verification = verify(&chunks, &[testing_key().verifying_key()]).unwrap();
+        assert!(!verification.signature_valid);
+        assert!(!verification.is_intact());
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let other = SigningKey::from_bytes(&[8; 32]).verifying_key();
+        assert!(verify(&signed_chunks(), &[other]).is_err());
+        assert!(verify(&testing_chunks(), &[other]).is_err());
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(hex(&[0, 15, 255]), "000fff");
+        assert_eq!(from_hex("000fff").unwrap(), [0, 15, 255]);
+        assert!(from_hex("0g").is_err());
+        assert!(from_hex("abc").is_err());
+    }
+}
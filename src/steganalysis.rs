@@ -0,0 +1,437 @@
+use crate::{
+    chunk::Chunk,
+    decoder::Image,
+    ihdr::{ColorType, Ihdr},
+    png::Png,
+    zlib,
+};
+use anyhow::ensure;
+
+/// Pairs of values seen fewer times than this are left out of the chi-square test
+const MIN_PAIR_SAMPLES: usize = 8;
+/// How many prefixes of a channel are tested to find where sequential embedding stops
+const CHI_SQUARE_STEPS: usize = 100;
+/// Estimated embedding rates above this are reported as likely embedding
+pub const DETECTION_THRESHOLD: f64 = 0.1;
+
+/// LSB embedding estimates for a single channel, rates go from 0 (clean) to 1 (every sample)
+#[derive(Debug, Clone)]
+pub struct ChannelReport {
+    pub channel: &'static str,
+    /// probability that pairs of values were equalized by LSB replacement
+    pub chi_square: f64,
+    /// fraction of the channel, counting from its start, where `chi_square` stays above one half
+    pub chi_square_rate: f64,
+    /// embedding rate estimated by RS (regular/singular groups) analysis
+    pub rs: f64,
+    /// embedding rate estimated by sample pair analysis
+    pub sample_pairs: f64,
+}
+
+impl ChannelReport {
+    /// The most pessimistic of the RS and sample pair estimates.
+    ///
+    /// The chi-square test is left out since it also fires on any image with a smooth histogram.
+    pub fn rate(&self) -> f64 {
+        self.rs.max(self.sample_pairs)
+    }
+}
+
+/// Runs every estimator over each channel of `image`
+pub fn analyze(image: &Image) -> anyhow::Result<Vec<ChannelReport>> {
+    let ihdr = image.ihdr();
+    ensure!(
+        ihdr.bit_depth == 8,
+        "steganalysis needs 8-bit samples, this image has {}-bit samples",
+        ihdr.bit_depth
+    );
+
+    let names = ihdr.color_type.channel_names();
+    Ok((0..names.len())
+        .map(|index| {
+            let rows = channel(image, index);
+            let (chi_square, chi_square_rate) = chi_square(&rows);
+            ChannelReport {
+                channel: names[index],
+                chi_square,
+                chi_square_rate,
+                rs: rs_analysis(&rows),
+                sample_pairs: sample_pair_analysis(&rows),
+            }
+        })
+        .collect())
+}
+
+/// Renders bit `bit` of channel `channel` as a black and white image
+pub fn bit_plane(image: &Image, channel: usize, bit: u8) -> anyhow::Result<Png> {
+    let ihdr = image.ihdr();
+    ensure!(ihdr.bit_depth == 8, "bit planes need 8-bit samples");
+    ensure!(
+        channel < ihdr.color_type.channels(),
+        "this image has no channel {}",
+        channel
+    );
+    ensure!(bit < 8, "bit must be between 0 and 7");
+
+    let plane = Ihdr::new(ihdr.width, ihdr.height, 8, ColorType::Grayscale)?;
+    let raw: Vec<u8> = self::channel(image, channel)
+        .iter()
+        .flat_map(|row| {
+            // filter type byte followed by the row
+            std::iter::once(0).chain(row.iter().map(|&x| if x >> bit & 1 == 1 { 255 } else { 0 }))
+        })
+        .collect();
+
+    Ok(Png::from_chunks(vec![
+        Chunk::new("IHDR".try_into()?, plane.as_bytes()),
+        Chunk::new("IDAT".try_into()?, zlib::compress_stored(&raw)),
+        Chunk::new("IEND".try_into()?, vec![]),
+    ]))
+}
+
+fn channel(image: &Image, index: usize) -> Vec<Vec<u8>> {
+    let channels = image.ihdr().color_type.channels();
+    image
+        .rows()
+        .map(|row| row.iter().skip(index).step_by(channels).copied().collect())
+        .collect()
+}
+
+/// Westfeld and Pfitzmann's test: LSB replacement makes the counts of values 2k and 2k+1 equal
+fn chi_square(rows: &[Vec<u8>]) -> (f64, f64) {
+    let samples: Vec<u8> = rows.iter().flatten().copied().collect();
+    if samples.is_empty() {
+        return (0.0, 0.0);
+    }
+
+    let mut histogram = [0usize; 256];
+    let mut probability = 0.0;
+    let mut sequential = 0.0;
+    let mut embedded = true;
+    let step = samples.len().div_ceil(CHI_SQUARE_STEPS);
+
+    for (i, prefix) in samples.chunks(step).enumerate() {
+        for &sample in prefix {
+            histogram[sample as usize] += 1;
+        }
+        // too few samples yet to say anything
+        probability = match chi_square_probability(&histogram) {
+            Some(probability) => probability,
+            None => continue,
+        };
+        embedded &= probability > 0.5;
+        if embedded {
+            sequential = ((i + 1) * step).min(samples.len()) as f64 / samples.len() as f64;
+        }
+    }
+
+    (probability, sequential)
+}
+
+fn chi_square_probability(histogram: &[usize; 256]) -> Option<f64> {
+    let mut statistic = 0.0;
+    let mut categories = 0;
+    for pair in histogram.chunks_exact(2) {
+        if pair[0] + pair[1] < MIN_PAIR_SAMPLES {
+            continue;
+        }
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        statistic += (pair[0] as f64 - expected).powi(2) / expected;
+        categories += 1;
+    }
+    if categories < 2 {
+        return None;
+    }
+
+    let freedom = (categories - 1) as f64;
+    Some(1.0 - regularized_gamma(freedom / 2.0, statistic / 2.0))
+}
+
+/// Fridrich, Goljan and Du's RS analysis over groups of four neighbouring samples
+fn rs_analysis(rows: &[Vec<u8>]) -> f64 {
+    const MASK: [i16; 4] = [0, 1, 1, 0];
+
+    let groups: Vec<[i16; 4]> = rows
+        .iter()
+        .flat_map(|row| row.chunks_exact(4))
+        .map(|group| {
+            [
+                group[0] as i16,
+                group[1] as i16,
+                group[2] as i16,
+                group[3] as i16,
+            ]
+        })
+        .collect();
+    if groups.is_empty() {
+        return 0.0;
+    }
+    let flipped: Vec<[i16; 4]> = groups.iter().map(|group| group.map(|x| x ^ 1)).collect();
+
+    // difference between regular and singular groups once `flip` is applied to the masked samples
+    let difference = |groups: &[[i16; 4]], flip: fn(i16) -> i16| -> f64 {
+        let mut balance = 0isize;
+        for group in groups {
+            let mut changed = *group;
+            for (x, mask) in changed.iter_mut().zip(MASK) {
+                if mask == 1 {
+                    *x = flip(*x);
+                }
+            }
+            balance += match smoothness(&changed).cmp(&smoothness(group)) {
+                std::cmp::Ordering::Greater => 1,
+                std::cmp::Ordering::Less => -1,
+                std::cmp::Ordering::Equal => 0,
+            };
+        }
+        balance as f64 / groups.len() as f64
+    };
+    let positive: fn(i16) -> i16 = |x| x ^ 1;
+    let negative: fn(i16) -> i16 = |x| ((x + 1) ^ 1) - 1;
+
+    let d0 = difference(&groups, positive);
+    let d1 = difference(&flipped, positive);
+    let n0 = difference(&groups, negative);
+    let n1 = difference(&flipped, negative);
+
+    let a = 2.0 * (d1 + d0);
+    let b = n0 - n1 - d1 - 3.0 * d0;
+    let c = d0 - n0;
+    let x = match smallest_root(a, b, c) {
+        Some(x) => x,
+        None => return 0.0,
+    };
+    clamp_rate(x / (x - 0.5))
+}
+
+fn smoothness(group: &[i16; 4]) -> i16 {
+    group.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum()
+}
+
+/// Dumitrescu, Wu and Wang's sample pair analysis over horizontally adjacent samples
+fn sample_pair_analysis(rows: &[Vec<u8>]) -> f64 {
+    let (mut x, mut y, mut k, mut n) = (0usize, 0usize, 0usize, 0usize);
+    for row in rows {
+        for pair in row.windows(2) {
+            let (u, v) = (pair[0], pair[1]);
+            n += 1;
+            if (v % 2 == 0 && u < v) || (v % 2 == 1 && u > v) {
+                x += 1;
+            }
+            if (v % 2 == 0 && u > v) || (v % 2 == 1 && u < v) {
+                y += 1;
+            }
+            if u / 2 == v / 2 {
+                k += 1;
+            }
+        }
+    }
+
+    let a = k as f64 / 2.0;
+    let b = 2.0 * x as f64 - n as f64;
+    let c = y as f64 - x as f64;
+    smallest_root(a, b, c).map(clamp_rate).unwrap_or(0.0)
+}
+
+/// Root of `a x² + b x + c` closest to zero
+fn smallest_root(a: f64, b: f64, c: f64) -> Option<f64> {
+    if a.abs() < 1e-12 {
+        return (b.abs() > 1e-12).then(|| -c / b);
+    }
+    let discriminant = (b * b - 4.0 * a * c).max(0.0).sqrt();
+    let roots = [
+        (-b + discriminant) / (2.0 * a),
+        (-b - discriminant) / (2.0 * a),
+    ];
+    roots
+        .into_iter()
+        .filter(|x| x.is_finite())
+        .min_by(|x, y| x.abs().total_cmp(&y.abs()))
+}
+
+fn clamp_rate(rate: f64) -> f64 {
+    if rate.is_finite() {
+        rate.clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/// Regularized lower incomplete gamma function P(a, x)
+fn regularized_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let prefix = (a * x.ln() - x - ln_gamma(a)).exp();
+
+    if x < a + 1.0 {
+        // series expansion
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        (sum * prefix).min(1.0)
+    } else {
+        // continued fraction for the upper function, evaluated with Lentz's method
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            d = if d.abs() < tiny { tiny } else { d };
+            c = b + an / c;
+            c = if c.abs() < tiny { tiny } else { c };
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-15 {
+                break;
+            }
+        }
+        (1.0 - prefix * h).max(0.0)
+    }
+}
+
+/// Lanczos approximation of ln Γ(x)
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| {
+            sum + c / (x + i as f64 + 1.0)
+        });
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: u32 = 128;
+
+    struct Noise(u32);
+
+    impl Noise {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+    }
+
+    /// Smooth gradients with a little noise, like a photograph
+    fn cover() -> Vec<u8> {
+        let mut noise = Noise(0x9E37_79B9);
+        (0..SIZE * SIZE)
+            .map(|i| {
+                let (x, y) = ((i % SIZE) as f64, (i / SIZE) as f64);
+                let value = 128.0 + 60.0 * (x / 17.0).sin() + 50.0 * (y / 23.0 + x / 40.0).cos();
+                (value + (noise.next() % 7) as f64 - 3.0).clamp(0.0, 255.0) as u8
+            })
+            .collect()
+    }
+
+    /// Replaces the LSB of a `rate` fraction of the samples with random bits
+    fn embed(samples: &[u8], rate: f64) -> Vec<u8> {
+        let mut noise = Noise(0x0BAD_5EED);
+        samples
+            .iter()
+            .map(|&x| {
+                if (noise.next() % 1000) as f64 / 1000.0 < rate {
+                    x & !1 | (noise.next() >> 7) as u8 & 1
+                } else {
+                    x
+                }
+            })
+            .collect()
+    }
+
+    fn image(samples: Vec<u8>) -> Image {
+        let ihdr = Ihdr::new(SIZE, SIZE, 8, ColorType::Grayscale).unwrap();
+        Image::new(ihdr, samples).unwrap()
+    }
+
+    #[test]
+    fn test_clean_image() {
+        let report = &analyze(&image(cover())).unwrap()[0];
+        assert_eq!(report.channel, "gray");
+        assert!(report.rs < DETECTION_THRESHOLD, "{:?}", report);
+        assert!(report.sample_pairs < DETECTION_THRESHOLD, "{:?}", report);
+    }
+
+    #[test]
+    fn test_chi_square() {
+        // a cover using only even values is as far as possible from equal pairs of values
+        let even: Vec<u8> = cover().iter().map(|x| x & !1).collect();
+        let report = &analyze(&image(even.clone())).unwrap()[0];
+        assert!(report.chi_square < 0.01, "{:?}", report);
+        assert_eq!(report.chi_square_rate, 0.0);
+
+        // embedding the first half sequentially
+        let half = even.len() / 2;
+        let mut stego = embed(&even[..half], 1.0);
+        stego.extend(&even[half..]);
+        let report = &analyze(&image(stego)).unwrap()[0];
+        assert!(report.chi_square < 0.01, "{:?}", report);
+        assert!((report.chi_square_rate - 0.5).abs() < 0.1, "{:?}", report);
+    }
+
+    #[test]
+    fn test_half_embedded_image() {
+        let report = &analyze(&image(embed(&cover(), 0.5))).unwrap()[0];
+        assert!((report.rs - 0.5).abs() < 0.15, "{:?}", report);
+        assert!((report.sample_pairs - 0.5).abs() < 0.15, "{:?}", report);
+    }
+
+    #[test]
+    fn test_fully_embedded_image() {
+        let report = &analyze(&image(embed(&cover(), 1.0))).unwrap()[0];
+        assert!(report.rate() > 0.8, "{:?}", report);
+        assert!(report.chi_square > 0.5, "{:?}", report);
+        assert!(report.chi_square_rate > 0.9, "{:?}", report);
+    }
+
+    #[test]
+    fn test_bit_plane() {
+        let samples: Vec<u8> = (0..SIZE * SIZE).map(|x| x as u8).collect();
+        let png = bit_plane(&image(samples), 0, 0).unwrap();
+        let plane = crate::decoder::decode(&png).unwrap();
+        assert_eq!(&plane.data()[..4], &[0, 255, 0, 255]);
+        assert!(bit_plane(&image(vec![0; (SIZE * SIZE) as usize]), 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_regularized_gamma() {
+        // P(1, x) = 1 - e^-x
+        assert!((regularized_gamma(1.0, 2.0) - (1.0 - (-2.0f64).exp())).abs() < 1e-9);
+        assert!((regularized_gamma(3.0, 0.5) - 0.014_387_677_966_970_687).abs() < 1e-9);
+        assert!((ln_gamma(5.0) - 24f64.ln()).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,93 @@
+//! Hides bits in the filter type starting every row of the image data: its value modulo 4 gives
+//! two bits, so types 0 and 4 both stand for `00`. Rows are filtered again with the chosen type,
+//! so the pixels stay the same.
+
+use super::{ensure_fits, frame, payload_capacity, unframe};
+use crate::{decoder, filter, png::Png, zlib};
+use anyhow::ensure;
+
+const BITS_PER_ROW: usize = 2;
+
+/// Bits the rows of the image can hold
+fn carrier_bits(png: &Png) -> anyhow::Result<usize> {
+    let ihdr = png.ihdr()?;
+    ensure!(!ihdr.interlaced, "interlaced images are not supported");
+    Ok(ihdr.height as usize * BITS_PER_ROW)
+}
+
+/// Payload bytes the rows of the image can hold
+pub fn capacity(png: &Png) -> anyhow::Result<usize> {
+    Ok(payload_capacity(carrier_bits(png)?))
+}
+
+/// Filters every row with a type spelling out the next bits of `payload`
+pub fn embed(png: &mut Png, payload: &[u8]) -> anyhow::Result<()> {
+    ensure_fits(payload, carrier_bits(png)?)?;
+
+    let image = decoder::decode(png)?;
+    let distance = image.ihdr().filter_distance();
+    let mut bits = frame(payload)?;
+    let mut raw = Vec::with_capacity((image.row_bytes() + 1) * image.ihdr().height as usize);
+    let mut previous = vec![0; image.row_bytes()];
+    for row in image.rows() {
+        let value =
+            (0..BITS_PER_ROW).fold(0, |acc, _| acc << 1 | bits.next().unwrap_or_default() as u8);
+
+        let (filter_type, filtered) = filter::filter_best(
+            [value, value + 4].into_iter().filter(|&x| x <= 4),
+            row,
+            &previous,
+            distance,
+        )?;
+        raw.push(filter_type);
+        raw.extend(filtered);
+        previous.copy_from_slice(row);
+    }
+
+    png.set_image_data(&zlib::compress(&raw))
+}
+
+/// Reads back a payload hidden with `embed`
+pub fn extract(png: &Png) -> anyhow::Result<Vec<u8>> {
+    let ihdr = png.ihdr()?;
+    ensure!(!ihdr.interlaced, "interlaced images are not supported");
+    let raw = zlib::decompress(&png.image_data())?;
+    let bits = raw
+        .chunks_exact(ihdr.row_bytes(ihdr.width) + 1)
+        .take(ihdr.height as usize)
+        .flat_map(|row| {
+            (0..BITS_PER_ROW)
+                .rev()
+                .map(move |i| (row[0] % 4) >> i & 1 == 1)
+        });
+    unframe(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk::Chunk,
+        ihdr::{ColorType, Ihdr},
+    };
+
+    #[test]
+    fn test_embed_and_extract() {
+        let ihdr = Ihdr::new(16, 200, 8, ColorType::Rgb).unwrap();
+        let data: Vec<u8> = (0..200u8)
+            .flat_map(|y| std::iter::once(0).chain((0..48u8).map(move |x| x.wrapping_mul(y))))
+            .collect();
+        let mut png = Png::from_chunks(vec![
+            Chunk::new("IHDR".try_into().unwrap(), ihdr.as_bytes()),
+            Chunk::new("IDAT".try_into().unwrap(), zlib::compress(&data)),
+            Chunk::new("IEND".try_into().unwrap(), vec![]),
+        ]);
+        let pixels = decoder::decode(&png).unwrap().data().to_vec();
+        assert_eq!(capacity(&png).unwrap(), 46);
+
+        embed(&mut png, b"filtered").unwrap();
+        assert_eq!(decoder::decode(&png).unwrap().data(), pixels);
+        assert_eq!(extract(&png).unwrap(), b"filtered");
+        assert!(embed(&mut png, &[0; 47]).is_err());
+    }
+}
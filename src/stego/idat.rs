@@ -0,0 +1,103 @@
+//! Hides bits in how the image data is compressed: every back reference of 4 bytes or more
+//! carries the parity of its length. Where the longest match is 5 bytes or more, the encoder
+//! shortens it by one when the parity is wrong, so the pixels stay the same and the stream only
+//! grows a little.
+
+use super::{ensure_fits, frame, payload_capacity, unframe};
+use crate::{png::Png, zlib};
+
+/// Shortest match that carries a bit
+const CARRIER_MATCH: usize = 4;
+
+/// Rough number of payload bytes the image data can hold, the exact amount depends on the bits
+/// since a shortened match changes the ones that follow
+pub fn capacity(png: &Png) -> anyhow::Result<usize> {
+    let data = zlib::decompress(&png.image_data())?;
+    let mut bits = 0;
+    zlib::lz77(&data, |longest| {
+        if longest > CARRIER_MATCH {
+            bits += 1;
+        }
+        longest
+    });
+    Ok(payload_capacity(bits))
+}
+
+/// Recompresses the image data so that its matches spell out `payload`
+pub fn embed(png: &mut Png, payload: &[u8]) -> anyhow::Result<()> {
+    let data = zlib::decompress(&png.image_data())?;
+    let mut bits = frame(payload)?;
+    let mut written = 0;
+
+    let tokens = zlib::lz77(&data, |longest| match longest {
+        // a 4 byte match would carry a bit it can't choose, so it is shortened to a plain one
+        CARRIER_MATCH => CARRIER_MATCH - 1,
+        longest if longest > CARRIER_MATCH => {
+            written += 1;
+            let bit = bits.next().unwrap_or_default() as usize;
+            if longest & 1 == bit {
+                longest
+            } else {
+                longest - 1
+            }
+        }
+        longest => longest,
+    });
+    ensure_fits(payload, written)?;
+
+    png.set_image_data(&zlib::compress_tokens(&tokens, &data))
+}
+
+/// Reads back a payload hidden with `embed`
+pub fn extract(png: &Png) -> anyhow::Result<Vec<u8>> {
+    let lengths = zlib::match_lengths(&png.image_data())?;
+    unframe(
+        lengths
+            .into_iter()
+            .filter(|&length| length as usize >= CARRIER_MATCH)
+            .map(|length| length & 1 == 1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk::Chunk,
+        ihdr::{ColorType, Ihdr},
+    };
+
+    /// Grayscale image drawn from a few short patterns, which compress into plenty of matches
+    fn patterned_png() -> Png {
+        let ihdr = Ihdr::new(64, 64, 8, ColorType::Grayscale).unwrap();
+        let mut state = 1u32;
+        let mut data = vec![0; 64 * 65];
+        for (i, x) in data.iter_mut().enumerate().filter(|(i, _)| i % 65 != 0) {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            *x = ((state >> 16) % 2) as u8 * 16 + (i % 65 / 8) as u8;
+        }
+        Png::from_chunks(vec![
+            Chunk::new("IHDR".try_into().unwrap(), ihdr.as_bytes()),
+            Chunk::new("IDAT".try_into().unwrap(), zlib::compress(&data)),
+            Chunk::new("IEND".try_into().unwrap(), vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_embed_and_extract() {
+        let mut png = patterned_png();
+        let pixels = zlib::decompress(&png.image_data()).unwrap();
+        assert!(capacity(&png).unwrap() >= 16);
+
+        embed(&mut png, b"hidden!").unwrap();
+        assert_eq!(zlib::decompress(&png.image_data()).unwrap(), pixels);
+        assert_eq!(extract(&png).unwrap(), b"hidden!");
+    }
+
+    #[test]
+    fn test_payload_too_large() {
+        let mut png = patterned_png();
+        let payload = vec![0; capacity(&png).unwrap() * 2 + 8];
+        assert!(embed(&mut png, &payload).is_err());
+    }
+}
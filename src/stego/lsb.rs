@@ -0,0 +1,149 @@
+//! Hides bits in the lowest bits of every color sample, leaving alpha alone since changing it is
+//! easily seen on transparent pixels. It changes pixels, unlike the other carriers, and is the
+//! one `analyze` looks for.
+
+use super::{ensure_fits, frame, payload_capacity, unframe};
+use crate::{
+    apng,
+    chunk::Chunk,
+    decoder::{self, Image},
+    encoder::{self, Options},
+    ihdr::{ColorType, Ihdr},
+    png::Png,
+};
+use anyhow::ensure;
+
+/// Index in the image data of the least significant byte of every color sample
+fn carriers(image: &Image) -> anyhow::Result<Vec<usize>> {
+    let ihdr = image.ihdr();
+    ensure!(
+        ihdr.color_type != ColorType::Indexed,
+        "indexed images can't change their samples, use the palette method"
+    );
+    ensure!(
+        ihdr.bit_depth >= 8,
+        "LSB embedding needs 8 or 16-bit samples, this image has {}-bit samples",
+        ihdr.bit_depth
+    );
+
+    let bytes = ihdr.bit_depth as usize / 8;
+    let channels = ihdr.color_type.channels();
+    let colors = channels - ihdr.color_type.has_alpha() as usize;
+    Ok((0..image.data().len() / bytes)
+        .filter(|sample| sample % channels < colors)
+        .map(|sample| sample * bytes + bytes - 1)
+        .collect())
+}
+
+fn validate(bits: u8) -> anyhow::Result<()> {
+    ensure!(
+        (1..=8).contains(&bits),
+        "bits per sample must be between 1 and 8"
+    );
+    Ok(())
+}
+
+/// Payload bytes the lowest `bits` bits of every color sample can hold
+pub fn capacity(png: &Png, bits: u8) -> anyhow::Result<usize> {
+    validate(bits)?;
+    let image = decoder::decode(png)?;
+    Ok(payload_capacity(carriers(&image)?.len() * bits as usize))
+}
+
+/// Replaces the lowest `bits` bits of the color samples with `payload`
+pub fn embed(png: &mut Png, payload: &[u8], bits: u8) -> anyhow::Result<()> {
+    validate(bits)?;
+    let image = decoder::decode(png)?;
+    ensure!(
+        !image.ihdr().interlaced || !apng::is_animated(png),
+        "the frames of an interlaced animated image can't be written back in plain order"
+    );
+    let carriers = carriers(&image)?;
+    ensure_fits(payload, carriers.len() * bits as usize)?;
+
+    let mut stream = frame(payload)?;
+    let mask = ((1u16 << bits) - 1) as u8;
+    let mut data = image.data().to_vec();
+    for i in carriers {
+        let value = (0..bits).fold(0, |acc, _| {
+            acc << 1 | stream.next().unwrap_or_default() as u8
+        });
+        data[i] = data[i] & !mask | value;
+    }
+
+    // rows are written back in plain order
+    let ihdr = Ihdr {
+        interlaced: false,
+        ..*image.ihdr()
+    };
+    png.replace_chunk(Chunk::new("IHDR".try_into()?, ihdr.as_bytes()))?;
+    let image = Image::new(ihdr, data)?;
+    png.set_image_data(&encoder::image_data(&image, &Options::default())?)
+}
+
+/// Reads back a payload hidden with `embed` using the same `bits`
+pub fn extract(png: &Png, bits: u8) -> anyhow::Result<Vec<u8>> {
+    validate(bits)?;
+    let image = decoder::decode(png)?;
+    let data = image.data();
+    unframe(
+        carriers(&image)?
+            .into_iter()
+            .flat_map(|i| (0..bits).rev().map(move |bit| data[i] >> bit & 1 == 1)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zlib;
+
+    fn rgba_png(bit_depth: u8) -> Png {
+        let ihdr = Ihdr::new(16, 16, bit_depth, ColorType::Rgba).unwrap();
+        let row_bytes = ihdr.row_bytes(16);
+        let data: Vec<u8> = (0..16)
+            .flat_map(|y| std::iter::once(0).chain((0..row_bytes).map(move |x| (x * y) as u8)))
+            .collect();
+        Png::from_chunks(vec![
+            Chunk::new("IHDR".try_into().unwrap(), ihdr.as_bytes()),
+            Chunk::new("IDAT".try_into().unwrap(), zlib::compress(&data)),
+            Chunk::new("IEND".try_into().unwrap(), vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_embed_and_extract() {
+        for (bit_depth, bits) in [(8, 1), (8, 3), (16, 2)] {
+            let mut png = rgba_png(bit_depth);
+            let before = decoder::decode(&png).unwrap();
+            assert_eq!(
+                capacity(&png, bits).unwrap(),
+                (16 * 16 * 3 * bits as usize - 32) / 8
+            );
+
+            embed(&mut png, b"least significant", bits).unwrap();
+            assert_eq!(extract(&png, bits).unwrap(), b"least significant");
+
+            // alpha and the high bits are left alone
+            let after = decoder::decode(&png).unwrap();
+            let sample = bit_depth as usize / 8;
+            for (i, (a, b)) in before.data().iter().zip(after.data()).enumerate() {
+                let is_alpha = i / sample % 4 == 3;
+                let is_low_byte = i % sample == sample - 1;
+                if is_alpha || !is_low_byte {
+                    assert_eq!(a, b);
+                } else {
+                    assert_eq!(a >> bits, b >> bits);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_payload_too_large() {
+        let mut png = rgba_png(8);
+        let error = embed(&mut png, &[0; 100], 1).unwrap_err();
+        assert_eq!(error.to_string(), "needs 100 bytes, have 92");
+        assert!(capacity(&png, 9).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! Carriers hiding a payload in the image itself rather than in a chunk of its own.
+//!
+//! Every carrier stores a stream of bits: the payload length as 4 big endian bytes, the payload
+//! and then random bits filling whatever capacity is left. The trailer after `IEND` is the
+//! exception, it has no fixed capacity to fill.
+
+use anyhow::ensure;
+use rand::{rngs::OsRng, RngCore};
+
+pub mod filter;
+pub mod idat;
+pub mod lsb;
+pub mod palette;
+pub mod trailer;
+
+/// Bits taken by the payload length
+const LENGTH_BITS: usize = 32;
+
+/// Payload bytes that fit in `bits` carrier bits
+fn payload_capacity(bits: usize) -> usize {
+    bits.saturating_sub(LENGTH_BITS) / 8
+}
+
+/// Bits of `payload` behind its length, most significant first, then random bits forever
+fn frame(payload: &[u8]) -> anyhow::Result<impl Iterator<Item = bool>> {
+    let length: u32 = payload.len().try_into()?;
+    let bytes = [&length.to_be_bytes()[..], payload].concat();
+    let random = std::iter::repeat_with(|| OsRng.next_u32() & 1 == 1);
+    Ok(bytes
+        .into_iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1))
+        .chain(random))
+}
+
+/// Bits needed to store `payload` with `frame`
+fn framed_bits(payload: &[u8]) -> usize {
+    LENGTH_BITS + payload.len() * 8
+}
+
+/// Fails with how much room `payload` needs when `bits` carrier bits can't hold it
+fn ensure_fits(payload: &[u8], bits: usize) -> anyhow::Result<()> {
+    ensure!(
+        framed_bits(payload) <= bits,
+        "needs {} bytes, have {}",
+        payload.len(),
+        payload_capacity(bits)
+    );
+    Ok(())
+}
+
+/// Reads back a payload stored with `frame`
+fn unframe(bits: impl IntoIterator<Item = bool>) -> anyhow::Result<Vec<u8>> {
+    let mut bits = bits.into_iter();
+    let mut byte = || (0..8).try_fold(0u8, |acc, _| bits.next().map(|bit| acc << 1 | bit as u8));
+
+    let mut length = [0; 4];
+    for x in &mut length {
+        *x = byte().ok_or_else(|| anyhow::anyhow!("no hidden payload found"))?;
+    }
+    let length = u32::from_be_bytes(length) as usize;
+
+    let mut payload = Vec::with_capacity(length.min(1 << 20));
+    while payload.len() < length {
+        let Some(x) = byte() else { break };
+        payload.push(x);
+    }
+    ensure!(
+        payload.len() == length,
+        "no hidden payload found, or it is truncated"
+    );
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_round_trip() {
+        let payload = b"This is where your secret message will be!";
+        let bits: Vec<bool> = frame(payload)
+            .unwrap()
+            .take(framed_bits(payload) + 13)
+            .collect();
+        assert_eq!(unframe(bits.iter().copied()).unwrap(), payload);
+        assert!(unframe(bits[..framed_bits(payload) - 1].iter().copied()).is_err());
+        assert_eq!(payload_capacity(framed_bits(payload) + 7), payload.len());
+    }
+}
@@ -0,0 +1,271 @@
+//! Hides bits in indexed images by giving palette colors an identical twin: a pixel pointing at
+//! the first entry of a color stands for 0 and one pointing at its twin for 1. The palette and
+//! `tRNS` grow with the twins, so every pixel keeps its exact color and transparency, and the
+//! `bKGD` index and `hIST` follow the new entries. `sPLT` lists colors rather than indices, so it
+//! stays as it is.
+
+use super::{ensure_fits, frame, payload_capacity, unframe};
+use crate::{
+    apng,
+    chunk::Chunk,
+    decoder::{self, Color, Image},
+    encoder::{self, Options},
+    ihdr::{ColorType, Ihdr},
+    palette::{self, Bkgd, Hist, PaletteChunks},
+    png::Png,
+};
+use anyhow::{ensure, Context};
+use std::collections::HashMap;
+
+const MAX_ENTRIES: usize = 256;
+
+/// Colors used by the image or its background, in palette order, and the ones getting a twin with
+/// how many pixels they cover
+struct Plan {
+    colors: Vec<Color>,
+    twins: Vec<(Color, usize)>,
+}
+
+impl Plan {
+    fn new(palette: &[Color], indices: &[u8], background: Option<u8>) -> anyhow::Result<Plan> {
+        let mut counts: HashMap<Color, usize> = HashMap::new();
+        for &index in indices {
+            let color = palette
+                .get(index as usize)
+                .with_context(|| format!("pixel index `{}` is outside the palette", index))?;
+            *counts.entry(*color).or_default() += 1;
+        }
+
+        let background = background.and_then(|x| palette.get(x as usize));
+        let mut colors: Vec<Color> = Vec::with_capacity(counts.len() + 1);
+        for color in palette {
+            let kept = counts.contains_key(color) || background == Some(color);
+            if kept && !colors.contains(color) {
+                colors.push(*color);
+            }
+        }
+        let mut twins: Vec<(Color, usize)> = colors
+            .iter()
+            .filter_map(|x| counts.get(x).map(|count| (*x, *count)))
+            .collect();
+        twins.sort_by_key(|x| std::cmp::Reverse(x.1));
+        twins.truncate(MAX_ENTRIES - colors.len());
+        Ok(Plan { colors, twins })
+    }
+    fn bits(&self) -> usize {
+        self.twins.iter().map(|x| x.1).sum()
+    }
+}
+
+/// Palette index of the background in `bKGD`
+fn background(png: &Png) -> anyhow::Result<Option<u8>> {
+    let palette = PaletteChunks::from_chunks(png.chunks(), &png.ihdr()?)?;
+    Ok(match palette.bkgd {
+        Some(Bkgd::Index(index)) => Some(index),
+        _ => None,
+    })
+}
+
+/// Image with its palette and the palette index of every pixel
+fn read(png: &Png) -> anyhow::Result<(Image, Vec<Color>, Vec<u8>)> {
+    let image = decoder::decode(png)?;
+    ensure!(
+        image.ihdr().color_type == ColorType::Indexed,
+        "only indexed images have a palette"
+    );
+    let palette = image.palette().to_vec();
+    let indices = image.samples().into_iter().map(|x| x as u8).collect();
+    Ok((image, palette, indices))
+}
+
+/// Payload bytes the pixels of the twinned colors can hold
+pub fn capacity(png: &Png) -> anyhow::Result<usize> {
+    let (_, palette, indices) = read(png)?;
+    let plan = Plan::new(&palette, &indices, background(png)?)?;
+    Ok(payload_capacity(plan.bits()))
+}
+
+/// Rewrites the palette with twins and points every pixel of a twinned color at the entry
+/// spelling out the next bit of `payload`
+pub fn embed(png: &mut Png, payload: &[u8]) -> anyhow::Result<()> {
+    ensure!(
+        !apng::is_animated(png),
+        "every frame of an animated image shares the palette, it can't be rebuilt"
+    );
+    let (image, palette, indices) = read(png)?;
+    let background = background(png)?;
+    let plan = Plan::new(&palette, &indices, background)?;
+    ensure_fits(payload, plan.bits())?;
+
+    let mut entries: HashMap<Color, (u8, Option<u8>)> = HashMap::new();
+    for (i, color) in plan.colors.iter().enumerate() {
+        entries.insert(*color, (i as u8, None));
+    }
+    let mut colors = plan.colors.clone();
+    for (color, _) in &plan.twins {
+        entries.get_mut(color).unwrap().1 = Some(colors.len() as u8);
+        colors.push(*color);
+    }
+
+    let mut bits = frame(payload)?;
+    let indices: Vec<u8> = indices
+        .iter()
+        .map(|&index| match entries[&palette[index as usize]] {
+            (_, Some(twin)) if bits.next().unwrap_or_default() => twin,
+            (first, _) => first,
+        })
+        .collect();
+
+    let ihdr = image.ihdr();
+    let depth = [1, 2, 4, 8]
+        .into_iter()
+        .find(|&x| colors.len() <= 1 << x)
+        .unwrap_or(8);
+    let ihdr = Ihdr::new(ihdr.width, ihdr.height, depth, ColorType::Indexed)?;
+    png.replace_chunk(Chunk::new("IHDR".try_into()?, ihdr.as_bytes()))?;
+
+    let plte: Vec<u8> = colors.iter().flat_map(|x| [x[0], x[1], x[2]]).collect();
+    png.replace_chunk(Chunk::new("PLTE".try_into()?, plte))?;
+    let mut alphas: Vec<u8> = colors.iter().map(|x| x[3]).collect();
+    while alphas.last() == Some(&255) {
+        alphas.pop();
+    }
+    if png.chunk_by_type("tRNS").is_some() {
+        png.remove_chunk("tRNS")?;
+    }
+    if !alphas.is_empty() {
+        png.insert_before_data(Chunk::new("tRNS".try_into()?, alphas));
+    }
+
+    if let Some(color) = background.and_then(|x| palette.get(x as usize)) {
+        let bkgd = Bkgd::Index(entries[color].0);
+        png.replace_chunk(Chunk::new("bKGD".try_into()?, bkgd.as_bytes()))?;
+    }
+
+    let indices: Vec<u16> = indices.into_iter().map(|x| x as u16).collect();
+    let image = Image::from_samples(ihdr, &indices)?.with_palette(colors);
+    png.set_image_data(&encoder::image_data(&image, &Options::default())?)?;
+    if png.chunk_by_type("hIST").is_some() {
+        let hist = Hist::from_counts(&palette::counts(&image));
+        png.replace_chunk(Chunk::new("hIST".try_into()?, hist.as_bytes()))?;
+    }
+    Ok(())
+}
+
+/// Reads back a payload hidden with `embed`
+pub fn extract(png: &Png) -> anyhow::Result<Vec<u8>> {
+    let (_, palette, indices) = read(png)?;
+
+    // every entry repeating an earlier color is the twin of its first entry
+    let mut bits: Vec<Option<bool>> = vec![None; palette.len()];
+    for (i, color) in palette.iter().enumerate() {
+        if let Some(first) = palette[..i].iter().position(|x| x == color) {
+            if bits[first].is_none() {
+                bits[first] = Some(false);
+                bits[i] = Some(true);
+            }
+        }
+    }
+    unframe(
+        indices
+            .into_iter()
+            .filter_map(|index| bits.get(index as usize).copied().flatten()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zlib;
+
+    fn packed(indices: &[u16], width: u32, depth: u8) -> Vec<u8> {
+        let ihdr = Ihdr::new(
+            width,
+            indices.len() as u32 / width,
+            depth,
+            ColorType::Indexed,
+        );
+        let image = Image::from_samples(ihdr.unwrap(), indices).unwrap();
+        zlib::compress(&encoder::filter_rows(&image, encoder::FilterStrategy::Fixed(0)).unwrap())
+    }
+
+    /// Indexed image 32 pixels wide of 5 colors, one of them half transparent
+    fn indexed_png() -> Png {
+        let ihdr = Ihdr::new(32, 32, 4, ColorType::Indexed).unwrap();
+        let indices: Vec<u16> = (0..32 * 32).map(|i| i / 7 % 5).collect();
+        Png::from_chunks(vec![
+            Chunk::new("IHDR".try_into().unwrap(), ihdr.as_bytes()),
+            Chunk::new(
+                "PLTE".try_into().unwrap(),
+                vec![0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255, 9, 9, 9],
+            ),
+            Chunk::new("tRNS".try_into().unwrap(), vec![255, 128]),
+            Chunk::new("IDAT".try_into().unwrap(), packed(&indices, 32, 4)),
+            Chunk::new("IEND".try_into().unwrap(), vec![]),
+        ])
+    }
+
+    fn colors(png: &Png) -> Vec<Color> {
+        let (_, palette, indices) = read(png).unwrap();
+        indices.iter().map(|&x| palette[x as usize]).collect()
+    }
+
+    #[test]
+    fn test_embed_and_extract() {
+        let mut png = indexed_png();
+        let before = colors(&png);
+        assert_eq!(capacity(&png).unwrap(), 124);
+
+        embed(&mut png, b"hidden in the palette").unwrap();
+        assert_eq!(colors(&png), before);
+        assert_eq!(read(&png).unwrap().1.len(), 10);
+        assert_eq!(
+            png.chunk_by_type("tRNS").unwrap().data(),
+            [255, 128, 255, 255, 255, 255, 128]
+        );
+        assert_eq!(extract(&png).unwrap(), b"hidden in the palette");
+    }
+
+    #[test]
+    fn test_grows_bit_depth() {
+        let ihdr = Ihdr::new(64, 4, 1, ColorType::Indexed).unwrap();
+        let indices: Vec<u16> = (0..256).map(|i| (i % 3 == 0) as u16).collect();
+        let mut png = Png::from_chunks(vec![
+            Chunk::new("IHDR".try_into().unwrap(), ihdr.as_bytes()),
+            Chunk::new("PLTE".try_into().unwrap(), vec![0, 0, 0, 255, 255, 255]),
+            Chunk::new("IDAT".try_into().unwrap(), packed(&indices, 64, 1)),
+            Chunk::new("IEND".try_into().unwrap(), vec![]),
+        ]);
+        let before = colors(&png);
+
+        embed(&mut png, b"2 bits").unwrap();
+        assert_eq!(png.ihdr().unwrap().bit_depth, 2);
+        assert_eq!(colors(&png), before);
+        assert!(png.chunk_by_type("tRNS").is_none());
+        assert_eq!(extract(&png).unwrap(), b"2 bits");
+    }
+
+    #[test]
+    fn test_background_and_histogram() {
+        // the background is the last entry, which no pixel uses
+        let mut png = indexed_png();
+        let mut plte = png.chunk_by_type("PLTE").unwrap().data().to_vec();
+        plte.extend([1, 2, 3]);
+        png.replace_chunk(Chunk::new("PLTE".try_into().unwrap(), plte))
+            .unwrap();
+        png.insert_before_data(Chunk::new("bKGD".try_into().unwrap(), vec![5]));
+        png.insert_before_data(Chunk::new("hIST".try_into().unwrap(), vec![0; 12]));
+
+        embed(&mut png, b"hidden in the palette").unwrap();
+        let (_, palette, indices) = read(&png).unwrap();
+        let bkgd = png.chunk_by_type("bKGD").unwrap().data()[0];
+        assert_eq!(palette[bkgd as usize], [1, 2, 3, 255]);
+
+        let hist = Hist::try_from(png.chunk_by_type("hIST").unwrap().data()).unwrap();
+        assert_eq!(hist.0.len(), palette.len());
+        for (i, frequency) in hist.0.iter().enumerate() {
+            assert_eq!(*frequency > 0, indices.contains(&(i as u8)));
+        }
+        assert_eq!(extract(&png).unwrap(), b"hidden in the palette");
+    }
+}
@@ -0,0 +1,57 @@
+//! Payload appended after `IEND`, where decoders stop reading. It is followed by its length as 4
+//! big endian bytes, so it is found from the end of the file whatever data was already there.
+
+use crate::png::Png;
+use anyhow::{ensure, Context};
+
+/// Bytes taken by the payload length
+const LENGTH_SIZE: usize = 4;
+
+/// The trailer holds any payload whose length fits in its length field
+pub fn capacity(_png: &Png) -> anyhow::Result<usize> {
+    Ok(u32::MAX as usize)
+}
+
+pub fn embed(png: &mut Png, payload: &[u8]) -> anyhow::Result<()> {
+    let length: u32 = payload
+        .len()
+        .try_into()
+        .context("the payload is too large for the trailer")?;
+    png.append(payload);
+    png.append(&length.to_be_bytes());
+    Ok(())
+}
+
+/// Reads back the last payload hidden with `embed`
+pub fn extract(png: &Png) -> anyhow::Result<Vec<u8>> {
+    let trailer = png.trailer();
+    ensure!(trailer.len() >= LENGTH_SIZE, "no payload after IEND");
+    let (rest, length) = trailer.split_at(trailer.len() - LENGTH_SIZE);
+    let length = u32::from_be_bytes(length.try_into()?) as usize;
+    ensure!(length <= rest.len(), "the data after IEND is not a payload");
+    Ok(rest[rest.len() - length..].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    #[test]
+    fn test_embed_and_extract() {
+        let bytes = [
+            &Png::STANDARD_HEADER[..],
+            &Chunk::new("IEND".try_into().unwrap(), vec![]).as_bytes(),
+            b"PK\x03\x04 appended earlier",
+        ]
+        .concat();
+        let mut png = Png::try_from(&bytes[..]).unwrap();
+        assert_eq!(png.trailer(), b"PK\x03\x04 appended earlier");
+        assert!(extract(&png).is_err());
+
+        embed(&mut png, b"hidden").unwrap();
+        let png = Png::try_from(&png.as_bytes()[..]).unwrap();
+        assert!(png.trailer().starts_with(b"PK\x03\x04"));
+        assert_eq!(extract(&png).unwrap(), b"hidden");
+    }
+}
@@ -0,0 +1,866 @@
+use anyhow::{bail, ensure, Context};
+
+/// Largest amount of data a stored deflate block can hold
+const MAX_STORED_BLOCK: usize = 65535;
+/// How far back a match can reach
+const WINDOW: usize = 32768;
+pub const MIN_MATCH: usize = 3;
+pub const MAX_MATCH: usize = 258;
+/// Candidates tried per position when looking for the longest match
+const MAX_CHAIN: usize = 128;
+/// Candidates tried per position at each compression level from 1 to 9
+const CHAIN_BY_LEVEL: [usize; 9] = [4, 8, 16, 32, 64, 128, 256, 1024, 4096];
+pub const DEFAULT_LEVEL: u8 = 6;
+const HASH_BITS: u32 = 15;
+/// Longest code allowed for literals, lengths and distances
+const MAX_CODE_LENGTH: u8 = 15;
+/// Longest code allowed for the code lengths of a dynamic block
+const MAX_CODE_LENGTH_CODE: u8 = 7;
+
+#[rustfmt::skip]
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+#[rustfmt::skip]
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+#[rustfmt::skip]
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+#[rustfmt::skip]
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+/// Order in which code length code lengths are stored in dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the largest run that cannot overflow before reducing
+    for block in data.chunks(5552) {
+        for &byte in block {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    b << 16 | a
+}
+
+/// Literal byte or back reference, the pieces a deflate stream is made of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+fn check_header(data: &[u8]) -> anyhow::Result<()> {
+    ensure!(data.len() >= 6, "zlib stream is too short");
+    let (cmf, flg) = (data[0], data[1]);
+    ensure!(cmf & 0x0F == 8, "unsupported zlib compression method");
+    ensure!(
+        (cmf as u16 * 256 + flg as u16).is_multiple_of(31),
+        "invalid zlib header checksum"
+    );
+    ensure!(
+        flg & 0x20 == 0,
+        "zlib preset dictionaries are not supported"
+    );
+    Ok(())
+}
+
+/// Inflates a zlib stream, checking its header and Adler-32 checksum
+pub fn decompress(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    check_header(data)?;
+
+    let (out, consumed) = inflate(&data[2..])?;
+    let trailer = data
+        .get(2 + consumed..2 + consumed + 4)
+        .context("zlib stream is missing its checksum")?;
+    ensure!(
+        u32::from_be_bytes(trailer.try_into()?) == adler32(&out),
+        "zlib checksum mismatch"
+    );
+
+    Ok(out)
+}
+
+/// Wraps `data` in a zlib stream made of stored (uncompressed) deflate blocks
+pub fn compress_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none() as u8;
+        let len = block.len() as u16;
+        out.push(last);
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(block);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+/// Length of every back reference in a zlib stream, in order
+pub fn match_lengths(data: &[u8]) -> anyhow::Result<Vec<u16>> {
+    check_header(data)?;
+    let mut lengths = Vec::new();
+    inflate_with(&data[2..], &mut |length| lengths.push(length))?;
+    Ok(lengths)
+}
+
+/// Compresses `data` into a zlib stream at the default level
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    compress_level(data, DEFAULT_LEVEL)
+}
+
+/// Compresses `data` into a zlib stream, from 0 (stored) to 9 (slowest, smallest)
+pub fn compress_level(data: &[u8], level: u8) -> Vec<u8> {
+    if level == 0 {
+        return compress_stored(data);
+    }
+    let max_chain = CHAIN_BY_LEVEL[level.min(9) as usize - 1];
+    let tokens = parse(data, max_chain, &mut |longest| longest);
+    stream(level, &tokens, data)
+}
+
+/// Wraps `tokens`, which must expand to `data`, in a zlib stream with a single Huffman block
+pub fn compress_tokens(tokens: &[Token], data: &[u8]) -> Vec<u8> {
+    stream(DEFAULT_LEVEL, tokens, data)
+}
+
+/// Zlib stream with a single block holding `tokens` with fixed or dynamic codes, whichever is
+/// smaller, its header telling the compression `level` used
+fn stream(level: u8, tokens: &[Token], data: &[u8]) -> Vec<u8> {
+    let header = match level {
+        0 | 1 => 0x01,
+        2..=5 => 0x5E,
+        6 => 0x9C,
+        _ => 0xDA,
+    };
+
+    let mut fixed = BitWriter::new();
+    fixed.write(1, 1);
+    fixed.write(1, 2);
+    let (literals, distances) = fixed_lengths();
+    write_tokens(&mut fixed, tokens, &literals, &distances);
+    let fixed = fixed.finish();
+
+    let mut dynamic = BitWriter::new();
+    dynamic.write(1, 1);
+    dynamic.write(2, 2);
+    write_dynamic(&mut dynamic, tokens);
+    let dynamic = dynamic.finish();
+
+    let block = if dynamic.len() < fixed.len() {
+        dynamic
+    } else {
+        fixed
+    };
+    [&[0x78, header][..], &block, &adler32(data).to_be_bytes()].concat()
+}
+
+/// Index in `LENGTH_BASE` and `DISTANCE_BASE` of the codes for a match
+fn match_codes(length: u16, distance: u16) -> (usize, usize) {
+    (
+        LENGTH_BASE.partition_point(|&x| x <= length) - 1,
+        DISTANCE_BASE.partition_point(|&x| x <= distance) - 1,
+    )
+}
+
+/// Writes `tokens` and the end of block with the given code lengths
+fn write_tokens(bits: &mut BitWriter, tokens: &[Token], literals: &[u8], distances: &[u8]) {
+    let literals = canonical_codes(literals);
+    let distances = canonical_codes(distances);
+    for &token in tokens {
+        match token {
+            Token::Literal(byte) => bits.write_code(literals[byte as usize]),
+            Token::Match { length, distance } => {
+                let (length_code, distance_code) = match_codes(length, distance);
+                bits.write_code(literals[257 + length_code]);
+                bits.write(
+                    (length - LENGTH_BASE[length_code]) as u32,
+                    LENGTH_EXTRA[length_code],
+                );
+                bits.write_code(distances[distance_code]);
+                bits.write(
+                    (distance - DISTANCE_BASE[distance_code]) as u32,
+                    DISTANCE_EXTRA[distance_code],
+                );
+            }
+        }
+    }
+    bits.write_code(literals[256]);
+}
+
+/// Writes the code lengths of a dynamic block fitted to `tokens`, then the tokens
+fn write_dynamic(bits: &mut BitWriter, tokens: &[Token]) {
+    let mut literal_counts = [0u32; 286];
+    let mut distance_counts = [0u32; 30];
+    literal_counts[256] = 1;
+    for &token in tokens {
+        match token {
+            Token::Literal(byte) => literal_counts[byte as usize] += 1,
+            Token::Match { length, distance } => {
+                let (length_code, distance_code) = match_codes(length, distance);
+                literal_counts[257 + length_code] += 1;
+                distance_counts[distance_code] += 1;
+            }
+        }
+    }
+    let literals = huffman_lengths(&literal_counts, MAX_CODE_LENGTH);
+    let distances = huffman_lengths(&distance_counts, MAX_CODE_LENGTH);
+
+    let literal_count = literals.iter().rposition(|&x| x > 0).unwrap_or(0).max(256) + 1;
+    let distance_count = distances.iter().rposition(|&x| x > 0).unwrap_or(0) + 1;
+    let runs = run_lengths(&[&literals[..literal_count], &distances[..distance_count]].concat());
+
+    let mut code_counts = [0u32; 19];
+    for &(symbol, _) in &runs {
+        code_counts[symbol as usize] += 1;
+    }
+    let code_lengths = huffman_lengths(&code_counts, MAX_CODE_LENGTH_CODE);
+    let code_count = CODE_LENGTH_ORDER
+        .iter()
+        .rposition(|&x| code_lengths[x] > 0)
+        .unwrap_or(0)
+        .max(3)
+        + 1;
+
+    bits.write(literal_count as u32 - 257, 5);
+    bits.write(distance_count as u32 - 1, 5);
+    bits.write(code_count as u32 - 4, 4);
+    for &symbol in &CODE_LENGTH_ORDER[..code_count] {
+        bits.write(code_lengths[symbol] as u32, 3);
+    }
+    let codes = canonical_codes(&code_lengths);
+    for (symbol, extra) in runs {
+        bits.write_code(codes[symbol as usize]);
+        match symbol {
+            16 => bits.write(extra as u32, 2),
+            17 => bits.write(extra as u32, 3),
+            18 => bits.write(extra as u32, 7),
+            _ => {}
+        }
+    }
+
+    write_tokens(bits, tokens, &literals, &distances);
+}
+
+/// Code lengths as the symbols of the code length alphabet, with the value of their extra bits:
+/// 16 repeats the previous length, 17 and 18 are runs of zeros
+fn run_lengths(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let length = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&x| x == length).count();
+        if length == 0 && run >= 11 {
+            let run = run.min(138);
+            runs.push((18, (run - 11) as u8));
+            i += run;
+        } else if length == 0 && run >= 3 {
+            runs.push((17, (run - 3) as u8));
+            i += run;
+        } else if length > 0 && run >= 4 {
+            let run = (run - 1).min(6);
+            runs.push((length, 0));
+            runs.push((16, (run - 3) as u8));
+            i += run + 1;
+        } else {
+            runs.push((length, 0));
+            i += 1;
+        }
+    }
+    runs
+}
+
+/// Huffman code lengths no longer than `limit` for symbols seen `counts` times, 0 for the unused
+/// ones. At least two symbols get a code, as a lone code of one bit is incomplete.
+fn huffman_lengths(counts: &[u32], limit: u8) -> Vec<u8> {
+    let mut counts = counts.to_vec();
+    for i in 0..2 {
+        if counts.iter().filter(|&&x| x > 0).count() < 2 && counts[i] == 0 {
+            counts[i] = 1;
+        }
+    }
+
+    loop {
+        let lengths = unlimited_huffman_lengths(&counts);
+        if lengths.iter().all(|&x| x <= limit) {
+            return lengths;
+        }
+        // flattening the counts shortens the longest codes, until they fit
+        for count in counts.iter_mut().filter(|x| **x > 0) {
+            *count = (*count >> 1).max(1);
+        }
+    }
+}
+
+fn unlimited_huffman_lengths(counts: &[u32]) -> Vec<u8> {
+    use std::{cmp::Reverse, collections::BinaryHeap};
+
+    // leaves first, then the nodes made by merging the two lightest ones
+    let mut parent: Vec<usize> = vec![usize::MAX; counts.len()];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = counts
+        .iter()
+        .enumerate()
+        .filter(|(_, &count)| count > 0)
+        .map(|(symbol, &count)| Reverse((count as u64, symbol)))
+        .collect();
+    while heap.len() > 1 {
+        let Reverse((a, first)) = heap.pop().unwrap();
+        let Reverse((b, second)) = heap.pop().unwrap();
+        let node = parent.len();
+        parent.push(usize::MAX);
+        parent[first] = node;
+        parent[second] = node;
+        heap.push(Reverse((a + b, node)));
+    }
+
+    counts
+        .iter()
+        .enumerate()
+        .map(|(symbol, &count)| {
+            if count == 0 {
+                return 0;
+            }
+            let mut depth = 0;
+            let mut node = symbol;
+            while parent[node] != usize::MAX {
+                node = parent[node];
+                depth += 1;
+            }
+            depth
+        })
+        .collect()
+}
+
+/// Greedy LZ77 parse of `data`, where `pick` is given the longest match found at each position
+/// and returns the length to use, below `MIN_MATCH` for a literal
+pub fn lz77(data: &[u8], mut pick: impl FnMut(usize) -> usize) -> Vec<Token> {
+    parse(data, MAX_CHAIN, &mut pick)
+}
+
+fn parse(data: &[u8], max_chain: usize, pick: &mut dyn FnMut(usize) -> usize) -> Vec<Token> {
+    let mut chains = HashChains::new(data, max_chain);
+    let mut tokens = Vec::with_capacity(data.len() / 2);
+    let mut i = 0;
+    while i < data.len() {
+        let (longest, distance) = chains.longest_match(i);
+        let length = if longest >= MIN_MATCH {
+            pick(longest).min(longest)
+        } else {
+            0
+        };
+        if length >= MIN_MATCH {
+            tokens.push(Token::Match {
+                length: length as u16,
+                distance: distance as u16,
+            });
+            for j in i..i + length {
+                chains.insert(j);
+            }
+            i += length;
+        } else {
+            tokens.push(Token::Literal(data[i]));
+            chains.insert(i);
+            i += 1;
+        }
+    }
+    tokens
+}
+
+/// Earlier positions starting with the same three bytes, chained from the most recent
+struct HashChains<'a> {
+    data: &'a [u8],
+    max_chain: usize,
+    head: Vec<usize>,
+    previous: Vec<usize>,
+}
+
+impl<'a> HashChains<'a> {
+    fn new(data: &'a [u8], max_chain: usize) -> Self {
+        HashChains {
+            data,
+            max_chain,
+            head: vec![usize::MAX; 1 << HASH_BITS],
+            previous: vec![usize::MAX; data.len()],
+        }
+    }
+    fn hash(&self, i: usize) -> usize {
+        let value = u32::from_le_bytes([self.data[i], self.data[i + 1], self.data[i + 2], 0]);
+        (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+    fn insert(&mut self, i: usize) {
+        if i + MIN_MATCH <= self.data.len() {
+            let hash = self.hash(i);
+            self.previous[i] = self.head[hash];
+            self.head[hash] = i;
+        }
+    }
+    /// Longest match for position `i` among the inserted ones, as (length, distance)
+    fn longest_match(&self, i: usize) -> (usize, usize) {
+        let (mut longest, mut distance) = (0, 0);
+        if i + MIN_MATCH > self.data.len() {
+            return (longest, distance);
+        }
+
+        let limit = MAX_MATCH.min(self.data.len() - i);
+        let mut candidate = self.head[self.hash(i)];
+        for _ in 0..self.max_chain {
+            if candidate == usize::MAX || i - candidate > WINDOW {
+                break;
+            }
+            let length = self.data[candidate..]
+                .iter()
+                .zip(&self.data[i..i + limit])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length > longest {
+                (longest, distance) = (length, i - candidate);
+                if length == limit {
+                    break;
+                }
+            }
+            candidate = self.previous[candidate];
+        }
+        (longest, distance)
+    }
+}
+
+/// Inflates a raw deflate stream, returns the data along with how many input bytes were used
+pub fn inflate(data: &[u8]) -> anyhow::Result<(Vec<u8>, usize)> {
+    inflate_with(data, &mut |_| {})
+}
+
+/// Like `inflate`, calling `on_match` with the length of every back reference
+fn inflate_with(data: &[u8], on_match: &mut dyn FnMut(u16)) -> anyhow::Result<(Vec<u8>, usize)> {
+    let mut bits = BitReader::new(data);
+    let mut out = Vec::with_capacity(data.len() * 4);
+
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                bits.align();
+                let len = bits.bits(16)? as u16;
+                let nlen = bits.bits(16)? as u16;
+                ensure!(len == !nlen, "invalid stored block length");
+                out.extend(bits.bytes(len as usize)?);
+            }
+            1 => {
+                let (literals, distances) = fixed_codes();
+                inflate_block(&mut bits, &mut out, &literals, &distances, on_match)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &mut out, &literals, &distances, on_match)?;
+            }
+            _ => bail!("invalid deflate block type"),
+        }
+        if last {
+            break;
+        }
+    }
+
+    bits.align();
+    Ok((out, bits.position()))
+}
+
+fn inflate_block(
+    bits: &mut BitReader,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+    on_match: &mut dyn FnMut(u16),
+) -> anyhow::Result<()> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let length = LENGTH_BASE[index] as usize + bits.bits(LENGTH_EXTRA[index])? as usize;
+                let index = distances.decode(bits)? as usize;
+                ensure!(index < 30, "invalid deflate distance code");
+                let distance =
+                    DISTANCE_BASE[index] as usize + bits.bits(DISTANCE_EXTRA[index])? as usize;
+                ensure!(distance <= out.len(), "deflate distance too far back");
+                on_match(length as u16);
+
+                let start = out.len() - distance;
+                for i in 0..length {
+                    out.push(out[start + i]);
+                }
+            }
+            _ => bail!("invalid deflate literal/length code"),
+        }
+    }
+}
+
+/// Code lengths of the fixed Huffman codes, for literals/lengths and distances
+fn fixed_lengths() -> ([u8; 288], [u8; 30]) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (lengths, [5; 30])
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let (literals, distances) = fixed_lengths();
+    // `new` only fails for over-subscribed lengths, these are the ones from the spec
+    (
+        Huffman::new(&literals).unwrap(),
+        Huffman::new(&distances).unwrap(),
+    )
+}
+
+/// Canonical code of every symbol, as (code, length), for the given code lengths
+fn canonical_codes(lengths: &[u8]) -> Vec<(u16, u8)> {
+    let mut count = [0u16; 16];
+    for &length in lengths {
+        count[length as usize] += 1;
+    }
+    count[0] = 0;
+
+    let mut next = [0u16; 16];
+    let mut code = 0u16;
+    for length in 1..16 {
+        code = (code + count[length - 1]) << 1;
+        next[length] = code;
+    }
+
+    lengths
+        .iter()
+        .map(|&length| {
+            if length == 0 {
+                return (0, 0);
+            }
+            let code = next[length as usize];
+            next[length as usize] += 1;
+            (code, length)
+        })
+        .collect()
+}
+
+fn dynamic_codes(bits: &mut BitReader) -> anyhow::Result<(Huffman, Huffman)> {
+    let literal_count = bits.bits(5)? as usize + 257;
+    let distance_count = bits.bits(5)? as usize + 1;
+    let code_count = bits.bits(4)? as usize + 4;
+    ensure!(
+        literal_count <= 286,
+        "too many deflate literal/length codes"
+    );
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_count] {
+        code_lengths[index] = bits.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(bits)? {
+            length @ 0..=15 => (length as u8, 1),
+            16 => (
+                *lengths
+                    .last()
+                    .context("deflate length repeat without a previous length")?,
+                3 + bits.bits(2)?,
+            ),
+            17 => (0, 3 + bits.bits(3)?),
+            _ => (0, 11 + bits.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    ensure!(
+        lengths.len() == literal_count + distance_count,
+        "deflate code lengths overflow"
+    );
+    ensure!(lengths[256] != 0, "deflate block without an end code");
+
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+/// Canonical Huffman decoding table indexed by the next `max_length` bits of input
+struct Huffman {
+    table: Vec<(u16, u8)>,
+    max_length: u8,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> anyhow::Result<Huffman> {
+        let max_length = lengths.iter().copied().max().unwrap_or(0);
+        let mut count = [0u16; 16];
+        for &length in lengths {
+            count[length as usize] += 1;
+        }
+        count[0] = 0;
+
+        let mut next = [0u32; 16];
+        let mut code = 0u32;
+        for length in 1..16 {
+            code = (code + count[length - 1] as u32) << 1;
+            next[length] = code;
+        }
+        ensure!(
+            next[max_length as usize] + count[max_length as usize] as u32 <= 1 << max_length,
+            "over-subscribed deflate code lengths"
+        );
+
+        // codes are read most significant bit first, so the table is indexed by reversed codes
+        let mut table = vec![(0, 0); 1 << max_length];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length == 0 {
+                continue;
+            }
+            let code = next[length as usize];
+            next[length as usize] += 1;
+            let reversed = reverse(code as u16, length) as usize;
+            for fill in (reversed..table.len()).step_by(1 << length) {
+                table[fill] = (symbol as u16, length);
+            }
+        }
+
+        Ok(Huffman { table, max_length })
+    }
+
+    fn decode(&self, bits: &mut BitReader) -> anyhow::Result<u16> {
+        let (symbol, length) = self.table[bits.peek(self.max_length) as usize];
+        ensure!(length != 0, "invalid deflate code");
+        bits.consume(length)?;
+        Ok(symbol)
+    }
+}
+
+pub(crate) fn reverse(code: u16, length: u8) -> u16 {
+    code.reverse_bits() >> (16 - length as u32)
+}
+
+/// Packs bits least significant first, the order deflate streams use
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    count: u8,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            out: Vec::new(),
+            buffer: 0,
+            count: 0,
+        }
+    }
+    fn write(&mut self, value: u32, count: u8) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+    /// Huffman codes are stored most significant bit first
+    fn write_code(&mut self, (code, length): (u16, u8)) {
+        self.write(reverse(code, length) as u32, length);
+    }
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u64,
+    count: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            position: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+    fn refill(&mut self) {
+        while self.count <= 56 && self.position < self.data.len() {
+            self.buffer |= (self.data[self.position] as u64) << self.count;
+            self.position += 1;
+            self.count += 8;
+        }
+    }
+    /// Next `count` bits without consuming them, missing bits past the end read as zeros
+    fn peek(&mut self, count: u8) -> u64 {
+        if self.count < count {
+            self.refill();
+        }
+        self.buffer & ((1 << count) - 1)
+    }
+    fn consume(&mut self, count: u8) -> anyhow::Result<()> {
+        ensure!(self.count >= count, "unexpected end of deflate stream");
+        self.buffer >>= count;
+        self.count -= count;
+        Ok(())
+    }
+    fn bits(&mut self, count: u8) -> anyhow::Result<u32> {
+        let value = self.peek(count) as u32;
+        self.consume(count)?;
+        Ok(value)
+    }
+    fn align(&mut self) {
+        let padding = self.count % 8;
+        self.buffer >>= padding;
+        self.count -= padding;
+    }
+    fn bytes(&mut self, len: usize) -> anyhow::Result<Vec<u8>> {
+        // only called once aligned, so whole bytes are either buffered or still in `data`
+        let mut out = Vec::with_capacity(len);
+        while out.len() < len && self.count >= 8 {
+            out.push(self.bits(8)? as u8);
+        }
+        let rest = len - out.len();
+        let slice = self
+            .data
+            .get(self.position..self.position + rest)
+            .context("unexpected end of deflate stream")?;
+        out.extend(slice);
+        self.position += rest;
+        Ok(out)
+    }
+    /// Input bytes consumed so far, bits already buffered are given back
+    fn position(&self) -> usize {
+        self.position - self.count as usize / 8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // zlib.compress(b"hello hello hello hello, png!") at the default level (fixed Huffman)
+    const FIXED: [u8; 22] = [
+        120, 156, 203, 72, 205, 201, 201, 87, 200, 64, 39, 117, 20, 10, 242, 210, 21, 1, 161, 202,
+        10, 99,
+    ];
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+
+    #[test]
+    fn test_decompress_fixed() {
+        let out = decompress(&FIXED).unwrap();
+        assert_eq!(out, b"hello hello hello hello, png!");
+    }
+
+    #[test]
+    fn test_stored_round_trip() {
+        let data: Vec<u8> = (0..200_000u32).map(|x| (x * 7 % 251) as u8).collect();
+        assert_eq!(decompress(&compress_stored(&data)).unwrap(), data);
+        assert_eq!(decompress(&compress_stored(&[])).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_bad_checksum() {
+        let mut bytes = FIXED;
+        bytes[21] ^= 1;
+        assert!(decompress(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_compress_round_trip() {
+        let data: Vec<u8> = b"hello hello hello hello, png! "
+            .iter()
+            .cycle()
+            .take(100_000)
+            .chain(&(0..=255).collect::<Vec<u8>>())
+            .copied()
+            .collect();
+        let compressed = compress(&data);
+        assert!(compressed.len() < data.len() / 20);
+        assert_eq!(decompress(&compressed).unwrap(), data);
+        assert_eq!(decompress(&compress(b"")).unwrap(), b"");
+        assert_eq!(decompress(&compress(b"ab")).unwrap(), b"ab");
+    }
+
+    #[test]
+    fn test_match_lengths() {
+        let data = b"abcdefabcdefabcdefabcdef-abcdef";
+        let tokens = lz77(data, |longest| longest.min(5));
+        assert_eq!(tokens.len(), 6 + 5 + 2);
+        let compressed = compress_tokens(&tokens, data);
+        assert_eq!(decompress(&compressed).unwrap(), data);
+        assert_eq!(match_lengths(&compressed).unwrap(), [5, 5, 5, 3, 5]);
+    }
+
+    #[test]
+    fn test_compression_levels() {
+        let text: Vec<u8> = b"the quick brown fox jumps over the lazy dog, "
+            .iter()
+            .cycle()
+            .take(20_000)
+            .enumerate()
+            .map(|(i, &x)| if i % 97 == 0 { b'!' } else { x })
+            .collect();
+        let mut sizes = vec![];
+        for level in 0..=9 {
+            let compressed = compress_level(&text, level);
+            assert_eq!(decompress(&compressed).unwrap(), text);
+            sizes.push(compressed.len());
+        }
+        assert!(sizes[0] > text.len());
+        assert!(sizes[9] <= sizes[1]);
+        // text with few distinct symbols is smaller with codes fitted to it
+        assert_eq!(compress(&text)[2] & 0b110, 0b100);
+    }
+
+    #[test]
+    fn test_huffman_lengths() {
+        // Fibonacci counts make the deepest unlimited tree
+        let mut counts = vec![1u32, 1];
+        while counts.len() < 30 {
+            counts.push(counts[counts.len() - 1] + counts[counts.len() - 2]);
+        }
+        let lengths = huffman_lengths(&counts, 15);
+        assert!(lengths.iter().all(|&x| x > 0 && x <= 15));
+        let kraft: f64 = lengths.iter().map(|&x| 0.5f64.powi(x as i32)).sum();
+        assert!(kraft <= 1.0);
+
+        assert_eq!(huffman_lengths(&[0, 0, 5], 7), [1, 0, 1]);
+        assert_eq!(huffman_lengths(&[3, 1, 1, 0], 7), [1, 2, 2, 0]);
+    }
+
+    #[test]
+    fn test_truncated_stream() {
+        assert!(decompress(&FIXED[..12]).is_err());
+    }
+}